@@ -0,0 +1,20 @@
+/// build.rs
+/// ========
+///
+/// Generates the game protocol (src/protocol/v622.rs) from protocol.json,
+/// see build/aubep.rs.
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[path = "build/aubep.rs"]
+mod aubep;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build/aubep.rs");
+    println!("cargo:rerun-if-changed=src/protocol/protocol.json");
+
+    let json = fs::read_to_string("src/protocol/protocol.json").unwrap();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("v622.rs");
+    fs::write(out, aubep::generate(&json)).unwrap();
+}
@@ -0,0 +1,1027 @@
+/// build/aubep.rs
+/// ==============
+///
+/// (Au)tomatic (Be)drock (P)rotocol (pronounced aw-bep), turns
+/// protocol.json into Rust at build time.
+///
+/// protocol.json describes everything with a handful of building blocks
+/// (container, array, switch, mapper, ...), so each of them gets turned
+/// into a Rust type plus the code to read and write it:
+///
+///     container -> struct          mapper   -> enum (+ Unrecognized)
+///     switch    -> enum / Option   bitflags -> newtype with consts
+///     array     -> Vec             pstring  -> String
+///
+/// Named types and packets get `FromBuffer`/`ToBuffer` impls, everything
+/// that's only used inline is read and written in place, since switches
+/// and arrays like to look at fields of whatever they're in.
+///
+/// Reference: https://github.com/ProtoDef-io/ProtoDef/blob/master/doc/datatypes.md
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+use serde_json::{Map, Value};
+
+/// What a switch can look at
+#[derive(Debug, Clone)]
+enum Kind {
+    Int,
+    Bool,
+    Mapper,
+    Bitflags(String),
+    Other,
+}
+
+/// How to handle one type
+#[derive(Debug, Clone)]
+struct Codec {
+    rust: String,
+    /// An expression that reads it from `buf`, with a `?` on anything
+    /// that can run out of bytes (it always ends up in a `from_buffer`)
+    read: String,
+    /// Statements that write `$v` (always a `&T`) to `buf`
+    write: String,
+    kind: Kind,
+}
+
+impl Codec {
+    fn new(rust: &str, read: &str, write: &str, kind: Kind) -> Self {
+        Self {
+            rust: rust.to_string(),
+            read: read.to_string(),
+            write: write.to_string(),
+            kind,
+        }
+    }
+
+    fn void() -> Self {
+        Self::new("()", "()", "", Kind::Other)
+    }
+
+    fn is_void(&self) -> bool {
+        self.rust == "()"
+    }
+
+    fn write(&self, value: &str) -> String {
+        self.write.replace("$v", &format!("({value})"))
+    }
+}
+
+struct Field {
+    name: String,
+    local: String,
+    codec: Codec,
+}
+
+pub struct Generator {
+    types: Map<String, Value>,
+    out: String,
+    /// Rust name of every protocol.json type
+    type_names: HashMap<String, String>,
+    used_names: HashSet<String>,
+    codecs: HashMap<String, Codec>,
+    /// Fields that have been read so far, innermost container last
+    scopes: Vec<HashMap<String, (String, Kind)>>,
+    counter: usize,
+}
+
+pub fn generate(protocol_json: &str) -> String {
+    let root: Value = serde_json::from_str(protocol_json).expect("protocol.json isn't JSON");
+    let types = root["types"].as_object().expect("no types").clone();
+
+    let mut gen = Generator {
+        types,
+        out: String::new(),
+        type_names: HashMap::new(),
+        // anything the generated code imports
+        used_names: ["MsgBuffer", "FromBuffer", "ToBuffer", "GamePacket"]
+            .map(String::from)
+            .into(),
+        codecs: HashMap::new(),
+        scopes: vec![],
+        counter: 0,
+    };
+    gen.run();
+    gen.out
+}
+
+impl Generator {
+    fn run(&mut self) {
+        let packet_ids = self.packet_ids();
+
+        // claim the names of everything upfront, so that inline types
+        // don't steal them
+        let names: Vec<String> = self.types.keys().cloned().collect();
+        for name in names.iter().filter(|n| !n.starts_with("packet_")) {
+            let rust = self.unique_name(&camel_case(name));
+            self.type_names.insert(name.clone(), rust);
+        }
+        for name in names.iter().filter(|n| n.starts_with("packet_")) {
+            let mut rust = camel_case(&name["packet_".len()..]);
+            if self.used_names.contains(&rust) {
+                rust += "Packet";
+            }
+            let rust = self.unique_name(&rust);
+            self.type_names.insert(name.clone(), rust);
+        }
+
+        for name in &names {
+            if name == "mcpe_packet" || self.types[name] == "native" {
+                continue;
+            }
+            self.named(name);
+        }
+
+        for (id, name) in &packet_ids {
+            if let Some(rust) = self.type_names.get(&format!("packet_{name}")) {
+                writeln!(
+                    self.out,
+                    "impl GamePacket for {rust} {{\n    const ID: u32 = {id:#04x};\n}}\n"
+                )
+                .unwrap();
+            }
+        }
+
+        self.out += "pub fn packet_name(id: u32) -> Option<&'static str> {\n    match id {\n";
+        for (id, name) in &packet_ids {
+            writeln!(self.out, "        {id:#04x} => Some({name:?}),").unwrap();
+        }
+        self.out += "        _ => None,\n    }\n}\n";
+    }
+
+    /// From the mapper in mcpe_packet
+    fn packet_ids(&self) -> BTreeMap<u32, String> {
+        let mappings = self.types["mcpe_packet"][1][0]["type"][1]["mappings"]
+            .as_object()
+            .expect("mcpe_packet has no id mapper");
+
+        mappings
+            .iter()
+            .map(|(id, name)| (id.parse().unwrap(), name.as_str().unwrap().to_string()))
+            .collect()
+    }
+
+    fn unique_name(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut i = 2;
+        while self.used_names.contains(&candidate) {
+            candidate = format!("{name}{i}");
+            i += 1;
+        }
+        self.used_names.insert(candidate.clone());
+        candidate
+    }
+
+    fn local(&mut self, name: &str) -> String {
+        self.counter += 1;
+        format!(
+            "l{}_{}",
+            self.counter,
+            snake_case(name).trim_start_matches('_')
+        )
+    }
+
+    /// A type referred to by name, generated the first time it's needed
+    fn named(&mut self, name: &str) -> Codec {
+        if let Some(codec) = self.codecs.get(name) {
+            return codec.clone();
+        }
+
+        let definition = match self.types.get(name) {
+            None => return primitive(name),
+            Some(Value::String(s)) if s == "native" => return native(name),
+            Some(definition) => definition.clone(),
+        };
+        let rust = self.type_names[name].clone();
+
+        let kind = definition[0].as_str().unwrap();
+        let codec = match kind {
+            "container" | "mapper" | "bitflags" | "bitfield" => {
+                let kind = match kind {
+                    "mapper" => Kind::Mapper,
+                    "bitflags" => Kind::Bitflags(rust.clone()),
+                    _ => Kind::Other,
+                };
+                let codec = Codec::new(
+                    &rust,
+                    &format!("{rust}::from_buffer(buf)?"),
+                    "buf.write($v.to_buffer().get_bytes());",
+                    kind,
+                );
+                // before generating, in case it refers to itself
+                self.codecs.insert(name.to_string(), codec.clone());
+
+                // named types can't see anything outside of themselves
+                let scopes = std::mem::take(&mut self.scopes);
+                let inner = self.build(&definition, &rust, true);
+                self.scopes = scopes;
+
+                writeln!(
+                    self.out,
+                    "impl FromBuffer for {rust} {{\n    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {{\n        Ok({})\n    }}\n}}\n",
+                    inner.read
+                )
+                .unwrap();
+                writeln!(
+                    self.out,
+                    "impl ToBuffer for {rust} {{\n    fn to_buffer(&self) -> MsgBuffer {{\n        let mut out = MsgBuffer::new();\n        let buf = &mut out;\n        {}\n        out\n    }}\n}}\n",
+                    inner.write("self")
+                )
+                .unwrap();
+                codec
+            }
+            _ => {
+                // arrays, strings and buffers just get an alias (except
+                // for string, String = String doesn't help anyone)
+                let mut codec = self.build(&definition, &rust, false);
+                if codec.rust != rust {
+                    writeln!(self.out, "pub type {rust} = {};\n", codec.rust).unwrap();
+                    codec.rust = rust;
+                }
+                self.codecs.insert(name.to_string(), codec.clone());
+                codec
+            }
+        };
+
+        codec
+    }
+
+    /// Any type, `hint` is what to call it if it needs a name of its own
+    fn ty(&mut self, node: &Value, hint: &str) -> Codec {
+        match node {
+            Value::String(name) => self.named(name),
+            Value::Array(parts) if parts.len() == 1 => self.named(parts[0].as_str().unwrap()),
+            Value::Array(parts) => match parts[0].as_str().unwrap() {
+                // these end up as types of their own
+                "container" | "mapper" | "bitflags" | "bitfield" => {
+                    let name = self.unique_name(hint);
+                    self.build(node, &name, false)
+                }
+                _ => self.build(node, hint, false),
+            },
+            _ => panic!("Unexpected type {node}"),
+        }
+    }
+
+    /// `name` has already been claimed if this is going to be a type of its
+    /// own (switches claim it themselves, if they need it). `named` means
+    /// it gets `FromBuffer`/`ToBuffer` and is read with `Self`.
+    fn build(&mut self, node: &Value, name: &str, named: bool) -> Codec {
+        let args = &node[1];
+        match node[0].as_str().unwrap() {
+            "container" => self.container(args.as_array().unwrap(), name, named),
+            "mapper" => self.mapper(args, name),
+            "bitflags" => self.bitflags(args, name),
+            "bitfield" => self.bitfield(args.as_array().unwrap(), name),
+            "switch" => self.switch(args, name),
+            "array" => self.array(args, name),
+            "option" => {
+                let inner = self.ty(args, name);
+                Codec::new(
+                    &format!("Option<{}>", inner.rust),
+                    &format!(
+                        "if buf.read_byte()? != 0 {{ Some({}) }} else {{ None }}",
+                        inner.read
+                    ),
+                    &format!(
+                        "match $v {{ Some(item) => {{ buf.write_byte(1); {} }} None => buf.write_byte(0), }}",
+                        inner.write("item")
+                    ),
+                    Kind::Other,
+                )
+            }
+            "pstring" if args["countType"] == "varint" => Codec::new(
+                "String",
+                "buf.read_varint_string()?",
+                "buf.write_varint_string($v);",
+                Kind::Other,
+            ),
+            "pstring" if args["countType"] == "li32" => Codec::new(
+                "String",
+                "buf.read_le_string()?",
+                "buf.write_le_string($v);",
+                Kind::Other,
+            ),
+            "pstring" => {
+                let count = self.count_type(&args["countType"]);
+                Codec::new(
+                    "String",
+                    &format!(
+                        "{{ let len = ({}) as usize; String::from_utf8_lossy(&buf.read_vec(len)?).to_string() }}",
+                        count.read
+                    ),
+                    &format!(
+                        "{} buf.write($v.as_bytes());",
+                        count.write(&format!("&($v.len() as {})", count.rust))
+                    ),
+                    Kind::Other,
+                )
+            }
+            "buffer" => {
+                if args.get("countType").is_some() {
+                    let count = self.count_type(&args["countType"]);
+                    Codec::new(
+                        "Vec<u8>",
+                        &format!(
+                            "{{ let len = ({}) as usize; buf.read_vec(len)? }}",
+                            count.read
+                        ),
+                        &format!(
+                            "{} buf.write($v);",
+                            count.write(&format!("&($v.len() as {})", count.rust))
+                        ),
+                        Kind::Other,
+                    )
+                } else {
+                    let count = self.count(&args["count"]);
+                    Codec::new(
+                        "Vec<u8>",
+                        &format!("{{ let len = ({count}) as usize; buf.read_vec(len)? }}"),
+                        "buf.write($v);",
+                        Kind::Other,
+                    )
+                }
+            }
+            "encapsulated" => {
+                let length = self.count_type(&args["lengthType"]);
+                let inner = self.ty(&args["type"], name);
+                Codec::new(
+                    &inner.rust,
+                    &format!(
+                        "{{ let len = ({}) as usize; let mut inner = MsgBuffer::from(buf.read_vec(len)?); let buf = &mut inner; {} }}",
+                        length.read, inner.read
+                    ),
+                    &format!(
+                        "{{ let mut inner = MsgBuffer::new(); {{ let buf = &mut inner; {} }} {} buf.write(inner.get_bytes()); }}",
+                        inner.write("$v"),
+                        length.write(&format!("&(inner.get_bytes().len() as {})", length.rust))
+                    ),
+                    inner.kind,
+                )
+            }
+            other => panic!("Don't know how to generate {other}"),
+        }
+    }
+
+    fn count_type(&mut self, node: &Value) -> Codec {
+        self.named(node.as_str().expect("countType isn't a type name"))
+    }
+
+    /// A fixed count, or one read earlier from another field
+    fn count(&mut self, node: &Value) -> String {
+        match node {
+            Value::Number(n) => n.to_string(),
+            Value::String(field) => self.lookup(field).0,
+            _ => panic!("Unexpected count {node}"),
+        }
+    }
+
+    fn lookup(&self, path: &str) -> (String, Kind) {
+        let mut scopes = self.scopes.iter().rev();
+        let mut field = path;
+        while let Some(rest) = field.strip_prefix("../") {
+            scopes.next();
+            field = rest;
+        }
+
+        // anonymous containers get merged into whatever they're in, so
+        // look outwards if it's not right here
+        for scope in scopes {
+            if let Some(found) = scope.get(field) {
+                return found.clone();
+            }
+        }
+        panic!("Can't find field {path}")
+    }
+
+    fn container(&mut self, fields: &[Value], name: &str, named: bool) -> Codec {
+        self.scopes.push(HashMap::new());
+        let mut read = String::new();
+        let mut parsed = vec![];
+        self.container_fields(fields, name, &mut read, &mut parsed);
+        self.scopes.pop();
+
+        let mut definition = format!("#[derive(Debug, Clone, PartialEq)]\npub struct {name} {{\n");
+        let mut literal = String::new();
+        let mut write = String::new();
+        for field in &parsed {
+            writeln!(definition, "    pub {}: {},", field.name, field.codec.rust).unwrap();
+            write!(literal, "{}: {}, ", field.name, field.local).unwrap();
+            write += &field.codec.write(&format!("&$v.{}", field.name));
+            write += "\n        ";
+        }
+        definition += "}\n";
+        self.out += &definition;
+        self.out += "\n";
+
+        let constructor = if named { "Self" } else { name };
+        Codec::new(
+            name,
+            &format!("{{\n        {read}{constructor} {{ {literal}}}\n        }}"),
+            &write,
+            Kind::Other,
+        )
+    }
+
+    fn container_fields(
+        &mut self,
+        fields: &[Value],
+        name: &str,
+        read: &mut String,
+        parsed: &mut Vec<Field>,
+    ) {
+        for field in fields {
+            let node = &field["type"];
+
+            let field_name = if field["anon"].as_bool().unwrap_or(false) {
+                if node[0] == "container" {
+                    // merged into this one
+                    self.container_fields(node[1].as_array().unwrap(), name, read, parsed);
+                    continue;
+                }
+                // anonymous switches get named after what they look at
+                let compare = node[1]["compareTo"].as_str().unwrap_or("content");
+                format!(
+                    "{}_data",
+                    compare.trim_start_matches("../").replace('.', "_")
+                )
+            } else {
+                field["name"].as_str().unwrap().to_string()
+            };
+
+            let codec = if node[0] == "enum_size_based_on_values_len" {
+                // not sent, worked out from the length of the enum values
+                let (values_len, _) = self.lookup("values_len");
+                Codec::new(
+                    "native::EnumSize",
+                    &format!("native::EnumSize::for_len({values_len} as usize)"),
+                    "",
+                    Kind::Mapper,
+                )
+            } else {
+                self.ty(node, &format!("{name}{}", camel_case(&field_name)))
+            };
+            if codec.is_void() {
+                continue;
+            }
+
+            let local = self.local(&field_name);
+            writeln!(read, "let {local} = {};", codec.read).unwrap();
+            read.push_str("        ");
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .insert(field_name.clone(), (local.clone(), codec.kind.clone()));
+
+            let mut rust_name = field_ident(&field_name);
+            while parsed.iter().any(|f| f.name == rust_name) {
+                rust_name += "_";
+            }
+            parsed.push(Field {
+                name: rust_name,
+                local,
+                codec,
+            });
+        }
+    }
+
+    fn array(&mut self, args: &Value, name: &str) -> Codec {
+        // named arrays have already taken the name
+        let hint = if self.used_names.contains(name) {
+            format!("{name}Entry")
+        } else {
+            name.to_string()
+        };
+        let item = self.ty(&args["type"], &hint);
+        let rust = format!("Vec<{}>", item.rust);
+        // every item's at least a byte, so a count bigger than what's
+        // left can only be someone lying to make us spin
+        let push = format!(
+            "if count > buf.len_rest() {{ return Err(DecodeError::BadLength(count as i64)); }} let mut list = Vec::with_capacity(count.min(1024)); for _ in 0..count {{ list.push({}); }} list",
+            item.read
+        );
+        let write_items = format!("for item in $v.iter() {{ {} }}", item.write("item"));
+
+        if args.get("countType").is_some() {
+            let count = self.count_type(&args["countType"]);
+            Codec::new(
+                &rust,
+                &format!("{{ let count = ({}) as usize; {push} }}", count.read),
+                &format!(
+                    "{} {write_items}",
+                    count.write(&format!("&($v.len() as {})", count.rust))
+                ),
+                Kind::Other,
+            )
+        } else {
+            // the count is another field, which has to be kept in sync
+            let count = self.count(&args["count"]);
+            Codec::new(
+                &rust,
+                &format!("{{ let count = ({count}) as usize; {push} }}"),
+                &write_items,
+                Kind::Other,
+            )
+        }
+    }
+
+    fn mapper(&mut self, args: &Value, name: &str) -> Codec {
+        let inner = self.named(args["type"].as_str().unwrap());
+        let mut mappings: Vec<(i64, String)> = args["mappings"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(value, key)| (value.parse().unwrap(), key.as_str().unwrap().to_string()))
+            .collect();
+        mappings.sort();
+
+        let mut used = HashSet::from(["Unrecognized".to_string()]);
+        let mut variants = vec![];
+        for (value, key) in mappings {
+            let mut variant = variant_name(&key);
+            while used.contains(&variant) {
+                variant += "_";
+            }
+            used.insert(variant.clone());
+            variants.push((value, key, variant));
+        }
+
+        let mut out = format!("#[derive(Debug, Clone, Copy, PartialEq, Eq)]\npub enum {name} {{\n");
+        for (_, _, variant) in &variants {
+            writeln!(out, "    {variant},").unwrap();
+        }
+        out += "    Unrecognized(i64),\n}\n\n";
+
+        writeln!(out, "impl {name} {{").unwrap();
+        out += "    pub fn from_value(value: i64) -> Self {\n        match value {\n";
+        for (value, _, variant) in &variants {
+            writeln!(out, "            {value} => Self::{variant},").unwrap();
+        }
+        out += "            other => Self::Unrecognized(other),\n        }\n    }\n\n";
+        out += "    pub fn value(&self) -> i64 {\n        match self {\n";
+        for (value, _, variant) in &variants {
+            writeln!(out, "            Self::{variant} => {value},").unwrap();
+        }
+        out += "            Self::Unrecognized(value) => *value,\n        }\n    }\n\n";
+        out += "    pub fn name(&self) -> &'static str {\n        match self {\n";
+        for (_, key, variant) in &variants {
+            writeln!(out, "            Self::{variant} => {key:?},").unwrap();
+        }
+        out += "            Self::Unrecognized(_) => \"\",\n        }\n    }\n}\n\n";
+        self.out += &out;
+
+        Codec::new(
+            name,
+            &format!("{name}::from_value(({}) as i64)", inner.read),
+            &inner.write(&format!("&($v.value() as {})", inner.rust)),
+            Kind::Mapper,
+        )
+    }
+
+    fn bitflags(&mut self, args: &Value, name: &str) -> Codec {
+        let inner = self.named(args["type"].as_str().unwrap());
+        let flags: Vec<(String, u64)> = match &args["flags"] {
+            // MetadataFlags1 lists one more flag than fits, that one's
+            // actually in MetadataFlags2
+            Value::Array(flags) => flags
+                .iter()
+                .take(64)
+                .enumerate()
+                .map(|(i, flag)| (flag.as_str().unwrap().to_string(), 1u64 << i))
+                .collect(),
+            Value::Object(flags) => flags
+                .iter()
+                .map(|(flag, value)| (flag.clone(), value.as_u64().unwrap()))
+                .collect(),
+            other => panic!("Unexpected flags {other}"),
+        };
+
+        let mut out =
+            format!("#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]\npub struct {name}(pub u64);\n\nimpl {name} {{\n");
+        let mut used = HashSet::new();
+        for (flag, value) in &flags {
+            let mut constant = flag_name(flag);
+            while used.contains(&constant) {
+                constant += "_";
+            }
+            used.insert(constant.clone());
+            writeln!(out, "    pub const {constant}: u64 = {value:#x};").unwrap();
+        }
+        out += "\n    pub fn has(&self, flag: u64) -> bool {\n        self.0 & flag == flag\n    }\n}\n\n";
+        self.out += &out;
+
+        Codec::new(
+            name,
+            &format!("{name}(({}) as u64)", inner.read),
+            &inner.write(&format!("&($v.0 as {})", inner.rust)),
+            Kind::Bitflags(name.to_string()),
+        )
+    }
+
+    /// Packed bits, most significant first
+    fn bitfield(&mut self, fields: &[Value], name: &str) -> Codec {
+        let total: u64 = fields.iter().map(|f| f["size"].as_u64().unwrap()).sum();
+        assert!(
+            total.is_multiple_of(8) && total <= 64,
+            "{name} isn't a whole number of bytes"
+        );
+        let bytes = total / 8;
+
+        let mut definition = format!(
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]\npub struct {name} {{\n"
+        );
+        let mut literal = String::new();
+        let mut write = "let mut bits: u64 = 0;".to_string();
+        let mut offset = 0;
+        for field in fields {
+            let size = field["size"].as_u64().unwrap();
+            let rust = match size {
+                0..=8 => "u8",
+                9..=16 => "u16",
+                17..=32 => "u32",
+                _ => "u64",
+            };
+            let field_name = field_ident(field["name"].as_str().unwrap());
+            let shift = total - offset - size;
+            let mask = if size == 64 {
+                u64::MAX
+            } else {
+                (1u64 << size) - 1
+            };
+            offset += size;
+
+            writeln!(definition, "    pub {field_name}: {rust},").unwrap();
+            write!(
+                literal,
+                "{field_name}: ((bits >> {shift}) & {mask:#x}) as {rust}, "
+            )
+            .unwrap();
+            write!(
+                write,
+                " bits |= ($v.{field_name} as u64 & {mask:#x}) << {shift};"
+            )
+            .unwrap();
+        }
+        definition += "}\n\n";
+        self.out += &definition;
+
+        write!(
+            write,
+            " for i in (0..{bytes}).rev() {{ buf.write_byte((bits >> (i * 8)) as u8); }}"
+        )
+        .unwrap();
+
+        Codec::new(
+            name,
+            &format!(
+                "{{ let mut bits: u64 = 0; for _ in 0..{bytes} {{ bits = (bits << 8) | buf.read_byte()? as u64; }} {name} {{ {literal}}} }}"
+            ),
+            &format!("{{ {write} }}"),
+            Kind::Other,
+        )
+    }
+
+    /// Turns a switch case into a condition on whatever's being compared
+    fn condition(&self, compare: &str, key: &str) -> String {
+        if compare.contains("||") {
+            let any: Vec<String> = compare
+                .split("||")
+                .map(|part| self.condition(part.trim(), "true"))
+                .collect();
+            return bool_condition(&format!("({})", any.join(" || ")), key);
+        }
+
+        if let Some((field, flag)) = compare
+            .rsplit_once('.')
+            .filter(|(_, flag)| !flag.starts_with('/'))
+        {
+            let (local, kind) = self.lookup(field);
+            let Kind::Bitflags(flags) = kind else {
+                panic!("{field} isn't bitflags");
+            };
+            return bool_condition(&format!("{local}.has({flags}::{})", flag_name(flag)), key);
+        }
+
+        let (local, kind) = self.lookup(compare);
+        let global = |key: &str| match key {
+            "/ShieldItemID" => "native::shield_item_id() as i64".to_string(),
+            other => panic!("Unknown global {other}"),
+        };
+
+        match kind {
+            Kind::Bool => bool_condition(&local, key),
+            Kind::Int => {
+                if key.starts_with('/') {
+                    format!("{local} as i64 == {}", global(key))
+                } else {
+                    let value: i64 = match key {
+                        "true" => 1,
+                        "false" => 0,
+                        n => n
+                            .parse()
+                            .expect("switch on a number with a non number case"),
+                    };
+                    format!("{local} as i64 == {value}")
+                }
+            }
+            Kind::Mapper => {
+                if key.starts_with('/') {
+                    format!("{local}.value() == {}", global(key))
+                } else if let Ok(value) = key.parse::<i64>() {
+                    format!("{local}.value() == {value}")
+                } else {
+                    format!("{local}.name() == {key:?}")
+                }
+            }
+            Kind::Bitflags(_) | Kind::Other => panic!("Can't switch on {compare}"),
+        }
+    }
+
+    fn switch(&mut self, args: &Value, name: &str) -> Codec {
+        let compare = args["compareTo"].as_str().unwrap();
+        let fields = args["fields"].as_object().unwrap();
+        let void = Value::from("void");
+        let default_node = args.get("default").unwrap_or(&void);
+
+        // one case with something in it and nothing in the rest is just
+        // an Option, which doesn't need a name
+        let non_void = fields
+            .values()
+            .chain([default_node])
+            .filter(|node| **node != "void")
+            .count();
+        if non_void == 0 {
+            return Codec::void();
+        }
+        let name = if non_void == 1 {
+            name.to_string()
+        } else {
+            self.unique_name(name)
+        };
+        let name = name.as_str();
+        let hint = |variant: &str| {
+            if non_void == 1 {
+                name.to_string()
+            } else {
+                format!("{name}{variant}")
+            }
+        };
+
+        let mut cases = vec![];
+        for (key, node) in fields {
+            let codec = self.ty(node, &hint(&variant_name(key)));
+            cases.push((key.clone(), codec));
+        }
+        let default = self.ty(default_node, &hint("Default"));
+
+        if non_void == 1 {
+            let (read, inner) = if default.is_void() {
+                let (key, inner) = cases.iter().find(|(_, c)| !c.is_void()).unwrap();
+                (
+                    format!(
+                        "if {} {{ Some({}) }} else {{ None }}",
+                        self.condition(compare, key),
+                        inner.read
+                    ),
+                    inner.clone(),
+                )
+            } else {
+                let any: Vec<String> = cases
+                    .iter()
+                    .map(|(key, _)| self.condition(compare, key))
+                    .collect();
+                (
+                    format!(
+                        "if {} {{ None }} else {{ Some({}) }}",
+                        any.join(" || "),
+                        default.read
+                    ),
+                    default.clone(),
+                )
+            };
+            return Codec::new(
+                &format!("Option<{}>", inner.rust),
+                &read,
+                &format!("if let Some(item) = $v {{ {} }}", inner.write("item")),
+                Kind::Other,
+            );
+        }
+
+        let mut used = HashSet::new();
+        let mut variants = vec![];
+        for (key, codec) in cases
+            .into_iter()
+            .map(|(k, c)| (Some(k), c))
+            .chain([(None, default)])
+        {
+            let mut variant = match &key {
+                Some(key) => variant_name(key),
+                None => "Default".to_string(),
+            };
+            while used.contains(&variant) {
+                variant += "_";
+            }
+            used.insert(variant.clone());
+            variants.push((key, variant, codec));
+        }
+
+        let mut definition = format!("#[derive(Debug, Clone, PartialEq)]\npub enum {name} {{\n");
+        let mut read = String::new();
+        let mut write = "match $v { ".to_string();
+        for (key, variant, codec) in &variants {
+            let constructor = if codec.is_void() {
+                writeln!(definition, "    {variant},").unwrap();
+                write!(write, "{name}::{variant} => {{}} ").unwrap();
+                format!("{name}::{variant}")
+            } else {
+                writeln!(definition, "    {variant}({}),", codec.rust).unwrap();
+                write!(
+                    write,
+                    "{name}::{variant}(item) => {{ {} }} ",
+                    codec.write("item")
+                )
+                .unwrap();
+                format!("{name}::{variant}({})", codec.read)
+            };
+
+            match key {
+                Some(key) => write!(
+                    read,
+                    "if {} {{ {constructor} }} else ",
+                    self.condition(compare, key)
+                )
+                .unwrap(),
+                None => write!(read, "{{ {constructor} }}").unwrap(),
+            }
+        }
+        definition += "}\n\n";
+        write += "}";
+        self.out += &definition;
+
+        Codec::new(name, &read, &write, Kind::Other)
+    }
+}
+
+fn bool_condition(value: &str, key: &str) -> String {
+    match key {
+        "true" | "1" => value.to_string(),
+        "false" | "0" => format!("!{value}"),
+        other => panic!("Can't compare a bool to {other}"),
+    }
+}
+
+/// Numbers, booleans and the like
+fn primitive(name: &str) -> Codec {
+    let (endian, number) = match name.strip_prefix('l') {
+        Some(rest) if rest.starts_with(['i', 'u', 'f']) => ("le", rest),
+        _ => ("be", name),
+    };
+
+    match number {
+        "void" => Codec::void(),
+        "bool" => Codec::new(
+            "bool",
+            "buf.read_byte()? != 0",
+            "buf.write_byte(*$v as u8);",
+            Kind::Bool,
+        ),
+        "u8" => Codec::new("u8", "buf.read_byte()?", "buf.write_byte(*$v);", Kind::Int),
+        "varint" => Codec::new(
+            "i32",
+            "buf.read_i32_varint_bytes()?",
+            "buf.write_i32_varint_bytes(*$v);",
+            Kind::Int,
+        ),
+        "i8" | "i16" | "u16" | "i32" | "u32" | "i64" | "u64" | "f32" | "f64" => {
+            let rust = number;
+            Codec::new(
+                rust,
+                &format!("{rust}::from_{endian}_bytes(buf.read_array()?)"),
+                &format!("buf.write(&$v.to_{endian}_bytes());"),
+                if number.starts_with('f') {
+                    Kind::Other
+                } else {
+                    Kind::Int
+                },
+            )
+        }
+        other => panic!("Unknown type {other}"),
+    }
+}
+
+/// What protocol.json calls "native". Most of these are on MsgBuffer,
+/// NBT and friends live in protocol/native.rs
+fn native(name: &str) -> Codec {
+    let (rust, read, write) = match name {
+        "varint64" => ("u64", "read_u64_varint_bytes", "write_u64_varint_bytes"),
+        "zigzag32" => ("i32", "read_zigzag32", "write_zigzag32"),
+        "zigzag64" => ("i64", "read_zigzag64", "write_zigzag64"),
+        "uuid" => ("u128", "read_uuid", "write_uuid"),
+        "byterot" => ("f32", "read_byterot", "write_byterot"),
+        "restBuffer" => {
+            return Codec::new("Vec<u8>", "buf.read_rest()", "buf.write($v);", Kind::Other)
+        }
+        "nbt" | "lnbt" => {
+            return Codec::new(
+                "native::Nbt",
+                &format!("native::read_{name}(buf)?"),
+                &format!("native::write_{name}(buf, $v);"),
+                Kind::Other,
+            )
+        }
+        "nbtLoop" => {
+            return Codec::new(
+                "Vec<native::Nbt>",
+                "native::read_nbt_loop(buf)?",
+                "native::write_nbt_loop(buf, $v);",
+                Kind::Other,
+            )
+        }
+        other => panic!("Don't know how to handle native type {other}"),
+    };
+
+    let kind = if rust.starts_with('f') || rust == "u128" {
+        Kind::Other
+    } else {
+        Kind::Int
+    };
+    Codec::new(
+        rust,
+        &format!("buf.{read}()?"),
+        &format!("buf.{write}(*$v);"),
+        kind,
+    )
+}
+
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            first.to_string() + chars.as_str()
+        })
+        .collect()
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+            prev_lower = false;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            out.push(c);
+            prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            out.push('_');
+            prev_lower = false;
+        }
+    }
+    out
+}
+
+fn field_ident(name: &str) -> String {
+    let name = snake_case(name);
+    let name = if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    };
+    match name.as_str() {
+        "self" | "super" | "crate" => format!("{name}_"),
+        "type" | "match" | "mod" | "loop" | "ref" | "move" | "use" | "box" | "final"
+        | "override" | "where" | "in" | "as" | "fn" | "impl" | "static" | "const" | "struct"
+        | "enum" | "trait" | "true" | "false" | "if" | "else" | "for" | "while" | "return"
+        | "break" | "continue" | "let" | "mut" | "pub" | "unsafe" | "dyn" | "async" | "await"
+        | "extern" | "macro" | "priv" | "typeof" | "unsized" | "virtual" | "yield" | "try"
+        | "abstract" | "become" | "do" => format!("r#{name}"),
+        _ => name,
+    }
+}
+
+fn variant_name(key: &str) -> String {
+    let name = camel_case(key);
+    if key.starts_with('-') {
+        format!("VMinus{name}")
+    } else if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("V{name}")
+    } else if name == "Self" {
+        "Self_".to_string()
+    } else {
+        name
+    }
+}
+
+fn flag_name(flag: &str) -> String {
+    let name = snake_case(flag).to_ascii_uppercase();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
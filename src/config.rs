@@ -1,9 +1,28 @@
 // barebones server.properties reader
 
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 
+/// A property that's there but doesn't make sense
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    pub name: String,
+    pub value: String,
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected {} for {}, got {}",
+            self.expected, self.name, self.value
+        )
+    }
+}
+
 pub struct Config {
     pub config: HashMap<String, String>,
 }
@@ -31,9 +50,50 @@ impl Config {
     }
 
     pub fn get_property(&self, name: &str) -> &String {
-        match self.config.get(&name.to_string()) {
+        match self.config.get(name) {
             Some(value) => value,
             None => panic!("Item not found in config: {}", name),
         }
     }
+
+    /// For properties added after people already had a server.properties
+    pub fn get_optional(&self, name: &str) -> Option<&String> {
+        self.config.get(name)
+    }
+
+    /// `parse` says whether it makes sense, `expected` what would have
+    pub fn get_parsed<T>(
+        &self,
+        name: &str,
+        expected: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, ConfigError> {
+        let value = self.get_property(name);
+        parse(value).ok_or_else(|| ConfigError {
+            name: name.to_string(),
+            value: value.clone(),
+            expected,
+        })
+    }
+
+    /// `get_parsed` for the ones `get_optional` is for
+    pub fn get_optional_parsed<T>(
+        &self,
+        name: &str,
+        expected: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, ConfigError> {
+        match self.get_optional(name) {
+            Some(_) => self.get_parsed(name, expected, parse).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        match self.get_property(name).as_str() {
+            "true" => true,
+            "false" => false,
+            other => panic!("Expected true or false for {}, got {}", name, other),
+        }
+    }
 }
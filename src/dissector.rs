@@ -0,0 +1,409 @@
+/// dissector.rs
+/// ============
+///
+/// Pretty-prints raw datagrams as a tree so trace logs are actually
+/// readable. Goes all the way down: frameset -> frames -> game packets.
+///
+/// Also usable from the command line, see `run_cli`:
+///     echo "84 00 00 00 60 ..." | voxel dissect
+use std::io::Read;
+
+use crate::protocol::batch::{BatchCodec, CompressionAlgorithm, CompressionSettings};
+use crate::protocol::v622::packet_name;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+use crate::raknet::packets::*;
+
+/// Log target for dissected datagrams, only on with `log-packets`
+pub const LOG_TARGET: &str = "voxel::packets";
+
+pub struct Node {
+    pub label: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(label: String) -> Self {
+        Self {
+            label,
+            children: vec![],
+        }
+    }
+
+    pub fn push(&mut self, label: String) -> &mut Node {
+        self.children.push(Node::new(label));
+        self.children.last_mut().unwrap()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = self.label.clone();
+        render_children(&self.children, "", &mut out);
+        out
+    }
+}
+
+fn render_children(children: &[Node], prefix: &str, out: &mut String) {
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        out.push('\n');
+        out.push_str(prefix);
+        out.push_str(if last { "└─ " } else { "├─ " });
+        out.push_str(&child.label);
+
+        let next_prefix = format!("{prefix}{}", if last { "   " } else { "│  " });
+        render_children(&child.children, &next_prefix, out);
+    }
+}
+
+pub fn raknet_packet_name(packet_id: u8) -> &'static str {
+    match packet_id {
+        0x00 => "ConnectedPing",
+        0x01 => "UnconnectedPing",
+        0x02 => "UnconnectedPingOpenConnections",
+        0x03 => "ConnectedPong",
+        0x05 => "OpenConnectionRequest1",
+        0x06 => "OpenConnectionReply1",
+        0x07 => "OpenConnectionRequest2",
+        0x08 => "OpenConnectionReply2",
+        0x09 => "ConnectionRequest",
+        0x10 => "ConnectionRequestAccepted",
+        0x13 => "NewIncomingConnection",
+        0x15 => "Disconnect",
+        0x19 => "IncompatibleProtocolVersion",
+        0x1c => "UnconnectedPong",
+        0x80..=0x8d => "FrameSet",
+        0xa0 => "Nack",
+        0xc0 => "Ack",
+        0xfe => "GamePacket",
+        _ => "Unknown",
+    }
+}
+
+/// Dissects a whole datagram, the first byte being the packet id
+/// `decompress` is for `voxel dissect`, live logging leaves compressed
+/// batches alone so the network thread isn't inflating every datagram twice
+pub fn dissect(datagram: &[u8], decompress: bool) -> String {
+    if datagram.is_empty() {
+        return "<empty datagram>".to_string();
+    }
+
+    let packet_id = datagram[0];
+    let mut root = Node::new(format!(
+        "{} ({packet_id:#04x}) {} bytes",
+        raknet_packet_name(packet_id),
+        datagram.len()
+    ));
+
+    // whatever made sense before it stops making sense is still shown
+    let body = MsgBuffer::from(datagram[1..].to_vec());
+    if let Err(e) = dissect_datagram(packet_id, body, decompress, &mut root) {
+        root.push(format!("<malformed: {e}>"));
+    }
+
+    root.render()
+}
+
+fn dissect_datagram(
+    packet_id: u8,
+    mut body: MsgBuffer,
+    decompress: bool,
+    node: &mut Node,
+) -> Result<(), DecodeError> {
+    match packet_id {
+        0x01 | 0x02 => {
+            let ping = OfflinePing::from_buffer(&mut body)?;
+            node.push(format!("timestamp: {}", ping.timestamp));
+            node.push(format!("client_guid: {}", ping.client_guid));
+        }
+        0x1c => {
+            node.push(format!("timestamp: {}", body.read_i64_be_bytes()?));
+            node.push(format!("server_guid: {}", body.read_i64_be_bytes()?));
+            body.read_magic()?;
+            node.push(format!("server_name: {:?}", body.read_string()?));
+        }
+        0x05 => {
+            let request1 = OfflineConnReq1::from_buffer(&mut body)?;
+            node.push(format!("protocol: {}", request1.protocol));
+            node.push(format!("mtu: {}", request1.mtu));
+        }
+        0x06 => {
+            body.read_magic()?;
+            node.push(format!("server_guid: {}", body.read_i64_be_bytes()?));
+            node.push(format!("use_security: {}", body.read_byte()? != 0));
+            node.push(format!("mtu: {}", body.read_i16_be_bytes()?));
+        }
+        0x07 => {
+            let request2 = OfflineConnReq2::from_buffer(&mut body)?;
+            node.push(format!("server_address: {}", request2.server_address));
+            node.push(format!("mtu: {}", request2.mtu));
+            node.push(format!("client_guid: {}", request2.client_guid));
+        }
+        0x08 => {
+            body.read_magic()?;
+            node.push(format!("server_guid: {}", body.read_i64_be_bytes()?));
+            node.push(format!("client_address: {}", body.read_address()?));
+            node.push(format!("mtu: {}", body.read_i16_be_bytes()?));
+            node.push(format!("use_encryption: {}", body.read_byte()? != 0));
+        }
+        0x19 => {
+            node.push(format!("protocol: {}", body.read_byte()?));
+            body.read_magic()?;
+            node.push(format!("server_guid: {}", body.read_i64_be_bytes()?));
+        }
+        0xa0 | 0xc0 => {
+            let records = Ack::from_buffer(&mut body)?.records;
+            node.push(format!("records: {records:?}"));
+        }
+        0x80..=0x8d => {
+            let frameset = FrameSet::from_buffer(&mut body)?;
+            node.push(format!("index: {}", frameset.index));
+
+            for frame in frameset.frames {
+                dissect_frame(frame, decompress, node)?;
+            }
+        }
+        _ => {
+            node.push(format!("body: {}", to_hex(body.get_bytes())));
+        }
+    }
+
+    Ok(())
+}
+
+fn dissect_frame(frame: Frame, decompress: bool, parent: &mut Node) -> Result<(), DecodeError> {
+    let rel = &frame.reliability;
+    let node = parent.push(format!("Frame ({} bytes)", frame.bodysize));
+
+    node.push(format!("reliability: {:?}", rel.reltype));
+    if let Some(index) = rel.rel_frameindex {
+        node.push(format!("reliable_index: {index}"));
+    }
+    if let Some(index) = rel.seq_frameindex {
+        node.push(format!("sequenced_index: {index}"));
+    }
+    if let (Some(index), Some(channel)) = (rel.ord_frameindex, rel.ord_channel) {
+        node.push(format!("ordered_index: {index} (channel {channel})"));
+    }
+
+    let frag = &frame.fragment_info;
+    if frag.is_fragmented {
+        node.push(format!(
+            "fragment: {}/{} of compound {}",
+            frag.index.unwrap() + 1,
+            frag.compound_size.unwrap(),
+            frag.compound_id.unwrap()
+        ));
+
+        // only the first fragment starts with the inner packet id,
+        // the rest can't be decoded until they're put back together
+        if frag.index != Some(0) {
+            return Ok(());
+        }
+    }
+
+    let packet_id = frame.inner_packet_id;
+    let inner = node.push(format!(
+        "{} ({packet_id:#04x})",
+        raknet_packet_name(packet_id)
+    ));
+    let mut body = frame.body;
+
+    match packet_id {
+        0x00 | 0x03 => {
+            inner.push(format!("timestamp: {}", body.read_i64_be_bytes()?));
+        }
+        0x09 => {
+            let request = OnlineConnReq::from_buffer(&mut body)?;
+            inner.push(format!("guid: {}", request.guid));
+            inner.push(format!("timestamp: {}", request.timestamp));
+        }
+        0x10 => {
+            inner.push(format!("client_address: {}", body.read_address()?));
+        }
+        0x13 => {
+            let incoming = NewIncomingConnection::from_buffer(&mut body)?;
+            inner.push(format!("server_address: {}", incoming.server_address));
+            inner.push(format!("request_timestamp: {}", incoming.request_timestamp));
+            inner.push(format!("accept_timestamp: {}", incoming.accept_timestamp));
+        }
+        0xfe if !frag.is_fragmented => {
+            dissect_game_batch(&body.read_rest(), decompress, inner);
+        }
+        _ => {
+            inner.push(format!("body: {}", to_hex(&body.read_rest())));
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits each game packet into its id and payload
+fn split_headers(packets: Vec<Vec<u8>>) -> Option<Vec<(u32, Vec<u8>)>> {
+    packets
+        .into_iter()
+        .map(|packet| {
+            let mut packet = MsgBuffer::from(packet);
+            let header = packet.read_u32_varint_bytes().ok()?;
+            Some((header & 0x3ff, packet.read_rest()))
+        })
+        .collect()
+}
+
+fn dissect_game_batch(bytes: &[u8], decompress: bool, node: &mut Node) {
+    // we don't know what the session negotiated, so just try everything
+    // from least to most mangled
+    let attempts = &[
+        ("uncompressed", None, false),
+        ("zlib", Some(CompressionAlgorithm::Zlib), false),
+        ("snappy", Some(CompressionAlgorithm::Snappy), false),
+        ("algorithm header", Some(CompressionAlgorithm::Zlib), true),
+    ];
+
+    let attempts = match decompress {
+        true => &attempts[..],
+        false => &attempts[..1],
+    };
+    let packets = attempts.iter().find_map(|(name, algorithm, header)| {
+        let mut codec = BatchCodec::default();
+        if let Some(algorithm) = algorithm {
+            let settings = CompressionSettings {
+                algorithm: *algorithm,
+                threshold: 0,
+            };
+            codec.enable_compression(settings, *header);
+        }
+
+        let packets = split_headers(codec.decode(bytes).ok()?)?;
+        Some((name, packets))
+    });
+
+    let (compression, packets) = match packets {
+        Some(found) => found,
+        None => {
+            node.push(match decompress {
+                true => "<couldn't decode batch (encrypted?)>".to_string(),
+                false => "<compressed or encrypted, try `voxel dissect`>".to_string(),
+            });
+            node.push(format!("body: {}", to_hex(bytes)));
+            return;
+        }
+    };
+
+    node.push(format!("compression: {compression}"));
+    for (packet_id, payload) in packets {
+        let game = node.push(format!(
+            "{} ({packet_id:#04x}) {} bytes",
+            packet_name(packet_id).unwrap_or("unknown"),
+            payload.len()
+        ));
+        game.push(format!("body: {}", to_hex(&payload)));
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    const MAX_SHOWN: usize = 64;
+
+    let mut out: Vec<String> = bytes
+        .iter()
+        .take(MAX_SHOWN)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if bytes.len() > MAX_SHOWN {
+        out.push(format!("... ({} more)", bytes.len() - MAX_SHOWN));
+    }
+    out.join(" ")
+}
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text
+        .split_whitespace()
+        .flat_map(|word| word.split(','))
+        .map(|word| word.trim_start_matches("0x"))
+        .collect();
+
+    // checked first so slicing by byte below can't land inside a char
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex digit {c:?}"));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex: {:?}", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// `voxel dissect`: one datagram per line, as hex, on stdin
+pub fn run_cli() {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .expect("Couldn't read stdin");
+
+    for line in input.lines().filter(|l| !l.trim().is_empty()) {
+        match from_hex(line) {
+            Ok(bytes) => println!("{}\n", dissect(&bytes, true)),
+            Err(e) => eprintln!("Skipping line: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(from_hex("84 0x00 ff,01"), Ok(vec![0x84, 0x00, 0xff, 0x01]));
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+        // multi-byte chars used to get sliced through the middle
+        assert!(from_hex("aé").is_err());
+        assert!(from_hex("éa").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        // an UnconnectedPing with half a timestamp
+        let tree = dissect(&[0x01, 0x00, 0x00, 0x00], true);
+        assert!(tree.contains("<malformed: "), "{tree}");
+
+        // a frame set whose frame says it's longer than it is
+        let tree = dissect(&[0x84, 0x00, 0x00, 0x00, 0x00, 0x00, 0x48, 0x00], true);
+        assert!(tree.contains("FrameSet"), "{tree}");
+        assert!(tree.contains("<malformed: "), "{tree}");
+    }
+
+    #[test]
+    fn only_decompresses_when_asked() {
+        let mut codec = BatchCodec::default();
+        codec.enable_compression(
+            CompressionSettings {
+                algorithm: CompressionAlgorithm::Zlib,
+                threshold: 0,
+            },
+            false,
+        );
+        let batch = codec.encode(&[vec![0xc1, 0x01, 0x00, 0x00, 0x02, 0x6e]]);
+
+        let mut node = Node::new(String::new());
+        dissect_game_batch(&batch, true, &mut node);
+        assert!(
+            node.render().contains("compression: zlib"),
+            "{}",
+            node.render()
+        );
+
+        let mut node = Node::new(String::new());
+        dissect_game_batch(&batch, false, &mut node);
+        assert!(
+            node.render().contains("<compressed or encrypted"),
+            "{}",
+            node.render()
+        );
+    }
+}
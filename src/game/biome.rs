@@ -0,0 +1,117 @@
+/// game/biome.rs
+/// =============
+///
+/// What the client needs to know about biomes (BiomeDefinitionList):
+/// temperature and downfall for grass and foliage colours and whether it
+/// rains or snows, plus tags the client's own logic goes by.
+///
+/// The full vanilla list comes from PocketMine's BedrockData
+/// (biome_definitions.nbt, one network NBT compound of biome name to
+/// definition), dropped next to the server like the block states.
+/// Without it there are the biomes our generators use, with vanilla's
+/// numbers.
+///
+/// Reference: https://github.com/pmmp/BedrockData
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::nbt::{self, Nbt, NbtFlavour, Tag};
+use crate::raknet::objects::MsgBuffer;
+
+pub const BIOME_DEFINITIONS_FILE: &str = "biome_definitions.nbt";
+
+/// name, temperature, downfall, rain, tags
+type Biome = (&'static str, f32, f32, bool, &'static [&'static str]);
+
+/// Every biome in generator/
+const BUILTIN: &[Biome] = &[
+    ("ocean", 0.5, 0.5, true, &["ocean", "overworld"]),
+    ("plains", 0.8, 0.4, true, &["plains", "overworld"]),
+    ("beach", 0.8, 0.4, true, &["beach", "overworld"]),
+    ("hell", 2.0, 0.0, false, &["nether", "nether_wastes"]),
+    ("the_end", 0.5, 0.5, false, &["the_end"]),
+];
+
+/// The whole BiomeDefinitionList NBT
+pub fn load_definitions(path: &Path) -> Nbt {
+    let read = fs::read(path).map_err(|e| e.to_string()).and_then(|data| {
+        nbt::read(&mut MsgBuffer::from(data), NbtFlavour::Network).map_err(|e| e.to_string())
+    });
+
+    match read.map(|nbt| (nbt.tag.as_compound().map(|b| b.len()), nbt)) {
+        Ok((Some(count), nbt)) => {
+            info!("Loaded {} biome definitions", count);
+            nbt
+        }
+        Ok((None, _)) => {
+            warn!(
+                "{} isn't a compound, using the built in biomes",
+                path.display()
+            );
+            builtin()
+        }
+        Err(e) => {
+            warn!(
+                "Couldn't read {} ({}), only a few biomes will be defined",
+                path.display(),
+                e
+            );
+            builtin()
+        }
+    }
+}
+
+fn builtin() -> Nbt {
+    let biomes = BUILTIN
+        .iter()
+        .map(|&(name, temperature, downfall, rain, tags)| {
+            let definition: Tag = [
+                ("temperature", Tag::Float(temperature)),
+                ("downfall", Tag::Float(downfall)),
+                ("rain", Tag::from(rain)),
+                ("red_spore_density", Tag::Float(0.0)),
+                ("blue_spore_density", Tag::Float(0.0)),
+                ("ash_density", Tag::Float(0.0)),
+                ("white_ash_density", Tag::Float(0.0)),
+                (
+                    "tags",
+                    Tag::List(tags.iter().map(|t| Tag::from(*t)).collect()),
+                ),
+            ]
+            .into_iter()
+            .collect();
+            (name, definition)
+        })
+        .collect();
+
+    Nbt::new(biomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_definitions() {
+        let nbt = load_definitions(Path::new("no-such-file"));
+        let Tag::Compound(biomes) = &nbt.tag else {
+            panic!("{:?}", nbt.tag);
+        };
+
+        assert_eq!(biomes.len(), BUILTIN.len());
+        let plains = biomes["plains"].get("temperature");
+        assert_eq!(plains, Some(&Tag::Float(0.8)));
+        assert_eq!(biomes["hell"].get("rain"), Some(&Tag::Byte(0)));
+
+        // the client reads it as network NBT
+        let mut buf = MsgBuffer::new();
+        nbt::write(&mut buf, &nbt, NbtFlavour::Network);
+        let read = nbt::read(
+            &mut MsgBuffer::from(buf.get_bytes().to_vec()),
+            NbtFlavour::Network,
+        );
+        assert_eq!(read.unwrap(), nbt);
+    }
+}
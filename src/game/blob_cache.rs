@@ -0,0 +1,123 @@
+/// game/blob_cache.rs
+/// ==================
+///
+/// Clients that support it (ClientCacheStatus) keep chunk data on disk,
+/// keyed by its xxHash64. Instead of sub chunks and biomes we send their
+/// hashes, the client answers with the ones it doesn't have
+/// (ClientCacheBlobStatus) and we only send those (ClientCacheMissResponse).
+/// Coming back to an area, or rejoining, ends up costing a few hashes.
+///
+/// We have to hold on to every blob we've sent a hash for until the
+/// client's said whether it has it. The same blob gets sent plenty (empty
+/// sub chunks, the same biomes everywhere) and the client only answers
+/// once for a hash it's been sent a few times, so answers can't be
+/// counted off against sends. Instead blobs are kept for a while after
+/// their hash was last sent, which is plenty of time to hear back.
+///
+/// Reference: https://github.com/df-mc/dragonfly (server/session/chunk.go)
+use std::collections::HashMap;
+
+use xxhash_rust::xxh64::xxh64;
+
+use crate::protocol::v622::Blob;
+
+/// How long (in ticks) a blob's kept after its hash was last sent
+const EXPIRY: u64 = 30 * 20;
+
+#[derive(Default)]
+pub struct BlobCache {
+    /// The client said it has a cache
+    pub enabled: bool,
+    /// Sent by hash, and the tick it was last sent on
+    pending: HashMap<u64, (Vec<u8>, u64)>,
+    ticks: u64,
+}
+
+impl BlobCache {
+    pub fn hash(blob: &[u8]) -> u64 {
+        xxh64(blob, 0)
+    }
+
+    /// Keeps the blob around until the client's had time to ask for it,
+    /// returns what to send instead
+    pub fn track(&mut self, blob: Vec<u8>) -> u64 {
+        let hash = Self::hash(&blob);
+        self.pending.entry(hash).or_insert((blob, 0)).1 = self.ticks;
+
+        hash
+    }
+
+    /// Once a tick, forgets blobs nobody's asked for in a while
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        let ticks = self.ticks;
+        self.pending.retain(|_, (_, sent)| ticks - *sent < EXPIRY);
+    }
+
+    /// Whatever the client's missing. It could still be asked about again
+    /// (it might've been sent twice), so it's only forgotten on expiry.
+    pub fn status(&mut self, missing: &[u64]) -> Vec<Blob> {
+        missing
+            .iter()
+            .filter_map(|hash| {
+                Some(Blob {
+                    hash: *hash,
+                    payload: self.pending.get(hash)?.0.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn miss_and_hit() {
+        let mut cache = BlobCache::default();
+        let a = cache.track(vec![1, 2, 3]);
+        let b = cache.track(vec![4, 5]);
+        assert_eq!(a, BlobCache::hash(&[1, 2, 3]));
+
+        // only what's missing is sent, and only what we sent
+        let blobs = cache.status(&[b, 1234]);
+        assert_eq!(
+            blobs,
+            [Blob {
+                hash: b,
+                payload: vec![4, 5],
+            }]
+        );
+        assert!(cache.status(&[]).is_empty());
+
+        // asked again, from another chunk with the same blob
+        assert_eq!(cache.status(&[b]).len(), 1);
+    }
+
+    #[test]
+    fn expiry() {
+        let mut cache = BlobCache::default();
+        let a = cache.track(vec![1]);
+        // the same blob a few times over, answered once
+        cache.track(vec![2]);
+        cache.track(vec![2]);
+        cache.status(&[a]);
+
+        for _ in 0..EXPIRY - 10 {
+            cache.tick();
+        }
+        // sent again, so it's kept for longer
+        let b = cache.track(vec![2]);
+        for _ in 0..10 {
+            cache.tick();
+        }
+        assert!(cache.status(&[a]).is_empty());
+        assert_eq!(cache.status(&[b]).len(), 1);
+
+        for _ in 0..EXPIRY {
+            cache.tick();
+        }
+        assert!(cache.pending.is_empty());
+    }
+}
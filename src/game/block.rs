@@ -0,0 +1,358 @@
+/// game/block.rs
+/// =============
+///
+/// Every block state the client knows about and the ids they go by. A
+/// state is a block name plus its properties, e.g.
+/// `minecraft:dirt {dirt_type: "coarse"}`, and there are two ways of
+/// numbering them:
+///
+///     runtime ids   0, 1, 2, ... in the order the client sorts states in
+///                   (by the FNV-1 64 hash of the name), so they shift
+///                   whenever an update adds blocks
+///     hashed ids    FNV-1a 32 of the state as little endian NBT, which
+///                   stay the same across updates
+///
+/// Runtime ids are what everything server side uses. Which of the two
+/// goes over the wire is up to block-network-ids-are-hashes, and the
+/// client is told in StartGame.
+///
+/// The states themselves come from the vanilla list (PocketMine's
+/// BedrockData has it as canonical_block_states.nbt), dropped next to the
+/// server like server.properties.
+///
+/// Reference: https://github.com/pmmp/BedrockData
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::nbt::{self, Compound, Nbt, NbtFlavour, Tag};
+use crate::raknet::objects::MsgBuffer;
+
+pub const BLOCK_STATES_FILE: &str = "canonical_block_states.nbt";
+
+/// 1.20.40 packed into an int (major << 24 | minor << 16 | patch << 8),
+/// stamped on every state we save
+pub const BLOCK_STATE_VERSION: i32 = 1 << 24 | 20 << 16 | 40 << 8;
+
+/// What the client shows for states it doesn't know, its hash is fixed
+const UNKNOWN: &str = "minecraft:unknown";
+const UNKNOWN_HASH: u32 = -2i32 as u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockState {
+    pub name: String,
+    pub states: Compound,
+    pub version: i32,
+}
+
+impl BlockState {
+    pub fn new(name: &str, states: Compound) -> Self {
+        Self {
+            name: name.to_string(),
+            states,
+            version: BLOCK_STATE_VERSION,
+        }
+    }
+
+    /// `{name, states, version}`, how states are stored everywhere
+    pub fn from_nbt(tag: &Tag) -> Option<Self> {
+        Some(Self {
+            name: tag.get("name")?.as_str()?.to_string(),
+            states: tag
+                .get("states")
+                .and_then(Tag::as_compound)
+                .cloned()
+                .unwrap_or_default(),
+            version: tag
+                .get("version")
+                .and_then(Tag::as_i64)
+                .map_or(BLOCK_STATE_VERSION, |v| v as i32),
+        })
+    }
+
+    pub fn to_nbt(&self) -> Tag {
+        [
+            ("name", Tag::from(self.name.as_str())),
+            ("states", Tag::Compound(self.states.clone())),
+            ("version", Tag::Int(self.version)),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// The hashed network id. Only the name and properties count, so
+    /// the same state from any version hashes the same.
+    pub fn network_hash(&self) -> u32 {
+        if self.name == UNKNOWN {
+            return UNKNOWN_HASH;
+        }
+
+        let tag: Tag = [
+            ("name", Tag::from(self.name.as_str())),
+            ("states", Tag::Compound(self.states.clone())),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut buf = MsgBuffer::new();
+        nbt::write(&mut buf, &Nbt::new(tag), NbtFlavour::LittleEndian);
+        fnv1a_32(buf.get_bytes())
+    }
+}
+
+pub struct BlockPalette {
+    /// Indexed by runtime id
+    states: Vec<BlockState>,
+    hashes: Vec<u32>,
+    by_hash: HashMap<u32, u32>,
+    /// The first state of every block, for when only the name matters
+    by_name: HashMap<String, u32>,
+    /// block-network-ids-are-hashes
+    pub hashed_ids: bool,
+    pub air: u32,
+}
+
+impl BlockPalette {
+    /// Falls back to a handful of built in states if there's no list,
+    /// enough to get a world going as long as ids are hashed (runtime
+    /// ids won't line up with the client's)
+    pub fn load(path: &Path, hashed_ids: bool) -> Self {
+        let states = match fs::read(path) {
+            Ok(data) => Self::parse(data),
+            Err(e) => {
+                warn!(
+                    "Couldn't read {} ({}), only a few blocks will be available",
+                    path.display(),
+                    e
+                );
+                if !hashed_ids {
+                    warn!("Set block-network-ids-are-hashes=true or clients will see the wrong blocks");
+                }
+                Self::builtin()
+            }
+        };
+
+        let palette = Self::new(states, hashed_ids);
+        info!("Loaded {} block states", palette.len());
+        palette
+    }
+
+    /// The file's just one network NBT compound after the other
+    fn parse(data: Vec<u8>) -> Vec<BlockState> {
+        let mut buf = MsgBuffer::from(data);
+        let mut states = vec![];
+
+        while buf.len_rest() > 0 {
+            let nbt = match nbt::read(&mut buf, NbtFlavour::Network) {
+                Ok(nbt) => nbt,
+                Err(e) => {
+                    warn!("{BLOCK_STATES_FILE} is cut short ({e}), skipping the rest");
+                    break;
+                }
+            };
+            match BlockState::from_nbt(&nbt.tag) {
+                Some(state) => states.push(state),
+                None => warn!("Skipping block state without a name: {:?}", nbt.tag),
+            }
+        }
+
+        states
+    }
+
+    fn builtin() -> Vec<BlockState> {
+        let state = |name: &str, states: &[(&str, Tag)]| {
+            BlockState::new(
+                name,
+                states
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            )
+        };
+
+        vec![
+            state("minecraft:air", &[]),
+            state("minecraft:bedrock", &[("infiniburn_bit", Tag::Byte(0))]),
+            state("minecraft:dirt", &[("dirt_type", "normal".into())]),
+            state("minecraft:end_stone", &[]),
+            state("minecraft:grass", &[]),
+            state("minecraft:lava", &[("liquid_depth", Tag::Int(0))]),
+            state("minecraft:netherrack", &[]),
+            state("minecraft:sand", &[("sand_type", "normal".into())]),
+            state("minecraft:stone", &[("stone_type", "stone".into())]),
+            state("minecraft:water", &[("liquid_depth", Tag::Int(0))]),
+            state(UNKNOWN, &[]),
+        ]
+    }
+
+    pub fn new(mut states: Vec<BlockState>, hashed_ids: bool) -> Self {
+        // same order as the client, states of one block keep theirs
+        states.sort_by_key(|s| fnv1_64(s.name.as_bytes()));
+
+        let hashes: Vec<u32> = states.iter().map(BlockState::network_hash).collect();
+        let mut by_hash = HashMap::new();
+        let mut by_name = HashMap::new();
+        for (id, state) in states.iter().enumerate() {
+            by_hash.insert(hashes[id], id as u32);
+            by_name.entry(state.name.clone()).or_insert(id as u32);
+        }
+
+        let air = by_name.get("minecraft:air").copied().unwrap_or(0);
+
+        Self {
+            states,
+            hashes,
+            by_hash,
+            by_name,
+            hashed_ids,
+            air,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn state(&self, runtime_id: u32) -> Option<&BlockState> {
+        self.states.get(runtime_id as usize)
+    }
+
+    pub fn runtime_id(&self, state: &BlockState) -> Option<u32> {
+        self.by_hash.get(&state.network_hash()).copied()
+    }
+
+    /// The first state of a block, `minecraft:` can be left out
+    pub fn default_state(&self, name: &str) -> Option<u32> {
+        if name.contains(':') {
+            self.by_name.get(name).copied()
+        } else {
+            self.by_name.get(&format!("minecraft:{name}")).copied()
+        }
+    }
+
+    /// One of a state's properties, e.g. a crop's `growth`
+    pub fn property(&self, runtime_id: u32, name: &str) -> Option<&Tag> {
+        self.state(runtime_id)?.states.get(name)
+    }
+
+    /// The same block with one property changed, `None` if there's no
+    /// such state
+    pub fn with_property(&self, runtime_id: u32, name: &str, value: Tag) -> Option<u32> {
+        let mut state = self.state(runtime_id)?.clone();
+        *state.states.get_mut(name)? = value;
+        self.runtime_id(&state)
+    }
+
+    /// What the client calls a runtime id, depending on
+    /// block-network-ids-are-hashes
+    pub fn network_id(&self, runtime_id: u32) -> u32 {
+        if self.hashed_ids {
+            self.hashes[runtime_id as usize]
+        } else {
+            runtime_id
+        }
+    }
+}
+
+fn fnv1a_32(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn fnv1_64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        hash.wrapping_mul(0x00000100000001b3) ^ *byte as u64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv() {
+        // the reference vectors
+        assert_eq!(fnv1a_32(b""), 0x811c9dc5);
+        assert_eq!(fnv1a_32(b"a"), 0xe40c292c);
+        assert_eq!(fnv1a_32(b"foobar"), 0xbf9cf968);
+        assert_eq!(fnv1_64(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1_64(b"a"), 0xaf63bd4c8601b7be);
+        assert_eq!(fnv1_64(b"foobar"), 0x340d8765a4dda9c2);
+    }
+
+    #[test]
+    fn network_hash() {
+        // what vanilla sends for air with hashed ids
+        let air = BlockState::new("minecraft:air", Compound::default());
+        assert_eq!(air.network_hash() as i32, -604749536);
+        assert_eq!(
+            BlockState::new(UNKNOWN, Compound::default()).network_hash(),
+            UNKNOWN_HASH
+        );
+
+        // the version doesn't count
+        let old = BlockState {
+            version: 17959425,
+            ..air.clone()
+        };
+        assert_eq!(old.network_hash(), air.network_hash());
+
+        let dirt = |dirt_type: &str| {
+            BlockState::new(
+                "minecraft:dirt",
+                [("dirt_type".to_string(), Tag::from(dirt_type))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        assert_ne!(dirt("normal").network_hash(), dirt("coarse").network_hash());
+    }
+
+    #[test]
+    fn palette_order() {
+        let mut states = BlockPalette::builtin();
+        let coarse = BlockState::new(
+            "minecraft:dirt",
+            [("dirt_type".to_string(), Tag::from("coarse"))]
+                .into_iter()
+                .collect(),
+        );
+        states.push(coarse.clone());
+
+        let palette = BlockPalette::new(states, false);
+        let names: Vec<&str> = palette.states.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "minecraft:stone",
+                "minecraft:lava",
+                "minecraft:end_stone",
+                "minecraft:sand",
+                "minecraft:unknown",
+                "minecraft:water",
+                "minecraft:dirt",
+                "minecraft:dirt",
+                "minecraft:air",
+                "minecraft:grass",
+                "minecraft:bedrock",
+                "minecraft:netherrack",
+            ]
+        );
+
+        // states of the same block stay in the order they came in
+        let normal = palette.default_state("dirt").unwrap();
+        assert_eq!(palette.runtime_id(&coarse), Some(normal + 1));
+        assert_eq!(
+            palette.with_property(normal, "dirt_type", "coarse".into()),
+            Some(normal + 1)
+        );
+        assert_eq!(palette.air, 8);
+        assert_eq!(palette.network_id(palette.air), 8);
+
+        let hashed = BlockPalette::new(BlockPalette::builtin(), true);
+        assert_eq!(hashed.network_id(hashed.air) as i32, -604749536);
+    }
+}
@@ -0,0 +1,146 @@
+/// game/block_tick.rs
+/// ==================
+///
+/// What blocks do on their own. There are two kinds of block tick, both
+/// only for chunks within tick-distance of a player:
+///
+///     random      every tick, randomTickSpeed blocks picked at random in
+///                 each sub chunk, slow things like crops growing
+///     scheduled   a block asking to be ticked so many ticks from now,
+///                 for things on a timer like fire burning out. They're
+///                 kept with the chunk (see world.rs) so they survive a
+///                 restart.
+///
+/// Which blocks do anything is worked out once from the palette, most
+/// don't.
+///
+/// Reference: https://minecraft.wiki/w/Tick#Block_tick
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use super::block::BlockPalette;
+use super::dimension::Dimension;
+use super::world::World;
+use crate::nbt::Tag;
+
+/// Fully grown
+const MAX_GROWTH: i32 = 7;
+/// Burnt out
+const MAX_FIRE_AGE: i32 = 15;
+/// Fire ages every 30 to 39 ticks
+const FIRE_DELAY: u64 = 30;
+const FIRE_DELAY_SPREAD: u64 = 10;
+
+const CROPS: [&str; 4] = [
+    "minecraft:wheat",
+    "minecraft:carrots",
+    "minecraft:potatoes",
+    "minecraft:beetroot",
+];
+const FIRE: &str = "minecraft:fire";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Behaviour {
+    Crop,
+    Fire,
+}
+
+/// Runtime id to what it does, for every state that does something
+pub struct BlockBehaviours {
+    behaviours: HashMap<u32, Behaviour>,
+}
+
+impl BlockBehaviours {
+    pub fn new(blocks: &BlockPalette) -> Self {
+        let behaviours = (0..blocks.len() as u32)
+            .filter_map(|id| {
+                let name = blocks.state(id)?.name.as_str();
+                let behaviour = match name {
+                    _ if CROPS.contains(&name) => Behaviour::Crop,
+                    FIRE => Behaviour::Fire,
+                    _ => return None,
+                };
+                Some((id, behaviour))
+            })
+            .collect();
+
+        Self { behaviours }
+    }
+
+    /// Whether there's any point picking it for a random tick
+    pub fn random_ticks(&self, block: u32) -> bool {
+        self.behaviours.contains_key(&block)
+    }
+
+    pub fn random_tick(
+        &self,
+        world: &mut World,
+        dimension: Dimension,
+        position: (i32, i32, i32),
+        block: u32,
+    ) {
+        match self.behaviours.get(&block) {
+            Some(Behaviour::Crop) => grow_crop(world, dimension, position, block),
+            // in case it's lost its scheduled tick somehow, e.g. it was
+            // placed by something that doesn't know about them
+            Some(Behaviour::Fire) if !world.has_scheduled_tick(dimension, position) => {
+                world.schedule_tick(dimension, position, fire_delay());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn scheduled_tick(
+        &self,
+        world: &mut World,
+        dimension: Dimension,
+        position: (i32, i32, i32),
+        block: u32,
+    ) {
+        if let Some(Behaviour::Fire) = self.behaviours.get(&block) {
+            age_fire(world, dimension, position, block);
+        }
+    }
+}
+
+/// A third of the time, one stage closer to harvest. Vanilla's odds
+/// depend on the farmland and the light, this is about the best case.
+fn grow_crop(world: &mut World, dimension: Dimension, position: (i32, i32, i32), block: u32) {
+    if !rand::thread_rng().gen_ratio(1, 3) {
+        return;
+    }
+
+    let blocks = world.blocks().clone();
+    let Some(growth) = blocks.property(block, "growth").and_then(Tag::as_i64) else {
+        return;
+    };
+    if growth as i32 >= MAX_GROWTH {
+        return;
+    }
+    if let Some(grown) = blocks.with_property(block, "growth", Tag::Int(growth as i32 + 1)) {
+        world.set_block(dimension, position, grown);
+    }
+}
+
+/// Fire gets older until it goes out. It doesn't spread (yet).
+fn age_fire(world: &mut World, dimension: Dimension, position: (i32, i32, i32), block: u32) {
+    let blocks = world.blocks().clone();
+    let age = blocks
+        .property(block, "age")
+        .and_then(Tag::as_i64)
+        .unwrap_or(0) as i32;
+
+    if age >= MAX_FIRE_AGE {
+        world.set_block(dimension, position, blocks.air);
+        return;
+    }
+    if let Some(older) = blocks.with_property(block, "age", Tag::Int(age + 1)) {
+        world.set_block(dimension, position, older);
+    }
+    world.schedule_tick(dimension, position, fire_delay());
+}
+
+fn fire_delay() -> u64 {
+    FIRE_DELAY + rand::thread_rng().gen_range(0..FIRE_DELAY_SPREAD)
+}
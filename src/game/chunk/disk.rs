@@ -0,0 +1,206 @@
+/// game/chunk/disk.rs
+/// ==================
+///
+/// How a chunk's kept in a world's database. It's split over a few keys
+/// (level_db.rs has the keys themselves), this is what goes in them:
+///
+///     sub chunk prefix  one per sub chunk with something in it, see
+///                       sub_chunk.rs
+///     data 3D           the heightmap (256 i16s, y + 1 of the highest
+///                       block counted from the bottom of the world), then
+///                       a biome storage per sub chunk with u32 values
+///     data 2D           before 1.18: the heightmap, then a biome id per
+///                       column (`z << 4 | x`)
+///     block entities    little endian NBT, one after the other
+///     pending ticks     little endian NBT, `currentTick` (int) and
+///                       `tickList`, each with the `blockState`, the
+///                       `time` (long) it's due and its `x`, `y` and `z`
+///
+/// Decoding gives back a DecodeError for anything that doesn't make sense,
+/// whoever's loading the chunk decides what to do about it.
+///
+/// Reference: https://github.com/df-mc/dragonfly (server/world/mcdb)
+use super::storage::{PalettedStorage, REPEAT_PREVIOUS_DISK};
+use super::{Chunk, ScheduledTick, SubChunk};
+use crate::game::block::BlockPalette;
+use crate::nbt::{self, Compound, Nbt, NbtFlavour, Tag};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+
+impl Chunk {
+    /// `None` for a sub chunk that's nothing but air, those aren't stored
+    pub fn encode_disk_sub_chunk(&self, y_index: i32, blocks: &BlockPalette) -> Option<Vec<u8>> {
+        let sub_chunk = self.sub_chunk(y_index).filter(|s| !s.is_empty())?;
+        let mut buf = MsgBuffer::new();
+        sub_chunk.encode_disk(&mut buf, y_index as i8, blocks);
+
+        Some(buf.get_bytes().clone())
+    }
+
+    pub fn set_sub_chunk(&mut self, y_index: i32, sub_chunk: SubChunk) {
+        if let Some(existing) = self.sub_chunk_mut(y_index) {
+            *existing = sub_chunk;
+        }
+    }
+
+    pub fn encode_disk_biomes(&self) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+        for height in self.heightmap {
+            buf.write(&(height - self.min_y() as i16).to_le_bytes());
+        }
+
+        let mut previous = None;
+        for biome in &self.biomes {
+            if previous == Some(biome) {
+                buf.write_byte(REPEAT_PREVIOUS_DISK);
+            } else {
+                biome.encode_disk(&mut buf, |buf, id| buf.write(&id.to_le_bytes()));
+            }
+            previous = Some(biome);
+        }
+
+        buf.get_bytes().clone()
+    }
+
+    /// Data 3D. The heightmap's worked out again from the blocks, see
+    /// `recalculate_heightmap`.
+    pub fn decode_disk_biomes(&mut self, data: Vec<u8>) -> Result<(), DecodeError> {
+        let mut buf = MsgBuffer::from(data);
+        buf.read_vec(512)?;
+
+        for i in 0..self.biomes.len() {
+            // older worlds can have fewer, the top ones are the same as
+            // the highest there is
+            let storage = match buf.at_end() {
+                true => None,
+                false => PalettedStorage::decode_disk(&mut buf, |buf| {
+                    Ok(u32::from_le_bytes(buf.read_array()?))
+                })?,
+            };
+
+            match (storage, i) {
+                (Some(storage), _) => self.biomes[i] = storage,
+                (None, 0) => {}
+                (None, _) => self.biomes[i] = self.biomes[i - 1].clone(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Data 2D, one biome for the whole column
+    pub fn decode_disk_biomes_2d(&mut self, data: Vec<u8>) -> Result<(), DecodeError> {
+        let mut buf = MsgBuffer::from(data);
+        buf.read_vec(512)?;
+        let columns = buf.read_vec(256)?;
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let biome = columns[(z as usize) << 4 | x as usize] as u32;
+                for y in self.min_y()..self.max_y() {
+                    self.set_biome(x, y, z, biome);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn encode_disk_block_entities(&self) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+        for block_entity in self.block_entities.values() {
+            let nbt = Nbt::new(Tag::Compound(block_entity.clone()));
+            nbt::write(&mut buf, &nbt, NbtFlavour::LittleEndian);
+        }
+
+        buf.get_bytes().clone()
+    }
+
+    /// Anything without a position is dropped
+    pub fn decode_disk_block_entities(&mut self, data: Vec<u8>) -> Result<(), DecodeError> {
+        let mut buf = MsgBuffer::from(data);
+
+        while !buf.at_end() {
+            let Tag::Compound(block_entity) = nbt::read(&mut buf, NbtFlavour::LittleEndian)?.tag
+            else {
+                continue;
+            };
+            let position = |name: &str| Some(block_entity.get(name)?.as_i64()? as i32);
+            let (Some(x), Some(y), Some(z)) = (position("x"), position("y"), position("z")) else {
+                continue;
+            };
+
+            self.block_entities.insert((x, y, z), block_entity);
+        }
+
+        Ok(())
+    }
+
+    pub fn encode_disk_scheduled_ticks(&self, current_tick: u64, blocks: &BlockPalette) -> Vec<u8> {
+        let ticks = self
+            .scheduled_ticks
+            .iter()
+            .filter_map(|tick| {
+                let (x, y, z) = tick.position;
+                let tick: Compound = [
+                    ("blockState", blocks.state(tick.block)?.to_nbt()),
+                    ("time", Tag::Long(tick.tick as i64)),
+                    ("x", Tag::Int(x)),
+                    ("y", Tag::Int(y)),
+                    ("z", Tag::Int(z)),
+                ]
+                .into_iter()
+                .map(|(name, tag)| (name.to_string(), tag))
+                .collect();
+                Some(Tag::Compound(tick))
+            })
+            .collect();
+
+        let pending_ticks: Compound = [
+            ("currentTick".to_string(), Tag::Int(current_tick as i32)),
+            ("tickList".to_string(), Tag::List(ticks)),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut buf = MsgBuffer::new();
+        let nbt = Nbt::new(Tag::Compound(pending_ticks));
+        nbt::write(&mut buf, &nbt, NbtFlavour::LittleEndian);
+
+        buf.get_bytes().clone()
+    }
+
+    /// `read_block` turns a `blockState` into a runtime id, ticks missing
+    /// anything are dropped
+    pub fn decode_disk_scheduled_ticks(
+        &mut self,
+        data: Vec<u8>,
+        read_block: impl Fn(&Tag) -> u32,
+    ) -> Result<(), DecodeError> {
+        let mut buf = MsgBuffer::from(data);
+        let nbt = nbt::read(&mut buf, NbtFlavour::LittleEndian)?;
+        let Some(ticks) = nbt.tag.get("tickList").and_then(Tag::as_list) else {
+            return Ok(());
+        };
+
+        for tick in ticks {
+            let field = |name: &str| tick.get(name)?.as_i64();
+            let (Some(block), Some(time), Some(x), Some(y), Some(z)) = (
+                tick.get("blockState"),
+                field("time"),
+                field("x"),
+                field("y"),
+                field("z"),
+            ) else {
+                continue;
+            };
+
+            self.scheduled_ticks.push(ScheduledTick {
+                position: (x as i32, y as i32, z as i32),
+                block: read_block(block),
+                tick: time.max(0) as u64,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+/// game/chunk/mod.rs
+/// =================
+///
+/// A 16 block wide column of the world, from the bottom of the dimension
+/// to the top, split into 16x16x16 sub chunks (24 of them in the
+/// overworld, y -64 to 319). Biomes are 3D and stored per sub chunk too,
+/// in the same paletted storage as blocks.
+///
+/// Positions inside a chunk are `x` and `z` from 0 to 15 and the world
+/// `y`, block ids are runtime ids from the block palette.
+///
+/// LevelChunk's payload (when the client isn't asking for sub chunks
+/// itself) is:
+///
+///     ...   the sub chunks, from the bottom up to the highest non-empty one
+///     ...   a biome storage per sub chunk in the dimension, all of them
+///     u8    border block count (education edition only, always 0)
+///     ...   block entities, as network NBT one after the other
+///
+/// or, when the client asks for sub chunks itself, just the biomes and
+/// the border blocks. The sub chunks then come one by one, each followed
+/// by the block entities in it.
+///
+/// With the blob cache the sub chunks and the biomes (as one blob) are
+/// sent separately by hash, so the payload's only what's left.
+///
+/// Reference: https://github.com/df-mc/dragonfly (server/world/chunk)
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::nbt::{self, Compound, Nbt, NbtFlavour, Tag};
+use crate::raknet::objects::MsgBuffer;
+
+use super::block::BlockPalette;
+
+mod disk;
+mod storage;
+mod sub_chunk;
+
+pub use storage::PalettedStorage;
+pub use sub_chunk::SubChunk;
+
+/// Where a sub chunk is compared to the highest block of each column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubChunkHeightmap {
+    /// The highest block of every column is below it
+    TooLow,
+    /// ... or above it
+    TooHigh,
+    /// Per column (`x | z << 4`), the highest block's y inside the sub
+    /// chunk, -1 if it's below and 16 if it's above
+    Data(Vec<i8>),
+}
+
+/// A block that's asked to be ticked later (see block_tick.rs)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledTick {
+    /// World position
+    pub position: (i32, i32, i32),
+    /// What was there when it was scheduled, the tick's dropped if that's
+    /// changed since
+    pub block: u32,
+    /// The world's `current_tick` it's due on
+    pub tick: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    /// Which sub chunks there are, -4..20 in the overworld
+    sections: Range<i32>,
+    sub_chunks: Vec<SubChunk>,
+    biomes: Vec<PalettedStorage>,
+    /// y + 1 of the highest block in each column (`x | z << 4`), or the
+    /// bottom of the chunk if there's nothing but air
+    heightmap: [i16; 256],
+    /// Keyed by world position, the NBT has it too (x, y, z)
+    pub block_entities: HashMap<(i32, i32, i32), Compound>,
+    pub scheduled_ticks: Vec<ScheduledTick>,
+    /// Loaded from disk with something that couldn't be written back
+    /// exactly as it was (block states we don't know, or it didn't load
+    /// at all), so it never is
+    pub read_only: bool,
+    air: u32,
+}
+
+impl Chunk {
+    /// All air, all `biome`
+    pub fn new(x: i32, z: i32, sections: Range<i32>, air: u32, biome: u32) -> Self {
+        let count = sections.len();
+
+        Self {
+            x,
+            z,
+            sub_chunks: vec![SubChunk::new(air); count],
+            biomes: vec![PalettedStorage::new(biome); count],
+            heightmap: [(sections.start * 16) as i16; 256],
+            sections,
+            block_entities: HashMap::new(),
+            scheduled_ticks: vec![],
+            read_only: false,
+            air,
+        }
+    }
+
+    pub fn min_y(&self) -> i32 {
+        self.sections.start * 16
+    }
+
+    /// Exclusive
+    pub fn max_y(&self) -> i32 {
+        self.sections.end * 16
+    }
+
+    pub fn sections(&self) -> Range<i32> {
+        self.sections.clone()
+    }
+
+    /// By y index, the same one the client uses (y >> 4)
+    pub fn sub_chunk(&self, y_index: i32) -> Option<&SubChunk> {
+        self.sub_chunks
+            .get(usize::try_from(y_index - self.sections.start).ok()?)
+    }
+
+    pub fn sub_chunk_mut(&mut self, y_index: i32) -> Option<&mut SubChunk> {
+        self.sub_chunks
+            .get_mut(usize::try_from(y_index - self.sections.start).ok()?)
+    }
+
+    pub fn block(&self, x: u8, y: i32, z: u8) -> u32 {
+        self.block_layer(x, y, z, 0)
+    }
+
+    /// Air for anything outside the chunk
+    pub fn block_layer(&self, x: u8, y: i32, z: u8, layer: usize) -> u32 {
+        match self.sub_chunk(y >> 4) {
+            Some(sub_chunk) => sub_chunk.block(x, (y & 15) as u8, z, layer),
+            None => self.air,
+        }
+    }
+
+    pub fn set_block(&mut self, x: u8, y: i32, z: u8, block: u32) {
+        self.set_block_layer(x, y, z, 0, block);
+    }
+
+    /// Anything outside the chunk is ignored
+    pub fn set_block_layer(&mut self, x: u8, y: i32, z: u8, layer: usize, block: u32) {
+        let air = self.air;
+        let Some(sub_chunk) = self.sub_chunk_mut(y >> 4) else {
+            return;
+        };
+        sub_chunk.set_block(x, (y & 15) as u8, z, layer, block);
+
+        if layer != 0 {
+            return;
+        }
+
+        let column = (x & 15) as usize | ((z & 15) as usize) << 4;
+        let height = self.heightmap[column] as i32;
+        if block != air && y >= height {
+            self.heightmap[column] = (y + 1) as i16;
+        } else if block == air && y + 1 == height {
+            self.heightmap[column] = self.find_height(x, z, y) as i16;
+        }
+    }
+
+    /// y + 1 of the highest block below `below`
+    fn find_height(&self, x: u8, z: u8, below: i32) -> i32 {
+        (self.min_y()..below)
+            .rev()
+            .find(|y| self.block(x, *y, z) != self.air)
+            .map_or(self.min_y(), |y| y + 1)
+    }
+
+    /// y + 1 of the highest block in the column
+    pub fn height(&self, x: u8, z: u8) -> i32 {
+        self.heightmap[(x & 15) as usize | ((z & 15) as usize) << 4] as i32
+    }
+
+    /// After filling sub chunks directly
+    pub fn recalculate_heightmap(&mut self) {
+        for x in 0..16 {
+            for z in 0..16 {
+                self.heightmap[x as usize | (z as usize) << 4] =
+                    self.find_height(x, z, self.max_y()) as i16;
+            }
+        }
+    }
+
+    pub fn biome(&self, x: u8, y: i32, z: u8) -> Option<u32> {
+        let index = usize::try_from((y >> 4) - self.sections.start).ok()?;
+        Some(self.biomes.get(index)?.get(x, (y & 15) as u8, z))
+    }
+
+    pub fn set_biome(&mut self, x: u8, y: i32, z: u8, biome: u32) {
+        let Ok(index) = usize::try_from((y >> 4) - self.sections.start) else {
+            return;
+        };
+        if let Some(storage) = self.biomes.get_mut(index) {
+            storage.set(x, (y & 15) as u8, z, biome);
+        }
+    }
+
+    /// How many sub chunks go in LevelChunk, everything above the
+    /// highest one with something in it is left out
+    pub fn network_sub_chunk_count(&self) -> usize {
+        self.sub_chunks
+            .iter()
+            .rposition(|s| !s.is_empty())
+            .map_or(0, |i| i + 1)
+    }
+
+    pub fn sub_chunk_heightmap(&self, y_index: i32) -> SubChunkHeightmap {
+        let base = y_index * 16;
+        let relative: Vec<i8> = self
+            .heightmap
+            .iter()
+            .map(|height| (*height as i32 - 1 - base).clamp(-1, 16) as i8)
+            .collect();
+
+        if relative.iter().all(|y| *y == -1) {
+            SubChunkHeightmap::TooLow
+        } else if relative.iter().all(|y| *y == 16) {
+            SubChunkHeightmap::TooHigh
+        } else {
+            SubChunkHeightmap::Data(relative)
+        }
+    }
+
+    /// One sub chunk on its own, as SubChunk and the blob cache send them
+    pub fn encode_sub_chunk(&self, y_index: i32, blocks: &BlockPalette) -> Option<Vec<u8>> {
+        let sub_chunk = self.sub_chunk(y_index)?;
+        let mut buf = MsgBuffer::new();
+        sub_chunk.encode_network(&mut buf, y_index as i8, blocks);
+
+        Some(buf.get_bytes().clone())
+    }
+
+    /// The block entities in one sub chunk, they go after it in SubChunk
+    pub fn encode_sub_chunk_block_entities(&self, y_index: i32) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+        for (_, block_entity) in self
+            .block_entities
+            .iter()
+            .filter(|((_, y, _), _)| y >> 4 == y_index)
+        {
+            let nbt = Nbt::new(Tag::Compound(block_entity.clone()));
+            nbt::write(&mut buf, &nbt, NbtFlavour::Network);
+        }
+
+        buf.get_bytes().clone()
+    }
+
+    /// Biomes for every sub chunk, a storage that's the same as the one
+    /// below it is just a marker
+    pub fn encode_biomes(&self, buf: &mut MsgBuffer) {
+        let mut previous = None;
+        for biome in &self.biomes {
+            if previous == Some(biome) {
+                buf.write_byte(storage::REPEAT_PREVIOUS);
+            } else {
+                biome.encode_network(buf, |id| id);
+            }
+            previous = Some(biome);
+        }
+    }
+
+    pub fn encode_block_entities(&self, buf: &mut MsgBuffer) {
+        for block_entity in self.block_entities.values() {
+            let nbt = Nbt::new(Tag::Compound(block_entity.clone()));
+            nbt::write(buf, &nbt, NbtFlavour::Network);
+        }
+    }
+
+    /// LevelChunk's payload with every (non-empty) sub chunk in it
+    pub fn encode_network(&self, blocks: &BlockPalette) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+
+        for (i, sub_chunk) in self.sub_chunks[..self.network_sub_chunk_count()]
+            .iter()
+            .enumerate()
+        {
+            let y_index = self.sections.start + i as i32;
+            sub_chunk.encode_network(&mut buf, y_index as i8, blocks);
+        }
+
+        self.encode_biomes(&mut buf);
+        // border blocks
+        buf.write_byte(0);
+        self.encode_block_entities(&mut buf);
+
+        buf.get_bytes().clone()
+    }
+
+    /// What goes in LevelChunk besides the blobs
+    pub fn encode_network_without_blobs(&self) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+
+        // border blocks
+        buf.write_byte(0);
+        self.encode_block_entities(&mut buf);
+
+        buf.get_bytes().clone()
+    }
+
+    /// LevelChunk's payload when the client's going to ask for the sub
+    /// chunks
+    pub fn encode_network_without_sub_chunks(&self) -> Vec<u8> {
+        let mut buf = MsgBuffer::new();
+
+        self.encode_biomes(&mut buf);
+        // border blocks
+        buf.write_byte(0);
+
+        buf.get_bytes().clone()
+    }
+}
@@ -0,0 +1,329 @@
+/// game/chunk/storage.rs
+/// =====================
+///
+/// Bedrock's paletted storage: 16x16x16 values (block runtime ids or
+/// biome ids) stored as indices into a palette of the distinct values,
+/// each index as small as the palette allows. Indices are packed into
+/// u32 words without spanning two words, so some sizes waste a few bits:
+///
+///     bits per index   0  1   2   3   4   5   6   8   16
+///     per word         -  32  16  10  8   6   5   4   2
+///
+/// 0 bits means every value is the same and there are no words at all.
+/// Positions are ordered x, then z, then y (`x << 8 | z << 4 | y`).
+///
+/// On the network it's a header byte (`bits << 1 | 1`), the words, and
+/// then the palette as zigzag varints. On disk the header's low bit is
+/// clear, the palette count's a u32 and the values are whatever they are
+/// (u32s for biomes, NBT for blocks). Either way there's no count when
+/// there's only the one value.
+///
+/// Reference: https://github.com/df-mc/dragonfly (server/world/chunk/paletted_storage.go)
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+
+const SIZES: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 8, 16];
+
+/// Header byte for "same as the previous storage", only allowed for biomes
+pub const REPEAT_PREVIOUS: u8 = 0x7f << 1 | 1;
+pub const REPEAT_PREVIOUS_DISK: u8 = 0x7f << 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalettedStorage {
+    bits: u8,
+    words: Vec<u32>,
+    palette: Vec<u32>,
+}
+
+impl PalettedStorage {
+    /// Every position set to `value`
+    pub fn new(value: u32) -> Self {
+        Self {
+            bits: 0,
+            words: vec![],
+            palette: vec![value],
+        }
+    }
+
+    fn index(x: u8, y: u8, z: u8) -> usize {
+        (x as usize & 15) << 8 | (z as usize & 15) << 4 | (y as usize & 15)
+    }
+
+    fn per_word(bits: u8) -> usize {
+        32 / bits as usize
+    }
+
+    fn word_count(bits: u8) -> usize {
+        match bits {
+            0 => 0,
+            bits => 4096usize.div_ceil(Self::per_word(bits)),
+        }
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let per_word = Self::per_word(self.bits);
+        let word = self.words[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits as u32;
+        ((word >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = Self::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits as u32;
+        let mask = ((1u32 << self.bits) - 1) << shift;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !mask) | ((palette_index as u32) << shift & mask);
+    }
+
+    pub fn get(&self, x: u8, y: u8, z: u8) -> u32 {
+        self.palette[self.palette_index(Self::index(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: u8, y: u8, z: u8, value: u32) {
+        let index = Self::index(x, y, z);
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(i) => i,
+            None => {
+                if self.palette.len() >= 1 << self.bits {
+                    self.repack(1);
+                }
+                self.palette.push(value);
+                self.palette.len() - 1
+            }
+        };
+
+        if self.bits > 0 {
+            self.set_palette_index(index, palette_index);
+        }
+    }
+
+    /// Drops palette entries nothing uses any more, then packs the
+    /// indices as small as they go with room for `extra` more values
+    fn repack(&mut self, extra: usize) {
+        let mut indices: Vec<usize> = (0..4096).map(|i| self.palette_index(i)).collect();
+
+        let mut remap = vec![None; self.palette.len()];
+        let mut palette = vec![];
+        for index in indices.iter_mut() {
+            *index = *remap[*index].get_or_insert_with(|| {
+                palette.push(self.palette[*index]);
+                palette.len() - 1
+            });
+        }
+
+        self.bits = SIZES
+            .into_iter()
+            .find(|b| 1usize << b >= palette.len() + extra)
+            .expect("a palette can't have more than 4096 values");
+        self.palette = palette;
+        self.words = vec![0; Self::word_count(self.bits)];
+        if self.bits > 0 {
+            for (i, palette_index) in indices.into_iter().enumerate() {
+                self.set_palette_index(i, palette_index);
+            }
+        }
+    }
+
+    /// The one value everywhere, if there is one
+    pub fn uniform(&self) -> Option<u32> {
+        if self.palette.len() == 1 {
+            return Some(self.palette[0]);
+        }
+
+        // the palette doesn't shrink when values get overwritten
+        let first = self.palette_index(0);
+        (1..4096)
+            .all(|i| self.palette_index(i) == first)
+            .then(|| self.palette[first])
+    }
+
+    pub fn palette(&self) -> &[u32] {
+        &self.palette
+    }
+
+    /// `network_id` turns what's in the palette into what the client
+    /// expects (block runtime ids into network ids, biomes as they are)
+    pub fn encode_network(&self, buf: &mut MsgBuffer, network_id: impl Fn(u32) -> u32) {
+        buf.write_byte(self.bits << 1 | 1);
+        for word in &self.words {
+            buf.write(&word.to_le_bytes());
+        }
+
+        if self.bits != 0 {
+            buf.write_zigzag32(self.palette.len() as i32);
+        }
+        for value in &self.palette {
+            buf.write_zigzag32(network_id(*value) as i32);
+        }
+    }
+
+    pub fn encode_disk(&self, buf: &mut MsgBuffer, write_value: impl Fn(&mut MsgBuffer, u32)) {
+        buf.write_byte(self.bits << 1);
+        for word in &self.words {
+            buf.write(&word.to_le_bytes());
+        }
+
+        if self.bits != 0 {
+            buf.write(&(self.palette.len() as u32).to_le_bytes());
+        }
+        for value in &self.palette {
+            write_value(buf, *value);
+        }
+    }
+
+    /// From disk, `None` if it's REPEAT_PREVIOUS_DISK
+    pub fn decode_disk(
+        buf: &mut MsgBuffer,
+        mut read_value: impl FnMut(&mut MsgBuffer) -> Result<u32, DecodeError>,
+    ) -> Result<Option<Self>, DecodeError> {
+        let bits = buf.read_byte()? >> 1;
+        if bits == REPEAT_PREVIOUS_DISK >> 1 {
+            return Ok(None);
+        }
+        if !SIZES.contains(&bits) {
+            return Err(DecodeError::Invalid(format!(
+                "paletted storage with {bits} bits per index"
+            )));
+        }
+
+        let words = (0..Self::word_count(bits))
+            .map(|_| Ok(u32::from_le_bytes(buf.read_array()?)))
+            .collect::<Result<_, DecodeError>>()?;
+        let count = match bits {
+            0 => 1,
+            _ => u32::from_le_bytes(buf.read_array()?) as usize,
+        };
+        if count == 0 || count > 4096 {
+            return Err(DecodeError::Invalid(format!(
+                "paletted storage with {count} values"
+            )));
+        }
+
+        let storage = Self {
+            bits,
+            words,
+            palette: (0..count)
+                .map(|_| read_value(buf))
+                .collect::<Result<_, _>>()?,
+        };
+        if (0..4096).any(|i| storage.palette_index(i) >= count) {
+            return Err(DecodeError::Invalid(
+                "paletted storage indexes past its palette".to_string(),
+            ));
+        }
+
+        Ok(Some(storage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_value() {
+        let mut storage = PalettedStorage::new(7);
+        assert_eq!(storage.get(3, 4, 5), 7);
+        assert_eq!(storage.uniform(), Some(7));
+
+        // the same value again doesn't need any words
+        storage.set(3, 4, 5, 7);
+        assert_eq!(storage.bits, 0);
+        assert!(storage.words.is_empty());
+
+        let mut buf = MsgBuffer::new();
+        storage.encode_network(&mut buf, |v| v);
+        // header, then the value as a zigzag varint, no count
+        assert_eq!(buf.get_bytes().as_slice(), [0x01, 14]);
+    }
+
+    #[test]
+    fn resizes() {
+        let mut storage = PalettedStorage::new(0);
+        let position = |i: u32| ((i % 16) as u8, (i / 256 % 16) as u8, (i / 16 % 16) as u8);
+
+        // every size up to 16 bits
+        let mut bits = vec![];
+        for value in 1..=300 {
+            let (x, y, z) = position(value);
+            storage.set(x, y, z, value);
+            if bits.last() != Some(&storage.bits) {
+                bits.push(storage.bits);
+            }
+        }
+        assert_eq!(bits, [1, 2, 3, 4, 5, 6, 8, 16]);
+        assert_eq!(storage.words.len(), 2048);
+
+        // nothing moved while it grew
+        assert_eq!(storage.get(0, 0, 0), 0);
+        for value in 1..=300 {
+            let (x, y, z) = position(value);
+            assert_eq!(storage.get(x, y, z), value);
+        }
+        assert_eq!(storage.get(15, 15, 15), 0);
+        assert_eq!(storage.uniform(), None);
+
+        // and back through the disk format
+        let mut buf = MsgBuffer::new();
+        storage.encode_disk(&mut buf, |buf, v| buf.write(&v.to_le_bytes()));
+        let mut buf = MsgBuffer::from(buf.get_bytes().to_vec());
+        let read =
+            PalettedStorage::decode_disk(&mut buf, |buf| Ok(u32::from_le_bytes(buf.read_array()?)));
+        assert_eq!(read.unwrap(), Some(storage));
+    }
+
+    #[test]
+    fn repack_drops_unused() {
+        let mut storage = PalettedStorage::new(0);
+        storage.set(0, 0, 0, 1);
+        storage.set(0, 0, 0, 2);
+        // repacking orders the palette by where values first show up
+        assert_eq!(storage.palette(), [1, 0, 2]);
+
+        // 1's not used any more, so a 4th value still fits in 2 bits
+        storage.set(1, 0, 0, 3);
+        storage.set(2, 0, 0, 4);
+        assert_eq!(storage.bits, 2);
+        assert_eq!(storage.palette(), [2, 0, 3, 4]);
+        assert_eq!(storage.get(0, 0, 0), 2);
+        assert_eq!(storage.get(2, 0, 0), 4);
+    }
+
+    #[test]
+    fn network_encoding() {
+        let mut storage = PalettedStorage::new(1);
+        storage.set(0, 1, 0, 2);
+        storage.set(0, 0, 1, 3);
+        // indices 1 and 16, in 2 bits each, 16 to a word
+        assert_eq!(storage.bits, 2);
+        assert_eq!(storage.words[0], 0b01 << 2);
+        assert_eq!(storage.words[1], 0b10);
+
+        let mut buf = MsgBuffer::new();
+        storage.encode_network(&mut buf, |v| v * 100);
+        let bytes = buf.get_bytes();
+
+        assert_eq!(bytes[0], 2 << 1 | 1);
+        assert_eq!(bytes.len(), 1 + 256 * 4 + 1 + 3 * 2);
+        // words are little endian
+        assert_eq!(bytes[1..9], [4, 0, 0, 0, 2, 0, 0, 0]);
+        // then the count and the values, as zigzag varints
+        let palette = &bytes[1 + 256 * 4..];
+        assert_eq!(palette, [6, 200, 1, 144, 3, 216, 4]);
+
+        // 3 bits is 10 to a word, the last 2 bits are wasted
+        let mut storage = PalettedStorage::new(0);
+        for value in 1..5 {
+            storage.set(0, value as u8, 0, value);
+        }
+        storage.set(0, 10, 0, 5);
+        assert_eq!(storage.bits, 3);
+        assert_eq!(storage.words.len(), 410);
+        let five = storage.palette().iter().position(|v| *v == 5).unwrap();
+        assert_eq!(storage.words[1], five as u32);
+        assert_eq!(storage.words[0] >> 30, 0);
+    }
+}
@@ -0,0 +1,188 @@
+/// game/chunk/sub_chunk.rs
+/// =======================
+///
+/// A 16x16x16 section of a chunk. Blocks are stored in layers, one
+/// paletted storage each: the first layer is the block itself and the
+/// second is what's "inside" it, which is how waterlogging works (a
+/// fence in layer 0, water in layer 1). Most sub chunks only ever have
+/// the one layer.
+///
+/// Network format (version 9):
+///
+///     u8    version (9)
+///     u8    layer count
+///     i8    y index of the sub chunk (-4 is the bottom of the overworld)
+///     ...   a paletted storage per layer
+///
+/// It's the same on disk, with the storages in their disk form (block
+/// states as little endian NBT). Older worlds can have version 8, which
+/// is the same minus the y index, or version 1, a single layer and no
+/// layer count.
+///
+/// Reference: https://github.com/df-mc/dragonfly (server/world/chunk/sub_chunk.go)
+use super::storage::PalettedStorage;
+use crate::game::block::BlockPalette;
+use crate::nbt::{self, Nbt, NbtFlavour};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+
+const NETWORK_VERSION: u8 = 9;
+
+#[derive(Debug, Clone)]
+pub struct SubChunk {
+    layers: Vec<PalettedStorage>,
+    air: u32,
+}
+
+impl SubChunk {
+    /// All air
+    pub fn new(air: u32) -> Self {
+        Self {
+            layers: vec![PalettedStorage::new(air)],
+            air,
+        }
+    }
+
+    pub fn block(&self, x: u8, y: u8, z: u8, layer: usize) -> u32 {
+        match self.layers.get(layer) {
+            Some(storage) => storage.get(x, y, z),
+            None => self.air,
+        }
+    }
+
+    pub fn set_block(&mut self, x: u8, y: u8, z: u8, layer: usize, block: u32) {
+        if layer >= self.layers.len() {
+            // don't bother making a layer just to put air in it
+            if block == self.air {
+                return;
+            }
+            self.layers
+                .resize(layer + 1, PalettedStorage::new(self.air));
+        }
+
+        self.layers[layer].set(x, y, z, block);
+    }
+
+    pub fn layers(&self) -> &[PalettedStorage] {
+        &self.layers
+    }
+
+    /// Nothing but air, in every layer
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(|l| l.uniform() == Some(self.air))
+    }
+
+    pub fn encode_network(&self, buf: &mut MsgBuffer, y_index: i8, blocks: &BlockPalette) {
+        buf.write_byte(NETWORK_VERSION);
+        buf.write_byte(self.layers.len() as u8);
+        buf.write_byte(y_index as u8);
+
+        for layer in &self.layers {
+            layer.encode_network(buf, |id| blocks.network_id(id));
+        }
+    }
+
+    pub fn encode_disk(&self, buf: &mut MsgBuffer, y_index: i8, blocks: &BlockPalette) {
+        buf.write_byte(NETWORK_VERSION);
+        buf.write_byte(self.layers.len() as u8);
+        buf.write_byte(y_index as u8);
+
+        for layer in &self.layers {
+            layer.encode_disk(buf, |buf, id| {
+                let state = blocks.state(id).expect("runtime ids come from the palette");
+                nbt::write(buf, &Nbt::new(state.to_nbt()), NbtFlavour::LittleEndian);
+            });
+        }
+    }
+
+    /// `read_block` reads a block state and gives back its runtime id
+    pub fn decode_disk(
+        buf: &mut MsgBuffer,
+        air: u32,
+        mut read_block: impl FnMut(&mut MsgBuffer) -> Result<u32, DecodeError>,
+    ) -> Result<Self, DecodeError> {
+        let version = buf.read_byte()?;
+        let layer_count = match version {
+            1 => 1,
+            8 | 9 => buf.read_byte()?,
+            // 0 and 2 to 7 are from before block states
+            other => {
+                return Err(DecodeError::Invalid(format!(
+                    "sub chunk version {other} isn't supported"
+                )))
+            }
+        };
+        if version == 9 {
+            // the key has it too
+            buf.read_byte()?;
+        }
+
+        let mut layers: Vec<PalettedStorage> = (0..layer_count)
+            .map(|_| {
+                // only biomes can repeat the previous storage
+                PalettedStorage::decode_disk(buf, &mut read_block)?.ok_or_else(|| {
+                    DecodeError::Invalid("sub chunk repeats the previous storage".to_string())
+                })
+            })
+            .collect::<Result<_, DecodeError>>()?;
+        if layers.is_empty() {
+            layers.push(PalettedStorage::new(air));
+        }
+
+        Ok(Self { layers, air })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::block::BlockState;
+
+    fn palette() -> BlockPalette {
+        let states = ["minecraft:air", "minecraft:dirt", "minecraft:stone"]
+            .map(|name| BlockState::new(name, Default::default()));
+        BlockPalette::new(states.to_vec(), false)
+    }
+
+    fn decode(data: Vec<u8>, blocks: &BlockPalette) -> Result<SubChunk, DecodeError> {
+        let read_block = |buf: &mut MsgBuffer| {
+            let state = BlockState::from_nbt(&nbt::read(buf, NbtFlavour::LittleEndian)?.tag);
+            Ok(state
+                .and_then(|s| blocks.runtime_id(&s))
+                .unwrap_or(blocks.air))
+        };
+        SubChunk::decode_disk(&mut MsgBuffer::from(data), blocks.air, read_block)
+    }
+
+    #[test]
+    fn disk_round_trip() {
+        let blocks = palette();
+        let dirt = blocks.default_state("dirt").unwrap();
+        let stone = blocks.default_state("stone").unwrap();
+        let mut sub_chunk = SubChunk::new(blocks.air);
+        sub_chunk.set_block(1, 2, 3, 0, dirt);
+        sub_chunk.set_block(15, 15, 15, 0, stone);
+        sub_chunk.set_block(1, 2, 3, 1, stone);
+
+        let mut buf = MsgBuffer::new();
+        sub_chunk.encode_disk(&mut buf, -4, &blocks);
+        let decoded = decode(buf.get_bytes().clone(), &blocks).unwrap();
+
+        assert_eq!(decoded.block(1, 2, 3, 0), dirt);
+        assert_eq!(decoded.block(15, 15, 15, 0), stone);
+        assert_eq!(decoded.block(1, 2, 3, 1), stone);
+        assert_eq!(decoded.block(0, 0, 0, 0), blocks.air);
+    }
+
+    #[test]
+    fn bad_disk_data_is_an_error() {
+        let blocks = palette();
+        // from before block states
+        assert!(decode(vec![0], &blocks).is_err());
+        // 7 bits per index isn't a size
+        assert!(decode(vec![8, 1, 7 << 1], &blocks).is_err());
+        // only biomes repeat the previous storage
+        assert!(decode(vec![8, 1, 0xff], &blocks).is_err());
+        // cut short
+        assert!(decode(vec![8, 1, 1 << 1, 0, 0], &blocks).is_err());
+    }
+}
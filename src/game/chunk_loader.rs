@@ -0,0 +1,538 @@
+/// game/chunk_loader.rs
+/// ====================
+///
+/// Which chunks a player has, and which they're getting next. Chunks go
+/// out from the one the player's standing in, ring by ring, so there's
+/// ground under their feet first and the horizon fills in last:
+///
+///      9 10 11 12 13
+///     24  1  2  3 14
+///     23  8  0  4 15
+///     22  7  6  5 16
+///     21 20 19 18 17
+///
+/// (minus the corners, the radius is a circle). When the player moves
+/// into another chunk anything now out of range is forgotten, the client
+/// drops those itself based on NetworkChunkPublisherUpdate, and whatever
+/// came into range is queued.
+///
+/// Chunks either go out whole, or (sub-chunk-requests) as just their
+/// biomes with the client asking for the sub chunks it wants afterwards
+/// with SubChunkRequest, which is less to send for a mostly empty world.
+///
+/// Reference: https://github.com/pmmp/PocketMine-MP (src/player/ChunkSelector.php)
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+use super::blob_cache::BlobCache;
+use super::block::BlockPalette;
+use super::chunk::{Chunk, SubChunkHeightmap};
+use crate::protocol::v622::{
+    BlockCoordinates, LevelChunk, LevelChunkBlobs, NetworkChunkPublisherUpdate,
+    SubChunkEntryWithCachingEntry,
+    SubChunkEntryWithCachingEntryHeightmapType as CachedHeightmapType,
+    SubChunkEntryWithCachingEntryResult as CachedSubChunkResult, SubChunkEntryWithoutCachingEntry,
+    SubChunkEntryWithoutCachingEntryHeightmapType as HeightmapType,
+    SubChunkEntryWithoutCachingEntryResult as SubChunkResult, SubchunkEntries,
+};
+use crate::raknet::objects::MsgBuffer;
+
+/// LevelChunk's sub chunk count when the client should ask for them, up
+/// to a highest one
+const REQUEST_MODE_LIMITED: i32 = -2;
+
+/// How far down the queue `next_batch` looks for chunks that are ready
+const LOOKAHEAD: usize = 32;
+
+pub struct ChunkLoader {
+    center: (i32, i32),
+    radius: i32,
+    /// Already sent
+    loaded: HashSet<(i32, i32)>,
+    queue: VecDeque<(i32, i32)>,
+}
+
+impl ChunkLoader {
+    pub fn new(center: (i32, i32), radius: i32) -> Self {
+        let mut loader = Self {
+            center,
+            radius,
+            loaded: HashSet::new(),
+            queue: VecDeque::new(),
+        };
+        loader.refresh();
+
+        loader
+    }
+
+    pub fn set_radius(&mut self, radius: i32) {
+        self.radius = radius;
+        self.refresh();
+    }
+
+    /// Returns whether the player's in a different chunk now
+    pub fn move_to(&mut self, center: (i32, i32)) -> bool {
+        if center == self.center {
+            return false;
+        }
+
+        self.center = center;
+        self.refresh();
+        true
+    }
+
+    /// Whether the chunk's within the player's view, sent yet or not
+    pub fn in_range(&self, (x, z): (i32, i32)) -> bool {
+        let (dx, dz) = (x - self.center.0, z - self.center.1);
+        dx * dx + dz * dz <= self.radius * self.radius
+    }
+
+    fn refresh(&mut self) {
+        let loaded = std::mem::take(&mut self.loaded);
+        self.loaded = loaded.into_iter().filter(|c| self.in_range(*c)).collect();
+
+        self.queue = spiral(self.center, self.radius)
+            .filter(|c| self.in_range(*c) && !self.loaded.contains(c))
+            .collect();
+    }
+
+    /// The next `max` chunks to send that `ready` has, which count as
+    /// loaded from now on. Anything it doesn't have yet (still being
+    /// generated) stays queued. Only the nearest few are asked about, so
+    /// the whole view distance isn't generated at once.
+    pub fn next_batch<T>(
+        &mut self,
+        max: usize,
+        mut ready: impl FnMut((i32, i32)) -> Option<T>,
+    ) -> Vec<T> {
+        let mut batch = vec![];
+        let mut i = 0;
+
+        while batch.len() < max && i < self.queue.len().min(LOOKAHEAD) {
+            match ready(self.queue[i]) {
+                Some(chunk) => {
+                    let position = self.queue.remove(i).unwrap();
+                    self.loaded.insert(position);
+                    batch.push(chunk);
+                }
+                None => i += 1,
+            }
+        }
+
+        batch
+    }
+
+    /// The client's dropped every chunk it had, they're all sent again
+    pub fn forget_all(&mut self) {
+        self.loaded.clear();
+        self.refresh();
+    }
+
+    /// Whether everything within `radius` of the center's been sent
+    pub fn has_around(&self, radius: i32) -> bool {
+        spiral(self.center, radius.min(self.radius))
+            .filter(|c| self.in_range(*c))
+            .all(|c| self.loaded.contains(&c))
+    }
+
+    pub fn is_loaded(&self, chunk: (i32, i32)) -> bool {
+        self.loaded.contains(&chunk)
+    }
+
+    /// Lets the client know which chunks to keep around (and render)
+    pub fn publisher_update(&self, position: (f32, f32, f32)) -> NetworkChunkPublisherUpdate {
+        NetworkChunkPublisherUpdate {
+            coordinates: BlockCoordinates {
+                x: position.0.floor() as i32,
+                y: position.1.floor() as i32,
+                z: position.2.floor() as i32,
+            },
+            radius: self.radius * 16,
+            saved_chunks: vec![],
+        }
+    }
+}
+
+/// Every chunk in a square of `radius` around `center`, ring by ring
+fn spiral(center: (i32, i32), radius: i32) -> impl Iterator<Item = (i32, i32)> {
+    let (x, z) = center;
+
+    std::iter::once(center).chain((1..=radius).flat_map(move |d| {
+        let top = (-d..d).map(move |i| (x + i, z - d));
+        let right = (-d..d).map(move |i| (x + d, z + i));
+        let bottom = (-d..d).map(move |i| (x - i, z + d));
+        let left = (-d..d).map(move |i| (x - d, z - i));
+        top.chain(right).chain(bottom).chain(left)
+    }))
+}
+
+pub fn level_chunk(
+    chunk: &Chunk,
+    blocks: &BlockPalette,
+    sub_chunk_requests: bool,
+    cache: &mut BlobCache,
+) -> LevelChunk {
+    let count = chunk.network_sub_chunk_count();
+    let (sub_chunk_count, highest_subchunk_count) = if sub_chunk_requests {
+        (REQUEST_MODE_LIMITED, Some(count as u16))
+    } else {
+        (count as i32, None)
+    };
+
+    if !cache.enabled {
+        return LevelChunk {
+            x: chunk.x,
+            z: chunk.z,
+            sub_chunk_count,
+            highest_subchunk_count,
+            cache_enabled: false,
+            blobs: None,
+            payload: if sub_chunk_requests {
+                chunk.encode_network_without_sub_chunks()
+            } else {
+                chunk.encode_network(blocks)
+            },
+        };
+    }
+
+    // sub chunks (unless they're requested), then all the biomes
+    let mut hashes = vec![];
+    if !sub_chunk_requests {
+        for y_index in chunk.sections().take(count) {
+            let sub_chunk = chunk.encode_sub_chunk(y_index, blocks).unwrap_or_default();
+            hashes.push(cache.track(sub_chunk));
+        }
+    }
+    let mut biomes = MsgBuffer::new();
+    chunk.encode_biomes(&mut biomes);
+    hashes.push(cache.track(biomes.get_bytes().clone()));
+
+    LevelChunk {
+        x: chunk.x,
+        z: chunk.z,
+        sub_chunk_count,
+        highest_subchunk_count,
+        cache_enabled: true,
+        blobs: Some(LevelChunkBlobs { hashes }),
+        payload: if sub_chunk_requests {
+            // border blocks, block entities are in SubChunk
+            vec![0]
+        } else {
+            chunk.encode_network_without_blobs()
+        },
+    }
+}
+
+/// One sub chunk of a SubChunkRequest: where it is relative to the
+/// origin, the chunk (`None` if the player doesn't have it) and its y index
+pub type SubChunkQuery = ((i8, i8, i8), Option<Arc<Chunk>>, i32);
+
+pub fn sub_chunk_entries(
+    queries: Vec<SubChunkQuery>,
+    blocks: &BlockPalette,
+    cache: &mut BlobCache,
+) -> SubchunkEntries {
+    let answers = queries
+        .into_iter()
+        .map(|(offset, chunk, y_index)| sub_chunk_entry(offset, chunk.as_deref(), y_index, blocks));
+
+    if !cache.enabled {
+        return SubchunkEntries::False(
+            answers
+                .map(|(mut entry, block_entities)| {
+                    entry.payload.extend(block_entities);
+                    entry
+                })
+                .collect(),
+        );
+    }
+
+    SubchunkEntries::True(
+        answers
+            .map(|(entry, block_entities)| {
+                let (payload, blob_id) = match entry.result {
+                    SubChunkResult::SuccessAllAir => (None, 0),
+                    SubChunkResult::Success => (Some(block_entities), cache.track(entry.payload)),
+                    _ => (Some(vec![]), 0),
+                };
+
+                SubChunkEntryWithCachingEntry {
+                    dx: entry.dx,
+                    dy: entry.dy,
+                    dz: entry.dz,
+                    result: CachedSubChunkResult::from_value(entry.result.value()),
+                    payload,
+                    heightmap_type: CachedHeightmapType::from_value(entry.heightmap_type.value()),
+                    heightmap: entry.heightmap,
+                    blob_id,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The entry with just the sub chunk in its payload, and the block
+/// entities in it separately
+fn sub_chunk_entry(
+    offset: (i8, i8, i8),
+    chunk: Option<&Chunk>,
+    y_index: i32,
+    blocks: &BlockPalette,
+) -> (SubChunkEntryWithoutCachingEntry, Vec<u8>) {
+    let mut entry = SubChunkEntryWithoutCachingEntry {
+        dx: offset.0,
+        dy: offset.1,
+        dz: offset.2,
+        result: SubChunkResult::ChunkNotFound,
+        payload: vec![],
+        heightmap_type: HeightmapType::NoData,
+        heightmap: None,
+    };
+
+    let Some(chunk) = chunk else {
+        return (entry, vec![]);
+    };
+    let Some(sub_chunk) = chunk.sub_chunk(y_index) else {
+        entry.result = SubChunkResult::YIndexOutOfBounds;
+        return (entry, vec![]);
+    };
+
+    (entry.heightmap_type, entry.heightmap) = match chunk.sub_chunk_heightmap(y_index) {
+        SubChunkHeightmap::TooLow => (HeightmapType::TooLow, None),
+        SubChunkHeightmap::TooHigh => (HeightmapType::TooHigh, None),
+        SubChunkHeightmap::Data(heights) => (
+            HeightmapType::HasData,
+            Some(heights.into_iter().map(|y| y as u8).collect()),
+        ),
+    };
+
+    let block_entities = chunk.encode_sub_chunk_block_entities(y_index);
+    if sub_chunk.is_empty() && block_entities.is_empty() {
+        entry.result = SubChunkResult::SuccessAllAir;
+    } else {
+        entry.result = SubChunkResult::Success;
+        entry.payload = chunk.encode_sub_chunk(y_index, blocks).unwrap_or_default();
+    }
+
+    (entry, block_entities)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game::dimension::Dimension;
+
+    #[test]
+    fn spiral_order() {
+        let order: Vec<_> = spiral((0, 0), 1).collect();
+        assert_eq!(
+            order,
+            [
+                (0, 0),
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+                (0, 1),
+                (-1, 1),
+                (-1, 0),
+            ]
+        );
+
+        // every chunk in the square once, around wherever the center is
+        let mut square: Vec<_> = spiral((10, -5), 3).collect();
+        assert_eq!(square.len(), 49);
+        square.sort();
+        square.dedup();
+        assert_eq!(square.len(), 49);
+        assert!(square
+            .iter()
+            .all(|(x, z)| (7..=13).contains(x) && (-8..=-2).contains(z)));
+    }
+
+    #[test]
+    fn radius() {
+        let loader = ChunkLoader::new((0, 0), 2);
+        // the square minus the corners
+        assert_eq!(loader.queue.len(), 13);
+        assert!(loader.in_range((2, 0)) && loader.in_range((-1, 1)));
+        assert!(!loader.in_range((2, 1)) && !loader.in_range((-2, -2)));
+        assert!(loader.queue.iter().all(|c| loader.in_range(*c)));
+        // nearest first
+        assert_eq!(loader.queue[0], (0, 0));
+        assert!(loader
+            .queue
+            .iter()
+            .take(9)
+            .all(|(x, z)| x.abs() <= 1 && z.abs() <= 1));
+    }
+
+    #[test]
+    fn next_batch() {
+        let mut loader = ChunkLoader::new((0, 0), 2);
+
+        // no more than asked for a tick
+        let batch = loader.next_batch(4, Some);
+        assert_eq!(batch, [(0, 0), (-1, -1), (0, -1), (1, -1)]);
+        assert!(loader.is_loaded((0, 0)));
+        assert_eq!(loader.queue.len(), 9);
+
+        // ones that aren't ready are skipped, not dropped
+        let batch = loader.next_batch(2, |c| (c != (1, 0)).then_some(c));
+        assert_eq!(batch, [(1, 1), (0, 1)]);
+        assert_eq!(loader.queue[0], (1, 0));
+
+        let batch = loader.next_batch(100, Some);
+        assert_eq!(batch.len(), 7);
+        assert!(loader.queue.is_empty());
+        assert!(loader.next_batch(100, Some).is_empty());
+    }
+
+    #[test]
+    fn lookahead() {
+        // only the nearest LOOKAHEAD are asked about
+        let mut loader = ChunkLoader::new((0, 0), 8);
+        let mut asked = 0;
+        let batch: Vec<()> = loader.next_batch(10, |_| {
+            asked += 1;
+            None
+        });
+        assert!(batch.is_empty());
+        assert_eq!(asked, LOOKAHEAD);
+    }
+
+    #[test]
+    fn moving() {
+        let mut loader = ChunkLoader::new((0, 0), 2);
+        loader.next_batch(100, Some);
+        assert!(!loader.move_to((0, 0)));
+
+        assert!(loader.move_to((1, 0)));
+        // what's out of range is forgotten, what came in is queued
+        assert!(!loader.is_loaded((-2, 0)) && !loader.is_loaded((-1, 1)));
+        assert!(loader.is_loaded((0, 0)) && loader.is_loaded((2, 0)));
+        let mut queued: Vec<_> = loader.queue.iter().copied().collect();
+        queued.sort();
+        assert_eq!(queued, [(1, -2), (1, 2), (2, -1), (2, 1), (3, 0)]);
+
+        loader.forget_all();
+        assert_eq!(loader.queue.len(), 13);
+        assert_eq!(loader.queue[0], (1, 0));
+    }
+
+    #[test]
+    fn has_around() {
+        let mut loader = ChunkLoader::new((0, 0), 2);
+        assert!(!loader.has_around(0));
+
+        loader.next_batch(1, Some);
+        assert!(loader.has_around(0));
+        assert!(!loader.has_around(1));
+
+        loader.next_batch(8, Some);
+        assert!(loader.has_around(1));
+        assert!(!loader.has_around(2));
+
+        // past the view distance is as far as it goes
+        loader.next_batch(100, Some);
+        assert!(loader.has_around(2));
+        assert!(loader.has_around(10));
+    }
+
+    /// Stone all over y 0, nothing else
+    fn chunk(blocks: &BlockPalette) -> Chunk {
+        let dimension = Dimension::Overworld;
+        let mut chunk = Chunk::new(
+            0,
+            0,
+            dimension.sections(),
+            blocks.air,
+            dimension.default_biome(),
+        );
+        let stone = blocks.default_state("stone").unwrap();
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block(x, 0, z, stone);
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn sub_chunk_entry_results() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let chunk = chunk(&blocks);
+        let entry = |chunk, y_index| sub_chunk_entry((1, 2, 3), chunk, y_index, &blocks).0;
+
+        let missing = entry(None, 0);
+        assert_eq!((missing.dx, missing.dy, missing.dz), (1, 2, 3));
+        assert_eq!(missing.result, SubChunkResult::ChunkNotFound);
+
+        let out_of_range = entry(Some(&chunk), 20);
+        assert_eq!(out_of_range.result, SubChunkResult::YIndexOutOfBounds);
+        assert!(out_of_range.payload.is_empty());
+        assert_eq!(
+            entry(Some(&chunk), -5).result,
+            SubChunkResult::YIndexOutOfBounds
+        );
+
+        let stone = entry(Some(&chunk), 0);
+        assert_eq!(stone.result, SubChunkResult::Success);
+        assert_eq!(stone.payload, chunk.encode_sub_chunk(0, &blocks).unwrap());
+        // the highest block's at the bottom of it in every column
+        assert_eq!(stone.heightmap_type, HeightmapType::HasData);
+        assert_eq!(stone.heightmap, Some(vec![0; 256]));
+
+        let sky = entry(Some(&chunk), 1);
+        assert_eq!(sky.result, SubChunkResult::SuccessAllAir);
+        assert!(sky.payload.is_empty());
+        assert_eq!(sky.heightmap_type, HeightmapType::TooLow);
+        assert_eq!(sky.heightmap, None);
+
+        let underground = entry(Some(&chunk), -1);
+        assert_eq!(underground.result, SubChunkResult::SuccessAllAir);
+        assert_eq!(underground.heightmap_type, HeightmapType::TooHigh);
+    }
+
+    #[test]
+    fn sub_chunk_entries_cached() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let chunk = Arc::new(chunk(&blocks));
+        let queries = || -> Vec<SubChunkQuery> {
+            vec![
+                ((0, 0, 0), Some(chunk.clone()), 0),
+                ((0, 1, 0), Some(chunk.clone()), 1),
+                ((0, 2, 0), None, 2),
+            ]
+        };
+
+        let mut cache = BlobCache::default();
+        let SubchunkEntries::False(entries) = sub_chunk_entries(queries(), &blocks, &mut cache)
+        else {
+            panic!("cached without a cache");
+        };
+        assert_eq!(entries.len(), 3);
+
+        cache.enabled = true;
+        let SubchunkEntries::True(entries) = sub_chunk_entries(queries(), &blocks, &mut cache)
+        else {
+            panic!("not cached");
+        };
+        let sub_chunk = chunk.encode_sub_chunk(0, &blocks).unwrap();
+        assert_eq!(entries[0].result, CachedSubChunkResult::Success);
+        assert_eq!(entries[0].blob_id, BlobCache::hash(&sub_chunk));
+        // no block entities
+        assert_eq!(entries[0].payload, Some(vec![]));
+        assert_eq!(entries[0].heightmap_type, CachedHeightmapType::HasData);
+
+        assert_eq!(entries[1].result, CachedSubChunkResult::SuccessAllAir);
+        assert_eq!((entries[1].payload.as_ref(), entries[1].blob_id), (None, 0));
+        assert_eq!(entries[1].heightmap_type, CachedHeightmapType::TooLow);
+
+        assert_eq!(entries[2].result, CachedSubChunkResult::ChunkNotFound);
+        assert_eq!(entries[2].blob_id, 0);
+    }
+}
@@ -0,0 +1,131 @@
+/// game/command.rs
+/// ===============
+///
+/// What players can type after a slash. There's no AvailableCommands yet
+/// so nothing autocompletes, but the client sends whatever's typed as a
+/// CommandRequest anyway:
+///
+///     /tp <x> <y> <z>                               (~ for relative)
+///     /execute in <dimension> run tp <x> <y> <z>    (overworld, nether, the_end)
+///
+/// Only with allow-cheats, like vanilla.
+use super::dimension::Dimension;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// To another dimension too, if there's one
+    Teleport {
+        dimension: Option<Dimension>,
+        position: [Coordinate; 3],
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Coordinate {
+    Absolute(f32),
+    /// `~` or `~5`, from where the player is
+    Relative(f32),
+}
+
+impl Coordinate {
+    fn parse(value: &str) -> Option<Self> {
+        match value.strip_prefix('~') {
+            Some("") => Some(Coordinate::Relative(0.0)),
+            Some(offset) => offset.parse().ok().map(Coordinate::Relative),
+            None => value.parse().ok().map(Coordinate::Absolute),
+        }
+        .filter(|c| c.value().is_finite())
+    }
+
+    fn value(self) -> f32 {
+        match self {
+            Coordinate::Absolute(value) | Coordinate::Relative(value) => value,
+        }
+    }
+
+    pub fn resolve(self, from: f32) -> f32 {
+        match self {
+            Coordinate::Absolute(value) => value,
+            Coordinate::Relative(offset) => from + offset,
+        }
+    }
+}
+
+/// The command as typed, slash or not. The error's for the player.
+pub fn parse(command: &str) -> Result<Command, String> {
+    let command = command.trim().trim_start_matches('/');
+    let words: Vec<&str> = command.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["tp" | "teleport", rest @ ..] => teleport(None, rest),
+        ["execute", "in", dimension, "run", "tp" | "teleport", rest @ ..] => {
+            let dimension = Dimension::from_name(dimension)
+                .ok_or_else(|| format!("Unknown dimension: {dimension}"))?;
+            teleport(Some(dimension), rest)
+        }
+        ["execute", ..] => Err("Only /execute in <dimension> run tp works".to_string()),
+        [name, ..] => Err(format!("Unknown command: {name}")),
+        [] => Err("Empty command".to_string()),
+    }
+}
+
+fn teleport(dimension: Option<Dimension>, args: &[&str]) -> Result<Command, String> {
+    let usage = || "Usage: /tp <x> <y> <z>".to_string();
+    let [x, y, z] = args else {
+        return Err(usage());
+    };
+
+    let mut position = [Coordinate::Relative(0.0); 3];
+    for (coordinate, value) in position.iter_mut().zip([x, y, z]) {
+        *coordinate = Coordinate::parse(value).ok_or_else(usage)?;
+    }
+    Ok(Command::Teleport {
+        dimension,
+        position,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn teleport() {
+        assert_eq!(
+            parse("/tp 1 ~ ~-2.5"),
+            Ok(Command::Teleport {
+                dimension: None,
+                position: [
+                    Coordinate::Absolute(1.0),
+                    Coordinate::Relative(0.0),
+                    Coordinate::Relative(-2.5),
+                ],
+            })
+        );
+        assert_eq!(
+            parse("execute in nether run teleport 0 64 0"),
+            Ok(Command::Teleport {
+                dimension: Some(Dimension::Nether),
+                position: [
+                    Coordinate::Absolute(0.0),
+                    Coordinate::Absolute(64.0),
+                    Coordinate::Absolute(0.0),
+                ],
+            })
+        );
+
+        assert!(parse("/tp 1 2").is_err());
+        assert!(parse("/tp 1 2 x").is_err());
+        assert!(parse("/tp 1 2 NaN").is_err());
+        assert!(parse("/tp ~inf 2 3").is_err());
+        assert!(parse("/execute in moon run tp 0 0 0").is_err());
+        assert!(parse("/give @s diamond").is_err());
+        assert!(parse("/").is_err());
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(Coordinate::Absolute(3.0).resolve(10.0), 3.0);
+        assert_eq!(Coordinate::Relative(-3.0).resolve(10.0), 7.0);
+    }
+}
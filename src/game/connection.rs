@@ -0,0 +1,1121 @@
+/// game/connection.rs
+/// ==================
+///
+/// A player's side of the game protocol, one per RakNet session. The
+/// session deals with frames, compression and encryption and hands whole
+/// game packets to the game loop (server.rs), which owns the connections
+/// and runs them through here to decide what to do with them.
+///
+/// Which packets make sense depends on how far into joining the player
+/// is, so the connection goes through these states in order:
+///
+///     AwaitingNetworkSettings  RequestNetworkSettings
+///     AwaitingLogin            Login
+///     Encrypting               ClientToServerHandshake
+///     ResourcePacks            ResourcePackClientResponse, ResourcePackChunkRequest
+///     Spawning                 RequestChunkRadius, SetLocalPlayerAsInitialized
+///     Playing                  everything else
+///     Disconnecting            nothing, we're done
+///
+/// Each state has its own handler table, anything that isn't in it is
+/// dropped.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::{debug, info, warn};
+
+use super::blob_cache::BlobCache;
+use super::block::BlockPalette;
+use super::chunk_loader::{self, ChunkLoader, SubChunkQuery};
+use super::command::{self, Command};
+use super::dimension::Dimension;
+use super::item::ItemPalette;
+use super::next_entity_id;
+use super::settings::LevelSettings;
+use super::spawn::{self, SpawnState};
+use super::world::{BlockUpdate, SharedWorld};
+use crate::nbt::Nbt;
+use crate::protocol::batch::CompressionSettings;
+use crate::protocol::encryption::Encryption;
+use crate::protocol::login::{LoginData, LoginError, LoginVerifier};
+use crate::protocol::packets::{decode_header, GamePacket};
+use crate::protocol::v622::packet_name;
+use crate::protocol::v622::{
+    Action, BehaviourPackInfosEntry, BlockCoordinates, ChangeDimension, ChunkRadiusUpdate,
+    ClientCacheBlobStatus, ClientCacheMissResponse, ClientCacheStatus, ClientToServerHandshake,
+    CommandRequest, Disconnect, DisconnectFailReason, LegacyEntityType, Login, MovePlayer,
+    MovePlayerMode, MovePlayerTeleport, MovePlayerTeleportCause, NetworkSettings,
+    NetworkSettingsCompressionAlgorithm, PlayStatus, PlayStatusStatus, PlayerAction,
+    RequestChunkRadius, RequestNetworkSettings, ResourcePackChunkData, ResourcePackChunkRequest,
+    ResourcePackClientResponse, ResourcePackClientResponseResponseStatus, ResourcePackDataInfo,
+    ResourcePackDataInfoPackType, ResourcePackIdVersionsEntry, ResourcePackStack,
+    ResourcePacksInfo, ServerToClientHandshake, SetLocalPlayerAsInitialized, Subchunk,
+    SubchunkRequest, Text, TextType, TextTypeData, TextTypeDataRaw, TexturePackInfosEntry,
+    UpdateBlock, UpdateBlockFlags, Vec3f,
+};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+use crate::raknet::packets::{FromBuffer, ToBuffer};
+use crate::resource_packs::{PackKind, ResourcePack, ResourcePacks, CHUNK_SIZE};
+use crate::server::VoxelServer;
+use crate::status::SharedStatus;
+
+/// What every connection gets a copy of, set up once by the listener
+#[derive(Clone)]
+pub struct ConnectionContext {
+    pub compression: CompressionSettings,
+    pub login_verifier: Arc<LoginVerifier>,
+    pub resource_packs: Arc<ResourcePacks>,
+    pub level: Arc<LevelSettings>,
+    pub blocks: Arc<BlockPalette>,
+    pub items: Arc<ItemPalette>,
+    /// BiomeDefinitionList's NBT
+    pub biomes: Arc<Nbt>,
+    pub world: SharedWorld,
+    pub status: SharedStatus,
+}
+
+/// Chunks sent per connection tick, so a big view distance doesn't
+/// clog up the connection (or the server) all at once
+const CHUNKS_PER_TICK: usize = 8;
+
+/// Chunks (radius) the player needs before they're let in, so they're
+/// not spawned into the void and falling
+const SPAWN_RADIUS: i32 = 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ConnectionState {
+    AwaitingNetworkSettings,
+    AwaitingLogin,
+    Encrypting,
+    ResourcePacks,
+    Spawning,
+    Playing,
+    Disconnecting,
+}
+
+/// Going to another dimension, see `teleport`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DimensionChange {
+    /// The new dimension's chunks are going out, PlayerSpawn's next
+    SendingChunks,
+    /// The client says when it's there
+    WaitingForAck,
+}
+
+type Handler = fn(&mut PlayerConnection, &mut MsgBuffer) -> Result<(), DecodeError>;
+
+/// What a session tells the game loop (server.rs), in the order it
+/// happened
+pub enum Incoming {
+    Connected(SocketAddr),
+    /// One batch, decompressed and decrypted
+    Packets(SocketAddr, Vec<Vec<u8>>),
+    Disconnected(SocketAddr),
+}
+
+/// What the session should send, and what to change about the transport
+/// once it has
+#[derive(Default)]
+pub struct Outgoing {
+    pub packets: Vec<Vec<u8>>,
+    /// Everything after NetworkSettings is compressed
+    pub start_compression: Option<CompressionSettings>,
+    /// and everything after ServerToClientHandshake is encrypted
+    pub start_encryption: Option<Encryption>,
+    /// Once it's all sent, the session's closed
+    pub close: bool,
+}
+
+impl Outgoing {
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+            && self.start_compression.is_none()
+            && self.start_encryption.is_none()
+            && !self.close
+    }
+}
+
+pub struct PlayerConnection {
+    pub addr: SocketAddr,
+    pub state: ConnectionState,
+    handlers: HashMap<u32, Handler>,
+    context: ConnectionContext,
+    outgoing: Outgoing,
+    /// Who's on the other end, once they've logged in
+    pub login: Option<LoginData>,
+    /// Only means something while Spawning
+    pub spawn_state: SpawnState,
+    /// Handed out in StartGame
+    pub entity_id: i64,
+    /// Feet, not eyes
+    pub position: (f32, f32, f32),
+    pub dimension: Dimension,
+    /// Between ChangeDimension and the client saying it's there, whatever
+    /// movement it sends is from before
+    changing_dimension: Option<DimensionChange>,
+    /// Once the client's asked for a chunk radius
+    pub chunks: Option<ChunkLoader>,
+    /// Whole chunks or the client asks for sub chunks, see chunk_loader.rs
+    pub sub_chunk_requests: bool,
+    blob_cache: BlobCache,
+}
+
+impl PlayerConnection {
+    pub fn new(addr: SocketAddr, context: ConnectionContext) -> Self {
+        let state = ConnectionState::AwaitingNetworkSettings;
+
+        Self {
+            addr,
+            state,
+            handlers: Self::handlers_for(state),
+            outgoing: Outgoing::default(),
+            login: None,
+            spawn_state: SpawnState::WaitingForRadius,
+            entity_id: 0,
+            position: (0.0, 0.0, 0.0),
+            dimension: Dimension::Overworld,
+            changing_dimension: None,
+            chunks: None,
+            sub_chunk_requests: context.level.sub_chunk_requests,
+            blob_cache: BlobCache::default(),
+            context,
+        }
+    }
+
+    fn handlers_for(state: ConnectionState) -> HashMap<u32, Handler> {
+        let handlers: Vec<(u32, Handler)> = match state {
+            ConnectionState::AwaitingNetworkSettings => vec![(
+                RequestNetworkSettings::ID,
+                Self::recv_request_network_settings,
+            )],
+            ConnectionState::AwaitingLogin => vec![(Login::ID, Self::recv_login)],
+            ConnectionState::Encrypting => vec![
+                (
+                    ClientToServerHandshake::ID,
+                    Self::recv_client_to_server_handshake,
+                ),
+                (ClientCacheStatus::ID, Self::recv_client_cache_status),
+            ],
+            ConnectionState::ResourcePacks => vec![
+                (
+                    ResourcePackClientResponse::ID,
+                    Self::recv_resource_pack_response,
+                ),
+                (
+                    ResourcePackChunkRequest::ID,
+                    Self::recv_resource_pack_chunk_request,
+                ),
+                (ClientCacheStatus::ID, Self::recv_client_cache_status),
+            ],
+            ConnectionState::Spawning => vec![
+                (RequestChunkRadius::ID, Self::recv_request_chunk_radius),
+                (SubchunkRequest::ID, Self::recv_sub_chunk_request),
+                (
+                    ClientCacheBlobStatus::ID,
+                    Self::recv_client_cache_blob_status,
+                ),
+                (
+                    SetLocalPlayerAsInitialized::ID,
+                    Self::recv_local_player_initialized,
+                ),
+            ],
+            ConnectionState::Playing => vec![
+                (RequestChunkRadius::ID, Self::recv_request_chunk_radius),
+                (MovePlayer::ID, Self::recv_move_player),
+                (PlayerAction::ID, Self::recv_player_action),
+                (CommandRequest::ID, Self::recv_command_request),
+                (SubchunkRequest::ID, Self::recv_sub_chunk_request),
+                (
+                    ClientCacheBlobStatus::ID,
+                    Self::recv_client_cache_blob_status,
+                ),
+            ],
+            ConnectionState::Disconnecting => vec![],
+        };
+
+        handlers.into_iter().collect()
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        if self.state == ConnectionState::Playing && state != ConnectionState::Playing {
+            self.leave();
+        }
+        if state == ConnectionState::Disconnecting {
+            self.outgoing.close = true;
+        }
+
+        self.state = state;
+        self.handlers = Self::handlers_for(state);
+    }
+
+    /// Runs every packet in a batch through the handler for the current
+    /// state (which might change along the way). A packet that doesn't
+    /// decode gets the client kicked, the rest of the batch goes with it.
+    pub fn handle(&mut self, packets: Vec<Vec<u8>>) -> Outgoing {
+        for packet in packets {
+            let result = decode_header(packet).and_then(|(packet_id, mut reader)| {
+                match self.handlers.get(&packet_id).copied() {
+                    Some(handler) => handler(self, &mut reader),
+                    None => {
+                        self.reject(packet_id);
+                        Ok(())
+                    }
+                }
+            });
+
+            if let Err(e) = result {
+                warn!("{} sent a bad packet ({}), kicking them", self.addr, e);
+                self.disconnect(DisconnectFailReason::UnknownPacket, "Bad packet");
+                break;
+            }
+        }
+
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn reject(&self, packet_id: u32) {
+        let name = packet_name(packet_id).unwrap_or("unknown");
+
+        match self.state {
+            ConnectionState::Disconnecting => {}
+            // not everything's implemented, so this is normal for now
+            ConnectionState::Playing => debug!(
+                "Unhandled game packet {} ({:#04x}) from {}",
+                name, packet_id, self.addr
+            ),
+            state => warn!(
+                "{} sent {} ({:#04x}) while {:?}, ignoring it",
+                self.addr, name, packet_id, state
+            ),
+        }
+    }
+
+    /// Whatever the connection wants to send on its own, every game tick
+    pub fn tick(&mut self) -> Outgoing {
+        self.blob_cache.tick();
+        if matches!(
+            self.state,
+            ConnectionState::Spawning | ConnectionState::Playing
+        ) {
+            self.send_chunks();
+        }
+
+        if self.state == ConnectionState::Spawning
+            && self.spawn_state == SpawnState::SendingChunks
+            && self.has_spawn_area()
+        {
+            self.send(PlayStatus {
+                status: PlayStatusStatus::PlayerSpawn,
+            });
+            self.spawn_state = SpawnState::WaitingForInitialized;
+        }
+        if self.changing_dimension == Some(DimensionChange::SendingChunks) && self.has_spawn_area()
+        {
+            self.send(PlayStatus {
+                status: PlayStatusStatus::PlayerSpawn,
+            });
+            self.changing_dimension = Some(DimensionChange::WaitingForAck);
+        }
+
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Whether the ground around the player's been sent, so they can be
+    /// let in (or out of the loading screen)
+    fn has_spawn_area(&self) -> bool {
+        self.chunks
+            .as_ref()
+            .is_some_and(|c| c.has_around(SPAWN_RADIUS))
+    }
+
+    /// The dimension and chunk the player's in, once they're in the world,
+    /// so the chunks around them can be ticked
+    pub fn ticking_center(&self) -> Option<(Dimension, (i32, i32))> {
+        (self.state == ConnectionState::Playing).then(|| (self.dimension, self.chunk_position()))
+    }
+
+    /// Whether the chunk's one the player has or is getting, so it has
+    /// to stay loaded
+    pub fn can_see(&self, dimension: Dimension, chunk: (i32, i32)) -> bool {
+        dimension == self.dimension && self.chunks.as_ref().is_some_and(|c| c.in_range(chunk))
+    }
+
+    /// Changes to chunks the player has, the rest they'll get with the
+    /// chunk
+    pub fn send_block_updates(&mut self, updates: &[BlockUpdate]) {
+        for update in updates {
+            let (x, y, z) = update.position;
+            let has_chunk = self
+                .chunks
+                .as_ref()
+                .is_some_and(|chunks| chunks.is_loaded((x >> 4, z >> 4)));
+            if update.dimension != self.dimension || !has_chunk {
+                continue;
+            }
+
+            self.send(UpdateBlock {
+                position: BlockCoordinates { x, y, z },
+                block_runtime_id: self.context.blocks.network_id(update.block) as i32,
+                flags: UpdateBlockFlags(UpdateBlockFlags::NETWORK),
+                layer: 0,
+            });
+        }
+    }
+
+    fn send<P: GamePacket + ToBuffer>(&mut self, packet: P) {
+        self.outgoing.packets.push(packet.encode());
+    }
+
+    fn disconnect(&mut self, reason: DisconnectFailReason, message: &str) {
+        self.send(Disconnect {
+            reason,
+            hide_disconnect_reason: false,
+            message: message.to_string(),
+        });
+        self.set_state(ConnectionState::Disconnecting);
+    }
+
+    /// The RakNet side of things went away
+    pub fn disconnected(&mut self) {
+        self.set_state(ConnectionState::Disconnecting);
+    }
+
+    /// Off the player list, however they stopped playing
+    fn leave(&mut self) {
+        info!("{} left the game", self.display_name());
+
+        let mut status = self.context.status.write().unwrap();
+        status.online_players -= 1;
+        status.players.retain(|(addr, _)| *addr != self.addr);
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.login.as_ref().map_or("?", |l| l.display_name.as_str())
+    }
+
+    fn recv_request_network_settings(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let request = RequestNetworkSettings::from_buffer(buf)?;
+
+        if request.client_protocol != VoxelServer::PROTOCOL_VERSION as i32 {
+            info!(
+                "{} tried to join with protocol {} (we're on {})",
+                self.addr,
+                request.client_protocol,
+                VoxelServer::PROTOCOL_VERSION
+            );
+
+            self.send(PlayStatus {
+                status: Self::protocol_mismatch(request.client_protocol),
+            });
+            self.set_state(ConnectionState::Disconnecting);
+            return Ok(());
+        }
+
+        let compression = self.context.compression;
+        self.send(NetworkSettings {
+            compression_threshold: compression.threshold,
+            compression_algorithm: NetworkSettingsCompressionAlgorithm::from_value(
+                compression.algorithm.network_id() as i64,
+            ),
+            client_throttle: false,
+            client_throttle_threshold: 0,
+            client_throttle_scalar: 0.0,
+        });
+        self.outgoing.start_compression = Some(self.context.compression);
+        self.set_state(ConnectionState::AwaitingLogin);
+
+        Ok(())
+    }
+
+    /// Which side the client should blame for not being able to join
+    fn protocol_mismatch(client_protocol: i32) -> PlayStatusStatus {
+        if client_protocol < VoxelServer::PROTOCOL_VERSION as i32 {
+            PlayStatusStatus::FailedClient
+        } else {
+            // protocol.json's name for the server being outdated
+            PlayStatusStatus::FailedSpawn
+        }
+    }
+
+    fn recv_login(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let login = Login::from_buffer(buf)?;
+
+        if login.protocol_version != VoxelServer::PROTOCOL_VERSION as i32 {
+            self.send(PlayStatus {
+                status: Self::protocol_mismatch(login.protocol_version),
+            });
+            self.set_state(ConnectionState::Disconnecting);
+            return Ok(());
+        }
+
+        let result = self
+            .context
+            .login_verifier
+            .verify(&login.tokens.identity, &login.tokens.client)
+            .and_then(|data| {
+                let (token, encryption) = Encryption::handshake(&data.identity_public_key)?;
+                Ok((data, token, encryption))
+            });
+
+        match result {
+            Ok((data, token, encryption)) => {
+                info!(
+                    "{} logged in from {} (xuid: {}, authenticated: {})",
+                    data.display_name,
+                    self.addr,
+                    if data.xuid.is_empty() {
+                        "none"
+                    } else {
+                        &data.xuid
+                    },
+                    data.authenticated
+                );
+                self.login = Some(data);
+
+                // LoginSuccess once the client's done its side of the handshake
+                self.send(ServerToClientHandshake { token });
+                self.outgoing.start_encryption = Some(encryption);
+                self.set_state(ConnectionState::Encrypting);
+            }
+            Err(e) => {
+                warn!("{} failed to log in: {}", self.addr, e);
+                let reason = match e {
+                    LoginError::NotAuthenticated => DisconnectFailReason::NotAuthenticated,
+                    _ => DisconnectFailReason::InvalidPlayer,
+                };
+                self.disconnect(reason, &e.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn recv_client_to_server_handshake(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        ClientToServerHandshake::from_buffer(buf)?;
+
+        self.send(PlayStatus {
+            status: PlayStatusStatus::LoginSuccess,
+        });
+        let info = self.resource_packs_info();
+        self.send(info);
+        self.set_state(ConnectionState::ResourcePacks);
+
+        Ok(())
+    }
+
+    fn resource_packs_info(&self) -> ResourcePacksInfo {
+        let packs = &self.context.resource_packs;
+
+        ResourcePacksInfo {
+            must_accept: packs.required,
+            has_scripts: packs.packs.iter().any(|p| p.has_scripts),
+            force_server_packs: false,
+            behaviour_packs: packs
+                .of_kind(PackKind::Behaviour)
+                .map(|p| BehaviourPackInfosEntry {
+                    uuid: p.uuid.clone(),
+                    version: p.version.clone(),
+                    size: p.size(),
+                    content_key: String::new(),
+                    sub_pack_name: String::new(),
+                    content_identity: String::new(),
+                    has_scripts: p.has_scripts,
+                })
+                .collect(),
+            texture_packs: packs
+                .of_kind(PackKind::Resources)
+                .map(|p| TexturePackInfosEntry {
+                    uuid: p.uuid.clone(),
+                    version: p.version.clone(),
+                    size: p.size(),
+                    content_key: String::new(),
+                    sub_pack_name: String::new(),
+                    content_identity: String::new(),
+                    has_scripts: p.has_scripts,
+                    rtx_enabled: false,
+                })
+                .collect(),
+            resource_pack_links: vec![],
+        }
+    }
+
+    fn recv_resource_pack_response(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let response = ResourcePackClientResponse::from_buffer(buf)?;
+        let packs = self.context.resource_packs.clone();
+
+        match response.response_status {
+            ResourcePackClientResponseResponseStatus::SendPacks => {
+                for id in response.resourcepackids {
+                    let Some(pack) = packs.get(&id) else {
+                        warn!("{} asked for a pack we don't have ({})", self.addr, id);
+                        continue;
+                    };
+
+                    self.send(ResourcePackDataInfo {
+                        pack_id: pack.id(),
+                        max_chunk_size: CHUNK_SIZE as u32,
+                        chunk_count: pack.chunk_count(),
+                        size: pack.size(),
+                        hash: pack.hash.to_vec(),
+                        is_premium: false,
+                        pack_type: match pack.kind {
+                            PackKind::Resources => ResourcePackDataInfoPackType::Resources,
+                            PackKind::Behaviour => ResourcePackDataInfoPackType::Behavior,
+                        },
+                    });
+                }
+            }
+            ResourcePackClientResponseResponseStatus::HaveAllPacks => {
+                let stack_entry = |p: &ResourcePack| ResourcePackIdVersionsEntry {
+                    uuid: p.uuid.clone(),
+                    version: p.version.clone(),
+                    name: String::new(),
+                };
+
+                self.send(ResourcePackStack {
+                    must_accept: packs.required,
+                    behavior_packs: packs
+                        .of_kind(PackKind::Behaviour)
+                        .map(stack_entry)
+                        .collect(),
+                    resource_packs: packs
+                        .of_kind(PackKind::Resources)
+                        .map(stack_entry)
+                        .collect(),
+                    game_version: VoxelServer::VERSION.to_string(),
+                    experiments: vec![],
+                    experiments_previously_used: false,
+                });
+            }
+            ResourcePackClientResponseResponseStatus::Refused => {
+                // the client only gets to refuse when they're required,
+                // otherwise it just says it has them all
+                self.disconnect(
+                    DisconnectFailReason::ResourcePackProblem,
+                    "You must accept resource packs to join this server.",
+                );
+            }
+            ResourcePackClientResponseResponseStatus::Completed => {
+                self.start_game();
+            }
+            status => warn!(
+                "Unexpected resource pack response {:?} from {}",
+                status, self.addr
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn recv_resource_pack_chunk_request(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let request = ResourcePackChunkRequest::from_buffer(buf)?;
+        let packs = self.context.resource_packs.clone();
+        let chunk = packs
+            .get(&request.pack_id)
+            .and_then(|pack| Some((pack, pack.chunk(request.chunk_index)?)));
+
+        match chunk {
+            Some((pack, payload)) => self.send(ResourcePackChunkData {
+                pack_id: pack.id(),
+                chunk_index: request.chunk_index,
+                progress: request.chunk_index as u64 * CHUNK_SIZE as u64,
+                payload: payload.to_vec(),
+            }),
+            None => warn!(
+                "{} asked for chunk {} of {}, which doesn't exist",
+                self.addr, request.chunk_index, request.pack_id
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn start_game(&mut self) {
+        self.entity_id = next_entity_id();
+        let (spawn, current_tick) = {
+            let world = self.context.world.read().unwrap();
+            (world.spawn, world.current_tick)
+        };
+        let (x, y, z) = spawn;
+        self.position = (x as f32 + 0.5, y as f32, z as f32 + 0.5);
+        self.send(spawn::start_game(
+            &self.context.level,
+            &self.context.blocks,
+            &self.context.items,
+            self.entity_id,
+            spawn,
+            current_tick,
+        ));
+        self.send(spawn::biome_definitions(&self.context.biomes));
+        self.send(spawn::entity_identifiers());
+        self.send(spawn::creative_content(
+            &self.context.items,
+            &self.context.blocks,
+        ));
+
+        self.spawn_state = SpawnState::WaitingForRadius;
+        self.set_state(ConnectionState::Spawning);
+    }
+
+    fn recv_request_chunk_radius(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let request = RequestChunkRadius::from_buffer(buf)?;
+        let radius = request
+            .chunk_radius
+            .clamp(1, self.context.level.view_distance);
+
+        self.send(ChunkRadiusUpdate {
+            chunk_radius: radius,
+        });
+
+        let center = self.chunk_position();
+        let chunks = self
+            .chunks
+            .get_or_insert_with(|| ChunkLoader::new(center, radius));
+        chunks.set_radius(radius);
+        let update = chunks.publisher_update(self.position);
+        self.send(update);
+
+        // PlayerSpawn once the ground's there, see tick
+        if self.state == ConnectionState::Spawning
+            && self.spawn_state == SpawnState::WaitingForRadius
+        {
+            self.spawn_state = SpawnState::SendingChunks;
+        }
+
+        Ok(())
+    }
+
+    fn recv_move_player(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let movement = MovePlayer::from_buffer(buf)?;
+        if self.changing_dimension.is_some() {
+            return Ok(());
+        }
+        // the position's the eyes
+        self.position = (
+            movement.position.x,
+            movement.position.y - 1.62,
+            movement.position.z,
+        );
+
+        let center = self.chunk_position();
+        if let Some(chunks) = &mut self.chunks {
+            if chunks.move_to(center) {
+                let update = chunks.publisher_update(self.position);
+                self.send(update);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn recv_player_action(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let action = PlayerAction::from_buffer(buf)?;
+
+        if action.action == Action::DimensionChangeAck
+            && self.changing_dimension == Some(DimensionChange::WaitingForAck)
+        {
+            debug!("{} is in {}", self.display_name(), self.dimension);
+            self.changing_dimension = None;
+        }
+
+        Ok(())
+    }
+
+    fn recv_command_request(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let request = CommandRequest::from_buffer(buf)?;
+        if !self.context.level.allow_cheats {
+            self.tell("Commands need allow-cheats");
+            return Ok(());
+        }
+
+        match command::parse(&request.command) {
+            Ok(Command::Teleport {
+                dimension,
+                position: [x, y, z],
+            }) => {
+                if self.changing_dimension.is_some() {
+                    self.tell("Still changing dimension");
+                    return Ok(());
+                }
+                let (from_x, from_y, from_z) = self.position;
+                self.teleport(
+                    dimension.unwrap_or(self.dimension),
+                    (x.resolve(from_x), y.resolve(from_y), z.resolve(from_z)),
+                );
+            }
+            Err(e) => self.tell(&e),
+        }
+
+        Ok(())
+    }
+
+    /// A line in the player's chat
+    fn tell(&mut self, message: &str) {
+        self.send(Text {
+            r#type: TextType::Raw,
+            needs_translation: false,
+            type_data: TextTypeData::Raw(TextTypeDataRaw {
+                message: message.to_string(),
+            }),
+            xuid: String::new(),
+            platform_chat_id: String::new(),
+        });
+    }
+
+    /// Moves the player, to another dimension too (without a portal), see
+    /// command.rs. Going to another dimension the client drops every chunk
+    /// it has and shows a loading screen until it's got the chunks around
+    /// it and PlayerSpawn (see `tick`), then acknowledges with PlayerAction.
+    pub fn teleport(&mut self, dimension: Dimension, position: (f32, f32, f32)) {
+        self.position = position;
+        let eyes = Vec3f {
+            x: position.0,
+            y: position.1 + 1.62,
+            z: position.2,
+        };
+
+        if dimension == self.dimension {
+            self.send(MovePlayer {
+                runtime_id: self.entity_id as i32,
+                position: eyes,
+                pitch: 0.0,
+                yaw: 0.0,
+                head_yaw: 0.0,
+                mode: MovePlayerMode::Teleport,
+                on_ground: false,
+                ridden_runtime_id: 0,
+                teleport: Some(MovePlayerTeleport {
+                    cause: MovePlayerTeleportCause::Command,
+                    source_entity_type: LegacyEntityType::from_value(0),
+                }),
+                tick: 0,
+            });
+        } else {
+            info!("{} went to {}", self.display_name(), dimension);
+            self.dimension = dimension;
+            self.changing_dimension = Some(DimensionChange::SendingChunks);
+            self.send(ChangeDimension {
+                dimension: dimension.id(),
+                position: eyes,
+                respawn: false,
+            });
+        }
+
+        let center = self.chunk_position();
+        if let Some(chunks) = &mut self.chunks {
+            chunks.move_to(center);
+            if self.changing_dimension.is_some() {
+                chunks.forget_all();
+            }
+            let update = chunks.publisher_update(self.position);
+            self.send(update);
+        }
+    }
+
+    fn recv_sub_chunk_request(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let request = SubchunkRequest::from_buffer(buf)?;
+        let origin = &request.origin;
+
+        let queries: Vec<SubChunkQuery> = {
+            let mut world = self.context.world.write().unwrap();
+            request
+                .requests
+                .iter()
+                .map(|offset| {
+                    let (x, z) = (origin.x + offset.dx as i32, origin.z + offset.dz as i32);
+                    let loaded = request.dimension == self.dimension.id()
+                        && self.chunks.as_ref().is_some_and(|c| c.is_loaded((x, z)));
+
+                    (
+                        (offset.dx, offset.dy, offset.dz),
+                        loaded.then(|| world.chunk(self.dimension, x, z)).flatten(),
+                        origin.y + offset.dy as i32,
+                    )
+                })
+                .collect()
+        };
+
+        let entries =
+            chunk_loader::sub_chunk_entries(queries, &self.context.blocks, &mut self.blob_cache);
+        self.send(Subchunk {
+            cache_enabled: self.blob_cache.enabled,
+            dimension: request.dimension,
+            origin: request.origin,
+            entries,
+        });
+
+        Ok(())
+    }
+
+    fn recv_client_cache_status(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let status = ClientCacheStatus::from_buffer(buf)?;
+        self.blob_cache.enabled = status.enabled;
+
+        Ok(())
+    }
+
+    fn recv_client_cache_blob_status(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        let status = ClientCacheBlobStatus::from_buffer(buf)?;
+
+        let blobs = self.blob_cache.status(&status.missing);
+        if !blobs.is_empty() {
+            self.send(ClientCacheMissResponse { blobs });
+        }
+
+        Ok(())
+    }
+
+    /// The chunk the player's in
+    fn chunk_position(&self) -> (i32, i32) {
+        (
+            (self.position.0.floor() as i32) >> 4,
+            (self.position.2.floor() as i32) >> 4,
+        )
+    }
+
+    fn send_chunks(&mut self) {
+        let Some(chunks) = &mut self.chunks else {
+            return;
+        };
+        let ready = {
+            let mut world = self.context.world.write().unwrap();
+            let dimension = self.dimension;
+            chunks.next_batch(CHUNKS_PER_TICK, |(x, z)| world.chunk(dimension, x, z))
+        };
+
+        for chunk in ready {
+            let packet = chunk_loader::level_chunk(
+                &chunk,
+                &self.context.blocks,
+                self.sub_chunk_requests,
+                &mut self.blob_cache,
+            );
+            self.send(packet);
+        }
+    }
+
+    fn recv_local_player_initialized(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
+        SetLocalPlayerAsInitialized::from_buffer(buf)?;
+
+        if self.spawn_state != SpawnState::WaitingForInitialized {
+            warn!("{} says it's initialized before spawning", self.addr);
+            return Ok(());
+        }
+
+        info!(
+            "{} spawned (entity id {})",
+            self.display_name(),
+            self.entity_id
+        );
+        self.set_state(ConnectionState::Playing);
+
+        let mut status = self.context.status.write().unwrap();
+        status.online_players += 1;
+        status
+            .players
+            .push((self.addr, self.display_name().to_string()));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::RwLock;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::game::biome;
+    use crate::game::generator::{self, GeneratorPool};
+    use crate::game::level_db::LevelDb;
+    use crate::game::settings::{Difficulty, LevelType};
+    use crate::game::world::World;
+    use crate::leveldb::test_dir;
+    use crate::protocol::v622::{
+        CommandOrigin, CommandOriginPlayerEntityId, CommandOriginType, LevelChunk,
+        NetworkChunkPublisherUpdate,
+    };
+    use crate::raknet::enums::Gamemode;
+    use crate::status::ServerStatus;
+
+    fn context(name: &str, allow_cheats: bool, texturepack_required: bool) -> ConnectionContext {
+        let level = LevelSettings {
+            level_name: name.to_string(),
+            seed: 1,
+            gamemode: Gamemode::Creative,
+            difficulty: Difficulty::Normal,
+            allow_cheats,
+            texturepack_required,
+            view_distance: 8,
+            tick_distance: 4,
+            sub_chunk_requests: false,
+            level_type: LevelType::Void,
+        };
+        let blocks = Arc::new(BlockPalette::load(Path::new("no-such-file"), true));
+        let level_db = LevelDb::open(&test_dir(name), &level).unwrap();
+        let generator = GeneratorPool::new(
+            generator::from_settings(&level.level_type, level.seed, &blocks),
+            2,
+        );
+
+        ConnectionContext {
+            compression: CompressionSettings {
+                algorithm: crate::protocol::batch::CompressionAlgorithm::Zlib,
+                threshold: 256,
+            },
+            login_verifier: Arc::new(LoginVerifier::new(false)),
+            resource_packs: Arc::new(ResourcePacks::load(
+                Path::new("no-such-dir"),
+                texturepack_required,
+            )),
+            world: Arc::new(RwLock::new(World::new(blocks.clone(), level_db, generator))),
+            level: Arc::new(level),
+            blocks,
+            items: Arc::new(ItemPalette::load(Path::new("no-such-file"))),
+            biomes: Arc::new(biome::load_definitions(Path::new("no-such-file"))),
+            status: ServerStatus {
+                server_name: "test".to_string(),
+                level_name: name.to_string(),
+                protocol_version: 622,
+                version: "1.20.40".to_string(),
+                online_players: 0,
+                players: vec![],
+                max_players: 20,
+                gamemode: Gamemode::Creative,
+                port_v4: 19132,
+                port_v6: 19133,
+            }
+            .shared(),
+        }
+    }
+
+    /// In the world at 0, 0 with everything around it already sent
+    fn playing(context: ConnectionContext) -> PlayerConnection {
+        let mut connection = PlayerConnection::new("127.0.0.1:50000".parse().unwrap(), context);
+        connection.set_state(ConnectionState::Playing);
+        connection.position = (0.5, 70.0, 0.5);
+        connection.chunks = Some(ChunkLoader::new((0, 0), 2));
+        connection
+    }
+
+    fn ids(packets: &[Vec<u8>]) -> Vec<u32> {
+        packets
+            .iter()
+            .map(|p| decode_header(p.clone()).unwrap().0)
+            .collect()
+    }
+
+    /// Ticks the world and the connection until `until` shows up
+    fn tick_until(connection: &mut PlayerConnection, until: u32) -> Vec<u32> {
+        let start = Instant::now();
+        let mut sent = vec![];
+        while !sent.contains(&until) {
+            assert!(start.elapsed() < Duration::from_secs(20), "{sent:?}");
+            connection.context.world.write().unwrap().tick(&[], 0);
+            sent.extend(ids(&connection.tick().packets));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        sent
+    }
+
+    fn command(command: &str) -> Vec<u8> {
+        CommandRequest {
+            command: command.to_string(),
+            origin: CommandOrigin {
+                r#type: CommandOriginType::Player,
+                uuid: 0,
+                request_id: String::new(),
+                player_entity_id: CommandOriginPlayerEntityId::Default,
+            },
+            internal: false,
+            version: 0,
+        }
+        .encode()
+    }
+
+    #[test]
+    fn teleport_same_dimension() {
+        let mut connection = playing(context("teleport-same", true, false));
+        connection.teleport(Dimension::Overworld, (100.5, 80.0, -20.5));
+
+        let sent = ids(&std::mem::take(&mut connection.outgoing).packets);
+        assert_eq!(sent, [MovePlayer::ID, NetworkChunkPublisherUpdate::ID]);
+        assert_eq!(connection.chunk_position(), (6, -2));
+        assert_eq!(connection.changing_dimension, None);
+    }
+
+    #[test]
+    fn teleport_to_another_dimension() {
+        let mut connection = playing(context("teleport-dimension", true, false));
+        connection.teleport(Dimension::Nether, (0.5, 70.0, 0.5));
+
+        let sent = ids(&std::mem::take(&mut connection.outgoing).packets);
+        assert_eq!(sent, [ChangeDimension::ID, NetworkChunkPublisherUpdate::ID]);
+        assert_eq!(connection.dimension, Dimension::Nether);
+
+        // the nether's chunks, then PlayerSpawn
+        let sent = tick_until(&mut connection, PlayStatus::ID);
+        let spawn = sent.iter().position(|id| *id == PlayStatus::ID).unwrap();
+        assert!(sent[..spawn].contains(&LevelChunk::ID));
+        assert!(connection.has_spawn_area());
+        assert_eq!(
+            connection.changing_dimension,
+            Some(DimensionChange::WaitingForAck)
+        );
+
+        let ack = PlayerAction {
+            runtime_entity_id: connection.entity_id as u64,
+            action: Action::DimensionChangeAck,
+            position: BlockCoordinates { x: 0, y: 0, z: 0 },
+            result_position: BlockCoordinates { x: 0, y: 0, z: 0 },
+            face: 0,
+        };
+        connection.handle(vec![ack.encode()]);
+        assert_eq!(connection.changing_dimension, None);
+    }
+
+    #[test]
+    fn teleport_command() {
+        let mut connection = playing(context("teleport-command", true, false));
+        connection.handle(vec![command("/tp ~10 ~ 5")]);
+        assert_eq!(connection.position, (10.5, 70.0, 5.0));
+
+        let sent = ids(&connection.handle(vec![command("/tp nowhere")]).packets);
+        assert_eq!(sent, [Text::ID]);
+
+        connection.handle(vec![command("/execute in the_end run tp 0 64 0")]);
+        assert_eq!(connection.dimension, Dimension::End);
+        // not while it's still getting there
+        connection.handle(vec![command("/tp 1 2 3")]);
+        assert_eq!(connection.position, (0.0, 64.0, 0.0));
+    }
+
+    #[test]
+    fn commands_need_cheats() {
+        let mut connection = playing(context("teleport-cheats", false, false));
+        let sent = ids(&connection.handle(vec![command("/tp 1 2 3")]).packets);
+        assert_eq!(sent, [Text::ID]);
+        assert_eq!(connection.position, (0.5, 70.0, 0.5));
+    }
+
+    #[test]
+    fn required_packs_refused() {
+        let mut connection = PlayerConnection::new(
+            "127.0.0.1:50000".parse().unwrap(),
+            context("packs-required", false, true),
+        );
+        connection.set_state(ConnectionState::ResourcePacks);
+        assert!(connection.resource_packs_info().must_accept);
+
+        let refused = ResourcePackClientResponse {
+            response_status: ResourcePackClientResponseResponseStatus::Refused,
+            resourcepackids: vec![],
+        };
+        let outgoing = connection.handle(vec![refused.encode()]);
+        assert_eq!(ids(&outgoing.packets), [Disconnect::ID]);
+        assert!(outgoing.close);
+        assert_eq!(connection.state, ConnectionState::Disconnecting);
+    }
+}
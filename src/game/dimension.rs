@@ -0,0 +1,107 @@
+/// game/dimension.rs
+/// =================
+///
+/// The overworld, the nether and the end. Each is its own set of chunks
+/// with its own height and generator, and a player's in one of them at a
+/// time (they switch with ChangeDimension, see connection.rs).
+///
+///     id  dimension   y
+///     0   overworld   -64 to 319
+///     1   nether      0 to 127
+///     2   end         0 to 255
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    /// What the protocol and level.dat call it
+    pub fn id(self) -> i32 {
+        match self {
+            Dimension::Overworld => 0,
+            Dimension::Nether => 1,
+            Dimension::End => 2,
+        }
+    }
+
+    /// What commands call it, e.g. `/execute in nether`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim_start_matches("minecraft:") {
+            "overworld" => Some(Dimension::Overworld),
+            "nether" | "the_nether" => Some(Dimension::Nether),
+            "the_end" | "end" => Some(Dimension::End),
+            _ => None,
+        }
+    }
+
+    /// Sub chunk y indices
+    pub fn sections(self) -> Range<i32> {
+        match self {
+            Dimension::Overworld => -4..20,
+            Dimension::Nether => 0..8,
+            Dimension::End => 0..16,
+        }
+    }
+
+    /// Plains, hell and the end, until something's generated
+    pub fn default_biome(self) -> u32 {
+        match self {
+            Dimension::Overworld => 1,
+            Dimension::Nether => 8,
+            Dimension::End => 9,
+        }
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dimension::Overworld => write!(f, "the overworld"),
+            Dimension::Nether => write!(f, "the nether"),
+            Dimension::End => write!(f, "the end"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::End];
+
+    #[test]
+    fn ids() {
+        let ids: Vec<i32> = ALL.iter().map(|d| d.id()).collect();
+        assert_eq!(ids, [0, 1, 2]);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            Dimension::from_name("overworld"),
+            Some(Dimension::Overworld)
+        );
+        assert_eq!(
+            Dimension::from_name("minecraft:the_nether"),
+            Some(Dimension::Nether)
+        );
+        assert_eq!(Dimension::from_name("the_end"), Some(Dimension::End));
+        assert_eq!(Dimension::from_name("Nether"), None);
+        assert_eq!(Dimension::from_name("aether"), None);
+    }
+
+    #[test]
+    fn heights() {
+        // in blocks, like the table up top
+        let heights: Vec<(i32, i32)> = ALL
+            .iter()
+            .map(|d| (d.sections().start * 16, d.sections().end * 16 - 1))
+            .collect();
+        assert_eq!(heights, [(-64, 319), (0, 127), (0, 255)]);
+    }
+}
@@ -0,0 +1,60 @@
+/// game/generator/end.rs
+/// =====================
+///
+/// The main island and nothing else: end stone around 0, 0 with a ragged
+/// edge, thickest in the middle and thinning out towards the edge, over
+/// the void.
+use super::noise::fractal;
+use super::{required_block, WorldGenerator};
+use crate::game::block::BlockPalette;
+use crate::game::chunk::Chunk;
+
+/// In blocks, before the edge is made ragged
+const ISLAND_RADIUS: f64 = 100.0;
+const EDGE_ROUGHNESS: f64 = 24.0;
+/// y of the top in the middle, and how far it strays
+const ISLAND_TOP: i32 = 64;
+const TOP_HEIGHT: f64 = 4.0;
+/// How deep the middle goes
+const ISLAND_DEPTH: f64 = 40.0;
+
+const EDGE_SALT: u64 = 0x65646765;
+const TOP_SALT: u64 = 0x746f7020;
+
+pub struct End {
+    seed: u64,
+    end_stone: u32,
+}
+
+impl End {
+    pub fn new(seed: u64, blocks: &BlockPalette) -> Self {
+        Self {
+            seed,
+            end_stone: required_block(blocks, "minecraft:end_stone"),
+        }
+    }
+}
+
+impl WorldGenerator for End {
+    fn generate(&self, chunk: &mut Chunk) {
+        for x in 0..16 {
+            for z in 0..16 {
+                let (world_x, world_z) = (chunk.x * 16 + x as i32, chunk.z * 16 + z as i32);
+                let distance = ((world_x as f64).powi(2) + (world_z as f64).powi(2)).sqrt();
+                let edge = ISLAND_RADIUS
+                    + fractal(self.seed ^ EDGE_SALT, world_x, world_z, 32.0, 3) * EDGE_ROUGHNESS;
+                if distance >= edge {
+                    continue;
+                }
+
+                let top = ISLAND_TOP
+                    + (fractal(self.seed ^ TOP_SALT, world_x, world_z, 32.0, 3) * TOP_HEIGHT)
+                        as i32;
+                let depth = ((edge - distance) / edge * ISLAND_DEPTH) as i32 + 1;
+                for y in top - depth + 1..=top {
+                    chunk.set_block(x, y, z, self.end_stone);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+/// game/generator/flat.rs
+/// ======================
+///
+/// A superflat world: the layers from flat-layers stacked up from the
+/// bottom of the world, the same in every column.
+use super::{required_block, WorldGenerator};
+use crate::game::block::BlockPalette;
+use crate::game::chunk::Chunk;
+use crate::game::dimension::Dimension;
+
+pub struct Flat {
+    /// Bottom up, one per y
+    column: Vec<u32>,
+}
+
+impl Flat {
+    pub fn new(layers: &[(String, u32)], blocks: &BlockPalette) -> Self {
+        let column = layers
+            .iter()
+            .flat_map(|(name, count)| {
+                std::iter::repeat_n(required_block(blocks, name), *count as usize)
+            })
+            .collect();
+
+        Self { column }
+    }
+}
+
+impl WorldGenerator for Flat {
+    fn generate(&self, chunk: &mut Chunk) {
+        for x in 0..16 {
+            for z in 0..16 {
+                for (y, block) in (chunk.min_y()..chunk.max_y()).zip(&self.column) {
+                    chunk.set_block(x, y, z, *block);
+                }
+            }
+        }
+    }
+
+    /// On top of the layers, flat's only ever the overworld
+    fn spawn_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        if self.column.is_empty() {
+            return None;
+        }
+        let sections = Dimension::Overworld.sections();
+        let (min_y, max_y) = (sections.start * 16, sections.end * 16);
+        Some((min_y + self.column.len() as i32).min(max_y))
+    }
+}
@@ -0,0 +1,172 @@
+/// game/generator/mod.rs
+/// =====================
+///
+/// Makes chunks that have never been saved. The overworld depends on
+/// level-type:
+///
+///     default   hills, plains and oceans out of noise (overworld.rs)
+///     flat      the same layers everywhere (flat.rs, flat-layers)
+///     void      nothing at all
+///
+/// and the nether (nether.rs) and the end (end.rs) are the same whatever
+/// it is, like vanilla.
+///
+/// Generating's the same every time for the same seed and chunk, so it
+/// doesn't matter which order chunks are made in or on which thread,
+/// they're made off the main loop on a few worker threads (pool.rs).
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::block::BlockPalette;
+use super::chunk::Chunk;
+use super::dimension::Dimension;
+use super::settings::LevelType;
+
+mod end;
+mod flat;
+mod nether;
+mod noise;
+mod overworld;
+mod pool;
+
+pub use end::End;
+pub use flat::Flat;
+pub use nether::Nether;
+pub use overworld::Overworld;
+pub use pool::GeneratorPool;
+
+pub type Generators = HashMap<Dimension, Arc<dyn WorldGenerator>>;
+
+pub trait WorldGenerator: Send + Sync {
+    /// Fills in `chunk`, which is all air and the dimension's default
+    /// biome
+    fn generate(&self, chunk: &mut Chunk);
+
+    /// Where a player can stand in the column (their feet), if anywhere
+    fn spawn_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        None
+    }
+}
+
+/// Air, so already done
+pub struct Void;
+
+impl WorldGenerator for Void {
+    fn generate(&self, _chunk: &mut Chunk) {}
+}
+
+/// One for each dimension. Panics if a block one of them needs isn't in
+/// the palette, like config errors.
+pub fn from_settings(level_type: &LevelType, seed: u64, blocks: &BlockPalette) -> Generators {
+    let overworld: Arc<dyn WorldGenerator> = match level_type {
+        LevelType::Default => Arc::new(Overworld::new(seed, blocks)),
+        LevelType::Flat(layers) => Arc::new(Flat::new(layers, blocks)),
+        LevelType::Void => Arc::new(Void),
+    };
+
+    HashMap::from([
+        (Dimension::Overworld, overworld),
+        (Dimension::Nether, Arc::new(Nether::new(seed, blocks)) as _),
+        (Dimension::End, Arc::new(End::new(seed, blocks)) as _),
+    ])
+}
+
+fn required_block(blocks: &BlockPalette, name: &str) -> u32 {
+    match blocks.default_state(name) {
+        Some(block) => block,
+        None => panic!(
+            "The world generator needs {}, which isn't a known block",
+            name
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn generate(
+        generators: &Generators,
+        blocks: &BlockPalette,
+        dimension: Dimension,
+        x: i32,
+        z: i32,
+    ) -> Chunk {
+        let mut chunk = Chunk::new(
+            x,
+            z,
+            dimension.sections(),
+            blocks.air,
+            dimension.default_biome(),
+        );
+        generators[&dimension].generate(&mut chunk);
+        chunk
+    }
+
+    #[test]
+    fn deterministic() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let flat = LevelType::Flat(vec![
+            ("bedrock".to_string(), 1),
+            ("dirt".to_string(), 2),
+            ("grass".to_string(), 1),
+        ]);
+
+        for level_type in [LevelType::Default, flat, LevelType::Void] {
+            let first = from_settings(&level_type, 1234, &blocks);
+            let second = from_settings(&level_type, 1234, &blocks);
+
+            for dimension in [Dimension::Overworld, Dimension::Nether, Dimension::End] {
+                for (x, z) in [(0, 0), (-3, 7), (100, -100)] {
+                    let a = generate(&first, &blocks, dimension, x, z);
+                    let b = generate(&second, &blocks, dimension, x, z);
+                    assert_eq!(
+                        a.encode_network(&blocks),
+                        b.encode_network(&blocks),
+                        "{level_type:?} {dimension} {x} {z}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeds() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let one = from_settings(&LevelType::Default, 1, &blocks);
+        let two = from_settings(&LevelType::Default, 2, &blocks);
+
+        let differs = (0..8).any(|x| {
+            let a = generate(&one, &blocks, Dimension::Overworld, x, 0);
+            let b = generate(&two, &blocks, Dimension::Overworld, x, 0);
+            a.encode_network(&blocks) != b.encode_network(&blocks)
+        });
+        assert!(differs);
+    }
+
+    #[test]
+    fn flat_and_void() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let layers = vec![("bedrock".to_string(), 1), ("dirt".to_string(), 2)];
+        let flat = from_settings(&LevelType::Flat(layers), 1, &blocks);
+
+        let chunk = generate(&flat, &blocks, Dimension::Overworld, 5, 5);
+        let min_y = chunk.min_y();
+        let (bedrock, dirt) = (
+            blocks.default_state("bedrock").unwrap(),
+            blocks.default_state("dirt").unwrap(),
+        );
+        for (x, z) in [(0, 0), (15, 3), (7, 15)] {
+            assert_eq!(chunk.block(x, min_y, z), bedrock);
+            assert_eq!(chunk.block(x, min_y + 2, z), dirt);
+            assert_eq!(chunk.block(x, min_y + 3, z), blocks.air);
+            assert_eq!(chunk.height(x, z), min_y + 3);
+        }
+
+        let void = from_settings(&LevelType::Void, 1, &blocks);
+        let chunk = generate(&void, &blocks, Dimension::Overworld, 0, 0);
+        assert_eq!(chunk.network_sub_chunk_count(), 0);
+    }
+}
@@ -0,0 +1,74 @@
+/// game/generator/nether.rs
+/// ========================
+///
+/// A cave as big as the dimension: a bumpy netherrack floor and ceiling
+/// from Perlin noise, with a lava sea in the low parts. From the bottom up:
+///
+///     bedrock     the bottom block, and patchily the 4 above it
+///     netherrack  up to the floor
+///     lava        above the floor, up to the lava level
+///     air
+///     netherrack  down from the ceiling
+///     bedrock     the top block, and patchily the 4 below it
+use super::noise::{fractal, is_bedrock};
+use super::{required_block, WorldGenerator};
+use crate::game::block::BlockPalette;
+use crate::game::chunk::Chunk;
+
+/// Everything below this that isn't netherrack is lava
+const LAVA_LEVEL: i32 = 32;
+/// Where the floor and ceiling are on average, and how far they stray
+const FLOOR: i32 = 40;
+const CEILING: i32 = 100;
+const BUMP_HEIGHT: f64 = 24.0;
+const BUMP_SCALE: f64 = 64.0;
+
+const FLOOR_SALT: u64 = 0x666c6f6f;
+const CEILING_SALT: u64 = 0x6365696c;
+
+pub struct Nether {
+    seed: u64,
+    bedrock: u32,
+    netherrack: u32,
+    lava: u32,
+}
+
+impl Nether {
+    pub fn new(seed: u64, blocks: &BlockPalette) -> Self {
+        Self {
+            seed,
+            bedrock: required_block(blocks, "minecraft:bedrock"),
+            netherrack: required_block(blocks, "minecraft:netherrack"),
+            lava: required_block(blocks, "minecraft:lava"),
+        }
+    }
+}
+
+impl WorldGenerator for Nether {
+    fn generate(&self, chunk: &mut Chunk) {
+        let (min_y, max_y) = (chunk.min_y(), chunk.max_y());
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let (world_x, world_z) = (chunk.x * 16 + x as i32, chunk.z * 16 + z as i32);
+                let bump = |salt| fractal(self.seed ^ salt, world_x, world_z, BUMP_SCALE, 4);
+                let floor = FLOOR + (bump(FLOOR_SALT) * BUMP_HEIGHT) as i32;
+                let ceiling = CEILING + (bump(CEILING_SALT) * BUMP_HEIGHT) as i32;
+
+                for y in min_y..max_y {
+                    let depth = (y - min_y).min(max_y - 1 - y);
+                    let block = if is_bedrock(self.seed, world_x, y, world_z, depth) {
+                        self.bedrock
+                    } else if y <= floor || y >= ceiling {
+                        self.netherrack
+                    } else if y < LAVA_LEVEL {
+                        self.lava
+                    } else {
+                        continue;
+                    };
+                    chunk.set_block(x, y, z, block);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/// game/generator/noise.rs
+/// =======================
+///
+/// The randomness every generator's made of. It's all worked out from the
+/// seed and the position alone (there's no permutation table to
+/// shuffle), so chunks come out the same however they're generated.
+///
+/// Reference: https://mrl.cs.nyu.edu/~perlin/paper445.pdf
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// So bedrock isn't the same noise as anything else
+const BEDROCK_SALT: u64 = 0x62656472;
+
+/// Mixes a seed and a position into something random looking
+/// (splitmix64's finaliser)
+pub fn hash(seed: u64, x: i32, z: i32) -> u64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (z as u32 as u64).wrapping_mul(0xc2b2ae3d27d4eb4f);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+/// Bedrock's solid at the edge of the world and gets patchier over the
+/// next 4 blocks in, `depth` is how far in the block is
+pub fn is_bedrock(seed: u64, x: i32, y: i32, z: i32, depth: i32) -> bool {
+    depth <= 0 || (depth < 5 && hash(seed ^ BEDROCK_SALT, x, z ^ y) % 5 >= depth as u64)
+}
+
+const DIAGONAL: f64 = FRAC_1_SQRT_2;
+const GRADIENTS: [(f64, f64); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (DIAGONAL, DIAGONAL),
+    (-DIAGONAL, DIAGONAL),
+    (DIAGONAL, -DIAGONAL),
+    (-DIAGONAL, -DIAGONAL),
+];
+
+/// 2D Perlin noise, about -0.7 to 0.7 and 0 at every whole number
+fn perlin(seed: u64, x: f64, z: f64) -> f64 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (fx, fz) = (x - x0, z - z0);
+    let (ix, iz) = (x0 as i32, z0 as i32);
+
+    // how far the point is along the corner's gradient
+    let corner = |dx: i32, dz: i32| {
+        let (gx, gz) = GRADIENTS[(hash(seed, ix + dx, iz + dz) & 7) as usize];
+        gx * (fx - dx as f64) + gz * (fz - dz as f64)
+    };
+
+    let (u, v) = (fade(fx), fade(fz));
+    lerp(
+        v,
+        lerp(u, corner(0, 0), corner(1, 0)),
+        lerp(u, corner(0, 1), corner(1, 1)),
+    )
+}
+
+/// Octaves of Perlin noise, each twice as detailed and half as strong as
+/// the last, scaled back to the range of one. `scale` is how wide the
+/// biggest bumps are, in blocks.
+pub fn fractal(seed: u64, x: i32, z: i32, scale: f64, octaves: u32) -> f64 {
+    let (x, z) = (x as f64 / scale, z as f64 / scale);
+    let (mut total, mut amplitude, mut frequency, mut max) = (0.0, 1.0, 1.0, 0.0);
+
+    for octave in 0..octaves {
+        let seed = hash(seed, octave as i32, 0);
+        total += perlin(seed, x * frequency, z * frequency) * amplitude;
+        max += amplitude;
+        amplitude /= 2.0;
+        frequency *= 2.0;
+    }
+
+    total / max
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
@@ -0,0 +1,112 @@
+/// game/generator/overworld.rs
+/// ===========================
+///
+/// A simple overworld, nothing like vanilla's but somewhere to stand:
+/// one height per column from two layers of Perlin noise, big and slow
+/// for continents and oceans, small and quick for hills on top. Then
+/// from the bottom up:
+///
+///     bedrock   the bottom block, and patchily the 4 above it
+///     stone
+///     dirt      the 3 below the surface, sand at the coast and underwater
+///     grass     the surface, or sand
+///     water     up to the sea level
+use super::noise::{fractal, is_bedrock};
+use super::{required_block, WorldGenerator};
+use crate::game::block::BlockPalette;
+use crate::game::chunk::Chunk;
+
+/// Everything below this that isn't ground is water
+const SEA_LEVEL: i32 = 63;
+/// How far above and below the sea level the land goes, roughly
+const CONTINENT_HEIGHT: f64 = 48.0;
+const HILL_HEIGHT: f64 = 16.0;
+/// How wide a bump in the noise is, in blocks
+const CONTINENT_SCALE: f64 = 512.0;
+const HILL_SCALE: f64 = 96.0;
+
+/// So continents and hills aren't the same noise
+const CONTINENT_SALT: u64 = 0x636f6e74;
+const HILL_SALT: u64 = 0x68696c6c;
+
+const OCEAN: u32 = 0;
+const PLAINS: u32 = 1;
+const BEACH: u32 = 16;
+
+pub struct Overworld {
+    seed: u64,
+    bedrock: u32,
+    stone: u32,
+    dirt: u32,
+    grass: u32,
+    sand: u32,
+    water: u32,
+}
+
+impl Overworld {
+    pub fn new(seed: u64, blocks: &BlockPalette) -> Self {
+        Self {
+            seed,
+            bedrock: required_block(blocks, "minecraft:bedrock"),
+            stone: required_block(blocks, "minecraft:stone"),
+            dirt: required_block(blocks, "minecraft:dirt"),
+            grass: required_block(blocks, "minecraft:grass"),
+            sand: required_block(blocks, "minecraft:sand"),
+            water: required_block(blocks, "minecraft:water"),
+        }
+    }
+
+    /// y of the highest block in the column
+    fn height(&self, x: i32, z: i32) -> i32 {
+        let continent = fractal(self.seed ^ CONTINENT_SALT, x, z, CONTINENT_SCALE, 3);
+        let hills = fractal(self.seed ^ HILL_SALT, x, z, HILL_SCALE, 4);
+
+        SEA_LEVEL + (continent * CONTINENT_HEIGHT + hills * HILL_HEIGHT) as i32
+    }
+}
+
+impl WorldGenerator for Overworld {
+    fn generate(&self, chunk: &mut Chunk) {
+        let (min_y, max_y) = (chunk.min_y(), chunk.max_y());
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let (world_x, world_z) = (chunk.x * 16 + x as i32, chunk.z * 16 + z as i32);
+                let height = self.height(world_x, world_z).clamp(min_y + 1, max_y - 1);
+
+                let (surface, below, biome) = if height < SEA_LEVEL - 1 {
+                    (self.sand, self.sand, OCEAN)
+                } else if height <= SEA_LEVEL + 1 {
+                    (self.sand, self.sand, BEACH)
+                } else {
+                    (self.grass, self.dirt, PLAINS)
+                };
+
+                for y in min_y..=height {
+                    let block = if is_bedrock(self.seed, world_x, y, world_z, y - min_y) {
+                        self.bedrock
+                    } else if y == height {
+                        surface
+                    } else if y > height - 4 {
+                        below
+                    } else {
+                        self.stone
+                    };
+                    chunk.set_block(x, y, z, block);
+                }
+                for y in height + 1..SEA_LEVEL {
+                    chunk.set_block(x, y, z, self.water);
+                }
+
+                for y in min_y..max_y {
+                    chunk.set_biome(x, y, z, biome);
+                }
+            }
+        }
+    }
+
+    /// On the ground, or on the water
+    fn spawn_height(&self, x: i32, z: i32) -> Option<i32> {
+        Some(self.height(x, z).max(SEA_LEVEL - 1) + 1)
+    }
+}
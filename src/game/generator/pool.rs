@@ -0,0 +1,76 @@
+/// game/generator/pool.rs
+/// ======================
+///
+/// The threads chunks are generated on, shared by every dimension.
+/// Chunks go in as they're asked for and come back whenever they're done,
+/// in no particular order, the world picks them up next time it's asked
+/// for a chunk.
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::Generators;
+use crate::game::chunk::Chunk;
+use crate::game::dimension::Dimension;
+
+type Job = (Dimension, Chunk);
+
+pub struct GeneratorPool {
+    generators: Arc<Generators>,
+    jobs: Sender<Job>,
+    /// Only in a mutex so the world can be shared between threads
+    finished: Mutex<Receiver<Job>>,
+}
+
+impl GeneratorPool {
+    /// The threads stop once the pool's dropped
+    pub fn new(generators: Generators, threads: usize) -> Self {
+        let (jobs, queued) = mpsc::channel::<Job>();
+        let (done, finished) = mpsc::channel();
+        let queued = Arc::new(Mutex::new(queued));
+        let generators = Arc::new(generators);
+
+        for i in 0..threads.max(1) {
+            let (generators, queued, done) = (generators.clone(), queued.clone(), done.clone());
+
+            thread::Builder::new()
+                .name(format!("generator-{i}"))
+                .spawn(move || loop {
+                    // the lock's dropped before generating
+                    let job = queued.lock().unwrap().recv();
+                    let Ok((dimension, mut chunk)) = job else {
+                        return;
+                    };
+
+                    if let Some(generator) = generators.get(&dimension) {
+                        generator.generate(&mut chunk);
+                    }
+                    if done.send((dimension, chunk)).is_err() {
+                        return;
+                    }
+                })
+                .expect("Couldn't start a world generator thread");
+        }
+
+        Self {
+            generators,
+            jobs,
+            finished: Mutex::new(finished),
+        }
+    }
+
+    pub fn generate(&self, dimension: Dimension, chunk: Chunk) {
+        // only fails once every thread's gone, which they don't on their own
+        let _ = self.jobs.send((dimension, chunk));
+    }
+
+    /// See `WorldGenerator::spawn_height`
+    pub fn spawn_height(&self, dimension: Dimension, x: i32, z: i32) -> Option<i32> {
+        self.generators.get(&dimension)?.spawn_height(x, z)
+    }
+
+    /// Everything that's been generated since last time
+    pub fn finished(&self) -> Vec<Job> {
+        self.finished.lock().unwrap().try_iter().collect()
+    }
+}
@@ -0,0 +1,207 @@
+/// game/item.rs
+/// ============
+///
+/// Every item the client knows about and the ids they go by, which it's
+/// told in StartGame (itemstates). Unlike blocks the client doesn't work
+/// the ids out itself, whatever we send is what it uses, but anything
+/// not on the list doesn't exist for it.
+///
+/// The list comes from the vanilla one (PocketMine's BedrockData has it
+/// as required_item_list.json), dropped next to the server like the
+/// block states. Without it there are the items for the blocks we have
+/// built in, with their vanilla ids.
+///
+/// The creative inventory is every item that places a block we know.
+///
+/// Reference: https://github.com/pmmp/BedrockData
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+use serde_json::Value;
+
+use super::block::BlockPalette;
+use crate::protocol::native;
+use crate::protocol::v622::{
+    CreativeContentItems, ItemExtraDataWithoutBlockingTick, ItemExtraDataWithoutBlockingTickHasNbt,
+    ItemLegacy, ItemLegacyNetworkIdData, ItemLegacyNetworkIdDataExtra, ItemstatesEntry,
+};
+
+pub const ITEMS_FILE: &str = "required_item_list.json";
+
+/// Items for the built in blocks (see block.rs), ids as of 1.20.40
+const BUILTIN: &[(&str, i16)] = &[
+    ("minecraft:stone", 1),
+    ("minecraft:grass", 2),
+    ("minecraft:dirt", 3),
+    ("minecraft:bedrock", 7),
+    ("minecraft:sand", 12),
+    ("minecraft:netherrack", 87),
+    ("minecraft:end_stone", 121),
+];
+
+pub struct ItemPalette {
+    /// By id
+    items: Vec<ItemstatesEntry>,
+}
+
+impl ItemPalette {
+    pub fn load(path: &Path) -> Self {
+        let items = match fs::read_to_string(path) {
+            Ok(json) => Self::parse(&json),
+            Err(e) => {
+                warn!(
+                    "Couldn't read {} ({}), only a few items will be available",
+                    path.display(),
+                    e
+                );
+                Self::builtin()
+            }
+        };
+
+        let palette = Self::new(items);
+        info!("Loaded {} items", palette.items.len());
+        palette
+    }
+
+    /// `{"minecraft:stone": {"runtime_id": 1, "component_based": false}, ...}`
+    fn parse(json: &str) -> Vec<ItemstatesEntry> {
+        let items = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(items)) => items,
+            _ => {
+                warn!("{ITEMS_FILE} isn't a JSON object, using the built in items");
+                return Self::builtin();
+            }
+        };
+
+        items
+            .into_iter()
+            .filter_map(|(name, item)| {
+                let runtime_id = item["runtime_id"]
+                    .as_i64()
+                    .and_then(|id| i16::try_from(id).ok());
+                let Some(runtime_id) = runtime_id else {
+                    warn!("Skipping item without a runtime id: {name}");
+                    return None;
+                };
+                Some(ItemstatesEntry {
+                    name,
+                    runtime_id,
+                    component_based: item["component_based"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect()
+    }
+
+    fn builtin() -> Vec<ItemstatesEntry> {
+        BUILTIN
+            .iter()
+            .map(|&(name, runtime_id)| ItemstatesEntry {
+                name: name.to_string(),
+                runtime_id,
+                component_based: false,
+            })
+            .collect()
+    }
+
+    pub fn new(mut items: Vec<ItemstatesEntry>) -> Self {
+        items.sort_by_key(|item| item.runtime_id);
+
+        // item data's laid out differently for shields, see native.rs
+        if let Some(shield) = items.iter().find(|i| i.name == "minecraft:shield") {
+            native::set_shield_item_id(shield.runtime_id as i32);
+        }
+
+        Self { items }
+    }
+
+    /// For StartGame
+    pub fn itemstates(&self) -> Vec<ItemstatesEntry> {
+        self.items.clone()
+    }
+
+    /// For CreativeContent, one of each block item
+    pub fn creative_items(&self, blocks: &BlockPalette) -> Vec<CreativeContentItems> {
+        self.items
+            .iter()
+            .filter_map(|item| Some((item, blocks.default_state(&item.name)?)))
+            .filter(|&(_, block)| block != blocks.air)
+            .enumerate()
+            .map(|(i, (item, block))| CreativeContentItems {
+                // entry ids start at 1
+                entry_id: i as i32 + 1,
+                item: ItemLegacy {
+                    network_id: item.runtime_id as i32,
+                    network_id_data: Some(ItemLegacyNetworkIdData {
+                        count: 1,
+                        metadata: 0,
+                        block_runtime_id: blocks.network_id(block) as i32,
+                        extra: ItemLegacyNetworkIdDataExtra::Default(
+                            ItemExtraDataWithoutBlockingTick {
+                                has_nbt: ItemExtraDataWithoutBlockingTickHasNbt::False,
+                                nbt: None,
+                                can_place_on: vec![],
+                                can_destroy: vec![],
+                            },
+                        ),
+                    }),
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raknet::packets::{FromBuffer, ToBuffer};
+
+    #[test]
+    fn parse() {
+        let items = ItemPalette::new(ItemPalette::parse(
+            r#"{
+                "minecraft:stone": {"runtime_id": 1, "component_based": false},
+                "minecraft:apple": {"runtime_id": 257, "component_based": false},
+                "custom:thing": {"runtime_id": 900, "component_based": true},
+                "minecraft:air": {"runtime_id": -158},
+                "broken": {"runtime_id": 99999}
+            }"#,
+        ));
+
+        let ids: Vec<(&str, i16)> = items
+            .items
+            .iter()
+            .map(|i| (i.name.as_str(), i.runtime_id))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("minecraft:air", -158),
+                ("minecraft:stone", 1),
+                ("minecraft:apple", 257),
+                ("custom:thing", 900),
+            ]
+        );
+        assert!(items.itemstates()[3].component_based);
+
+        assert_eq!(ItemPalette::parse("[]").len(), BUILTIN.len());
+    }
+
+    #[test]
+    fn creative_items() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let items = ItemPalette::load(Path::new("no-such-file"));
+
+        let creative = items.creative_items(&blocks);
+        assert_eq!(creative.len(), BUILTIN.len());
+        assert_eq!(creative[0].entry_id, 1);
+        assert_eq!(creative[0].item.network_id, 1);
+
+        let stone = blocks.default_state("minecraft:stone").unwrap();
+        let data = creative[0].item.network_id_data.as_ref().unwrap();
+        assert_eq!(data.block_runtime_id, blocks.network_id(stone) as i32);
+
+        let mut buf = creative[0].item.to_buffer();
+        assert_eq!(ItemLegacy::from_buffer(&mut buf).unwrap(), creative[0].item);
+    }
+}
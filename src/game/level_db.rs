@@ -0,0 +1,469 @@
+/// game/level_db.rs
+/// ================
+///
+/// Worlds on disk, in vanilla's format: a world from a Bedrock Dedicated
+/// Server can be dropped into worlds/ and used as is, and taken back the
+/// other way. A world's a directory:
+///
+///     level.dat       settings, little endian NBT after an 8 byte header
+///                     (storage version and length, both i32s)
+///     levelname.txt   the name, for the world list
+///     db/             LevelDB (see leveldb/)
+///
+/// Chunk keys are the chunk's x and z (i32s), the dimension's id (an i32
+/// too, left out for the overworld), then a tag for what's in it, and for
+/// sub chunks their y index:
+///
+///     43   data 3D            heightmap and biomes
+///     44   version            u8
+///     45   data 2D            heightmap and biomes, before 1.18
+///     47   sub chunk prefix   one per sub chunk
+///     49   block entities
+///     51   pending ticks      scheduled block ticks
+///     54   finalized state    i32, 2 once it's been generated
+///     118  version            before 1.16.100
+///
+/// (what's in them is in chunk/disk.rs). Entities and so on we don't
+/// know about are left alone.
+///
+/// Reference: https://minecraft.wiki/w/Bedrock_Edition_level_format
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::block::{BlockPalette, BlockState};
+use super::chunk::{Chunk, SubChunk};
+use super::dimension::Dimension;
+use super::settings::{LevelSettings, LevelType};
+use crate::leveldb::{Db, DbError, WriteBatch};
+use crate::nbt::{self, Compound, Nbt, NbtFlavour, Tag};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+use crate::server::VoxelServer;
+
+/// Where worlds are, by level-name
+pub const WORLDS_DIR: &str = "worlds";
+
+const STORAGE_VERSION: i32 = 10;
+/// SpawnY in a level.dat whose spawn hasn't been picked yet
+const UNKNOWN_SPAWN_Y: i32 = 32767;
+/// What 1.20.40 writes
+const CHUNK_VERSION: u8 = 40;
+const FINALIZED: i32 = 2;
+
+const DATA_3D: u8 = 43;
+const VERSION: u8 = 44;
+const DATA_2D: u8 = 45;
+const SUB_CHUNK_PREFIX: u8 = 47;
+const BLOCK_ENTITIES: u8 = 49;
+const PENDING_TICKS: u8 = 51;
+const FINALIZED_STATE: u8 = 54;
+const LEGACY_VERSION: u8 = 118;
+
+/// Overworld generator types in level.dat
+const GENERATOR_INFINITE: i32 = 1;
+const GENERATOR_FLAT: i32 = 2;
+
+/// Random ticks per sub chunk per tick, vanilla's default
+const DEFAULT_RANDOM_TICK_SPEED: i32 = 1;
+
+/// What flat worlds' layers are written as, as of 1.18
+const FLAT_ENCODING_VERSION: i32 = 6;
+const FLAT_WORLD_VERSION: &str = "version.post_1_18";
+
+fn chunk_key(dimension: Dimension, x: i32, z: i32, tag: u8) -> Vec<u8> {
+    let mut key = Vec::with_capacity(14);
+    key.extend_from_slice(&x.to_le_bytes());
+    key.extend_from_slice(&z.to_le_bytes());
+    if dimension != Dimension::Overworld {
+        key.extend_from_slice(&dimension.id().to_le_bytes());
+    }
+    key.push(tag);
+    key
+}
+
+fn sub_chunk_key(dimension: Dimension, x: i32, z: i32, y_index: i32) -> Vec<u8> {
+    let mut key = chunk_key(dimension, x, z, SUB_CHUNK_PREFIX);
+    key.push(y_index as u8);
+    key
+}
+
+/// FlatWorldLayers, which is JSON in a string
+fn flat_world_layers(layers: &[(String, u32)]) -> String {
+    let block_layers: Vec<serde_json::Value> = layers
+        .iter()
+        .map(|(name, count)| serde_json::json!({ "block_name": name, "count": count }))
+        .collect();
+
+    serde_json::json!({
+        "biome_id": Dimension::Overworld.default_biome(),
+        "block_layers": block_layers,
+        "encoding_version": FLAT_ENCODING_VERSION,
+        "structure_options": null,
+        "world_version": FLAT_WORLD_VERSION,
+    })
+    .to_string()
+}
+
+fn parse_flat_world_layers(json: &str) -> Option<Vec<(String, u32)>> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+
+    value["block_layers"]
+        .as_array()?
+        .iter()
+        .map(|layer| {
+            let name = layer["block_name"].as_str()?.to_string();
+            let count = u32::try_from(layer["count"].as_u64()?).ok()?;
+            Some((name, count))
+        })
+        .collect()
+}
+
+fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+pub struct LevelDb {
+    dir: PathBuf,
+    db: Db,
+    /// level.dat, anything we don't touch is written back as it was
+    level_dat: Compound,
+}
+
+impl LevelDb {
+    /// Opens the world in `dir`, or makes a new one from `settings`
+    pub fn open(dir: &Path, settings: &LevelSettings) -> Result<Self, DbError> {
+        let level_dat = match fs::read(dir.join("level.dat")) {
+            Ok(data) => Self::read_level_dat(data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::new_level_dat(settings),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut level_db = Self {
+            dir: dir.to_path_buf(),
+            db: Db::open(&dir.join("db"))?,
+            level_dat,
+        };
+        level_db.save_level_dat()?;
+
+        Ok(level_db)
+    }
+
+    fn read_level_dat(data: Vec<u8>) -> Result<Compound, DbError> {
+        let read = || {
+            let mut buf = MsgBuffer::from(data);
+            // storage version and length
+            buf.read_vec(8)?;
+            Ok::<_, DecodeError>(nbt::read(&mut buf, NbtFlavour::LittleEndian)?.tag)
+        };
+
+        match read() {
+            Ok(Tag::Compound(level_dat)) => Ok(level_dat),
+            _ => Err(DbError::Corrupt("level.dat isn't NBT".to_string())),
+        }
+    }
+
+    /// The least vanilla needs to open it
+    fn new_level_dat(settings: &LevelSettings) -> Compound {
+        let game_version: Vec<Tag> = VoxelServer::VERSION
+            .split('.')
+            .map(|part| Tag::Int(part.parse().unwrap_or(0)))
+            .chain(std::iter::repeat(Tag::Int(0)))
+            .take(5)
+            .collect();
+
+        // void's a flat world without layers as far as vanilla's concerned
+        let (generator, flat_layers) = match &settings.level_type {
+            LevelType::Default => (GENERATOR_INFINITE, None),
+            LevelType::Flat(layers) => (GENERATOR_FLAT, Some(flat_world_layers(layers))),
+            LevelType::Void => (GENERATOR_FLAT, Some(flat_world_layers(&[]))),
+        };
+
+        let mut level_dat: Compound = [
+            ("LevelName", Tag::from(settings.level_name.as_str())),
+            ("RandomSeed", Tag::Long(settings.seed as i64)),
+            ("GameType", Tag::Int(settings.gamemode.id())),
+            ("Difficulty", Tag::Int(settings.difficulty as i32)),
+            ("Generator", Tag::Int(generator)),
+            ("StorageVersion", Tag::Int(STORAGE_VERSION)),
+            (
+                "NetworkVersion",
+                Tag::Int(VoxelServer::PROTOCOL_VERSION as i32),
+            ),
+            ("lastOpenedWithVersion", Tag::List(game_version)),
+            ("commandsEnabled", Tag::from(settings.allow_cheats)),
+            ("LastPlayed", Tag::Long(unix_time())),
+            ("currentTick", Tag::Long(0)),
+            ("randomtickspeed", Tag::Int(DEFAULT_RANDOM_TICK_SPEED)),
+        ]
+        .into_iter()
+        .map(|(name, tag)| (name.to_string(), tag))
+        .collect();
+
+        if let Some(layers) = flat_layers {
+            level_dat.insert("FlatWorldLayers".to_string(), Tag::from(layers.as_str()));
+        }
+        level_dat
+    }
+
+    /// From level.dat, which wins over server.properties once the world
+    /// exists
+    pub fn seed(&self) -> Option<u64> {
+        Some(self.level_dat.get("RandomSeed")?.as_i64()? as u64)
+    }
+
+    /// From level.dat too, a world keeps the generator it was made with.
+    /// `None` for generators we don't have (the nether, legacy worlds).
+    pub fn level_type(&self) -> Option<LevelType> {
+        match self.level_dat.get("Generator")?.as_i64()? as i32 {
+            GENERATOR_INFINITE => Some(LevelType::Default),
+            GENERATOR_FLAT => {
+                let json = self.level_dat.get("FlatWorldLayers")?.as_str()?;
+                let layers = parse_flat_world_layers(json)?;
+                match layers.is_empty() {
+                    true => Some(LevelType::Void),
+                    false => Some(LevelType::Flat(layers)),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The world spawn, once it's been worked out. Vanilla leaves y at
+    /// 32767 until it has.
+    pub fn spawn(&self) -> Option<(i32, i32, i32)> {
+        let get = |name| Some(self.level_dat.get(name)?.as_i64()? as i32);
+        let spawn = (get("SpawnX")?, get("SpawnY")?, get("SpawnZ")?);
+        (spawn.1 != UNKNOWN_SPAWN_Y).then_some(spawn)
+    }
+
+    /// Written with the rest of level.dat
+    pub fn set_spawn(&mut self, (x, y, z): (i32, i32, i32)) {
+        for (name, value) in [("SpawnX", x), ("SpawnY", y), ("SpawnZ", z)] {
+            self.level_dat.insert(name.to_string(), Tag::Int(value));
+        }
+    }
+
+    /// The game rule, how many blocks in each sub chunk near a player get
+    /// a random tick every tick
+    pub fn random_tick_speed(&self) -> u32 {
+        self.level_dat
+            .get("randomtickspeed")
+            .and_then(Tag::as_i64)
+            .map_or(DEFAULT_RANDOM_TICK_SPEED as u32, |speed| {
+                speed.max(0) as u32
+            })
+    }
+
+    /// How many ticks the world's been running for, scheduled ticks are
+    /// due on ticks counted from this
+    pub fn current_tick(&self) -> u64 {
+        self.level_dat
+            .get("currentTick")
+            .and_then(Tag::as_i64)
+            .map_or(0, |tick| tick.max(0) as u64)
+    }
+
+    /// Written with the rest of level.dat
+    pub fn set_current_tick(&mut self, tick: u64) {
+        self.level_dat
+            .insert("currentTick".to_string(), Tag::Long(tick as i64));
+    }
+
+    /// Keeps the last one around as level.dat_old, like vanilla
+    pub fn save_level_dat(&mut self) -> Result<(), DbError> {
+        self.level_dat
+            .insert("LastPlayed".to_string(), Tag::Long(unix_time()));
+
+        let mut buf = MsgBuffer::new();
+        let level_dat = Nbt::new(Tag::Compound(self.level_dat.clone()));
+        nbt::write(&mut buf, &level_dat, NbtFlavour::LittleEndian);
+
+        let storage_version = self
+            .level_dat
+            .get("StorageVersion")
+            .and_then(Tag::as_i64)
+            .map_or(STORAGE_VERSION, |v| v as i32);
+        let mut data = storage_version.to_le_bytes().to_vec();
+        data.extend_from_slice(&(buf.get_bytes().len() as i32).to_le_bytes());
+        data.extend_from_slice(buf.get_bytes());
+
+        let path = self.dir.join("level.dat");
+        if path.exists() {
+            fs::copy(&path, self.dir.join("level.dat_old"))?;
+        }
+        fs::write(path, data)?;
+
+        if let Some(name) = self.level_dat.get("LevelName").and_then(Tag::as_str) {
+            fs::write(self.dir.join("levelname.txt"), name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills in `chunk` (an empty one) from the world, false if it's
+    /// never been saved. It's read only if any of its block states aren't
+    /// in the palette.
+    pub fn load_chunk(
+        &self,
+        dimension: Dimension,
+        chunk: &mut Chunk,
+        blocks: &BlockPalette,
+    ) -> Result<bool, DbError> {
+        let (x, z) = (chunk.x, chunk.z);
+        let chunk_key = |tag| chunk_key(dimension, x, z, tag);
+        if self.db.get(&chunk_key(VERSION))?.is_none()
+            && self.db.get(&chunk_key(LEGACY_VERSION))?.is_none()
+        {
+            return Ok(false);
+        }
+
+        let mut sub_chunks = vec![];
+        for y_index in chunk.sections() {
+            if let Some(data) = self.db.get(&sub_chunk_key(dimension, x, z, y_index))? {
+                sub_chunks.push((y_index, data));
+            }
+        }
+        let data_3d = self.db.get(&chunk_key(DATA_3D))?;
+        let data_2d = self.db.get(&chunk_key(DATA_2D))?;
+        let block_entities = self.db.get(&chunk_key(BLOCK_ENTITIES))?;
+        let pending_ticks = self.db.get(&chunk_key(PENDING_TICKS))?;
+
+        // states we don't have are shown as something close, but saving
+        // them like that would lose what they were
+        let inexact = Cell::new(false);
+        let decode = || {
+            let unknown = blocks.default_state("unknown").unwrap_or(blocks.air);
+            let state_id = |tag: &Tag| {
+                let state = BlockState::from_nbt(tag);
+                if let Some(id) = state.as_ref().and_then(|s| blocks.runtime_id(s)) {
+                    return id;
+                }
+                inexact.set(true);
+                state
+                    .and_then(|s| blocks.default_state(&s.name))
+                    .unwrap_or(unknown)
+            };
+            let read_block =
+                |buf: &mut MsgBuffer| Ok(state_id(&nbt::read(buf, NbtFlavour::LittleEndian)?.tag));
+
+            for (y_index, data) in sub_chunks {
+                let mut buf = MsgBuffer::from(data);
+                let sub_chunk = SubChunk::decode_disk(&mut buf, blocks.air, read_block)?;
+                chunk.set_sub_chunk(y_index, sub_chunk);
+            }
+            match (data_3d, data_2d) {
+                (Some(data), _) => chunk.decode_disk_biomes(data)?,
+                (None, Some(data)) => chunk.decode_disk_biomes_2d(data)?,
+                (None, None) => {}
+            }
+            if let Some(data) = block_entities {
+                chunk.decode_disk_block_entities(data)?;
+            }
+            if let Some(data) = pending_ticks {
+                chunk.decode_disk_scheduled_ticks(data, state_id)?;
+            }
+            chunk.recalculate_heightmap();
+            Ok::<_, DecodeError>(())
+        };
+
+        decode().map_err(|e| {
+            DbError::Corrupt(format!("chunk {x}, {z} in {dimension} can't be read: {e}"))
+        })?;
+        chunk.read_only = inexact.get();
+        Ok(true)
+    }
+
+    /// All in one write
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl Iterator<Item = (Dimension, &'a Chunk)>,
+        blocks: &BlockPalette,
+        current_tick: u64,
+    ) -> Result<(), DbError> {
+        let mut batch = WriteBatch::default();
+
+        for (dimension, chunk) in chunks {
+            let (x, z) = (chunk.x, chunk.z);
+            let chunk_key = |tag| chunk_key(dimension, x, z, tag);
+            batch.put(chunk_key(VERSION), vec![CHUNK_VERSION]);
+            batch.delete(chunk_key(LEGACY_VERSION));
+
+            for y_index in chunk.sections() {
+                let key = sub_chunk_key(dimension, x, z, y_index);
+                match chunk.encode_disk_sub_chunk(y_index, blocks) {
+                    Some(data) => batch.put(key, data),
+                    None => batch.delete(key),
+                }
+            }
+
+            batch.put(chunk_key(DATA_3D), chunk.encode_disk_biomes());
+            batch.delete(chunk_key(DATA_2D));
+
+            let key = chunk_key(BLOCK_ENTITIES);
+            match chunk.block_entities.is_empty() {
+                true => batch.delete(key),
+                false => batch.put(key, chunk.encode_disk_block_entities()),
+            }
+
+            let key = chunk_key(PENDING_TICKS);
+            match chunk.scheduled_ticks.is_empty() {
+                true => batch.delete(key),
+                false => batch.put(key, chunk.encode_disk_scheduled_ticks(current_tick, blocks)),
+            }
+
+            batch.put(chunk_key(FINALIZED_STATE), FINALIZED.to_le_bytes().to_vec());
+        }
+
+        self.db.write(batch)?;
+        self.db.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::settings::Difficulty;
+    use crate::leveldb::test_dir;
+    use crate::raknet::enums::Gamemode;
+
+    fn settings() -> LevelSettings {
+        LevelSettings {
+            level_name: "level-db".to_string(),
+            seed: 42,
+            gamemode: Gamemode::Creative,
+            difficulty: Difficulty::Peaceful,
+            allow_cheats: true,
+            texturepack_required: false,
+            view_distance: 8,
+            tick_distance: 4,
+            sub_chunk_requests: false,
+            level_type: LevelType::Flat(vec![("minecraft:stone".to_string(), 2)]),
+        }
+    }
+
+    #[test]
+    fn level_dat() {
+        let dir = test_dir("level-dat");
+        let mut level_db = LevelDb::open(&dir, &settings()).unwrap();
+        assert_eq!(level_db.spawn(), None);
+
+        level_db.set_spawn((1, 2, 3));
+        level_db.set_current_tick(99);
+        level_db.save_level_dat().unwrap();
+        drop(level_db);
+
+        let mut level_db = LevelDb::open(&dir, &settings()).unwrap();
+        assert_eq!(level_db.spawn(), Some((1, 2, 3)));
+        assert_eq!(level_db.current_tick(), 99);
+        assert_eq!(level_db.seed(), Some(42));
+        assert_eq!(level_db.level_type(), Some(settings().level_type));
+
+        // vanilla's "not yet"
+        level_db.set_spawn((0, UNKNOWN_SPAWN_Y, 0));
+        assert_eq!(level_db.spawn(), None);
+    }
+}
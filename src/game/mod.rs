@@ -0,0 +1,32 @@
+/// game/mod.rs
+/// ===========
+///
+/// Everything above the protocol: players, the world and what happens
+/// in it.
+use std::sync::atomic::{AtomicI64, Ordering};
+
+pub mod biome;
+pub mod blob_cache;
+#[allow(dead_code)] // not everything's used (yet)
+pub mod block;
+pub mod block_tick;
+#[allow(dead_code)] // not everything's used (yet)
+pub mod chunk;
+pub mod chunk_loader;
+pub mod command;
+pub mod connection;
+pub mod dimension;
+pub mod generator;
+pub mod item;
+pub mod level_db;
+pub mod settings;
+pub mod spawn;
+#[allow(dead_code)] // not everything's used (yet)
+pub mod world;
+
+static NEXT_ENTITY_ID: AtomicI64 = AtomicI64::new(1);
+
+/// Entity ids are unique for as long as the server's running
+pub fn next_entity_id() -> i64 {
+    NEXT_ENTITY_ID.fetch_add(1, Ordering::Relaxed)
+}
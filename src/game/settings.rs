@@ -0,0 +1,255 @@
+/// game/settings.rs
+/// ================
+///
+/// The parts of server.properties that describe the world, as the game
+/// side needs them (mostly for StartGame).
+use std::ops::RangeInclusive;
+
+use crate::config::{Config, ConfigError};
+use crate::game::block::BlockPalette;
+use crate::raknet::enums::Gamemode;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Difficulty {
+    Peaceful = 0,
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// For the config error when it's none of them
+    pub const EXPECTED: &'static str = "peaceful, easy, normal, hard or 0 to 3";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "peaceful" | "0" => Some(Difficulty::Peaceful),
+            "easy" | "1" => Some(Difficulty::Easy),
+            "normal" | "2" => Some(Difficulty::Normal),
+            "hard" | "3" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+}
+
+/// What new chunks look like, see generator/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelType {
+    Default,
+    /// Block names and how many of each, bottom up
+    Flat(Vec<(String, u32)>),
+    Void,
+}
+
+/// Vanilla's classic flat
+const DEFAULT_FLAT_LAYERS: &str = "minecraft:bedrock,2*minecraft:dirt,minecraft:grass";
+
+impl LevelType {
+    const EXPECTED: &'static str = "default, flat or void";
+    const EXPECTED_LAYERS: &'static str = "[count*]block separated by commas";
+
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        // neither's in older server.properties
+        let level_type = config.get_optional_parsed("level-type", Self::EXPECTED, |t| {
+            match t.to_lowercase().as_str() {
+                "default" => Some(LevelType::Default),
+                "flat" => Some(LevelType::Flat(Vec::new())),
+                "void" => Some(LevelType::Void),
+                _ => None,
+            }
+        })?;
+        Ok(match level_type {
+            Some(LevelType::Flat(_)) => LevelType::Flat(
+                config
+                    .get_optional_parsed("flat-layers", Self::EXPECTED_LAYERS, parse_flat_layers)?
+                    .unwrap_or_else(|| parse_flat_layers(DEFAULT_FLAT_LAYERS).unwrap()),
+            ),
+            other => other.unwrap_or(LevelType::Default),
+        })
+    }
+
+    /// Flat layers are only names until there's a palette to look them up in
+    pub fn check_blocks(&self, blocks: &BlockPalette) -> Result<(), ConfigError> {
+        let LevelType::Flat(layers) = self else {
+            return Ok(());
+        };
+        match layers
+            .iter()
+            .find(|(name, _)| blocks.default_state(name).is_none())
+        {
+            Some((name, _)) => Err(ConfigError {
+                name: "flat-layers".to_string(),
+                value: name.clone(),
+                expected: "known block names",
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelSettings {
+    pub level_name: String,
+    pub seed: u64,
+    pub gamemode: Gamemode,
+    pub difficulty: Difficulty,
+    pub allow_cheats: bool,
+    pub texturepack_required: bool,
+    /// The most chunks (radius) a player gets sent, whatever they ask for
+    pub view_distance: i32,
+    /// How far from players the world's ticked, in chunks
+    pub tick_distance: i32,
+    /// Whether clients ask for sub chunks themselves
+    pub sub_chunk_requests: bool,
+    pub level_type: LevelType,
+}
+
+impl LevelSettings {
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        Ok(Self {
+            level_name: config.get_property("level-name").clone(),
+            seed: parse_seed(config.get_property("level-seed")),
+            gamemode: config.get_parsed("gamemode", Gamemode::EXPECTED, Gamemode::from_name)?,
+            difficulty: config.get_parsed(
+                "difficulty",
+                Difficulty::EXPECTED,
+                Difficulty::from_name,
+            )?,
+            allow_cheats: config.get_bool("allow-cheats"),
+            texturepack_required: config.get_bool("texturepack-required"),
+            view_distance: parse_distance(config, "view-distance", 5..=i32::MAX, "5 or more")?,
+            tick_distance: parse_distance(
+                config,
+                "tick-distance",
+                4..=12,
+                "a number from 4 to 12",
+            )?,
+            // not in older server.properties
+            sub_chunk_requests: config
+                .get_optional("sub-chunk-requests")
+                .is_none_or(|v| v == "true"),
+            level_type: LevelType::from_config(config)?,
+        })
+    }
+}
+
+fn parse_distance(
+    config: &Config,
+    name: &str,
+    allowed: RangeInclusive<i32>,
+    expected: &'static str,
+) -> Result<i32, ConfigError> {
+    config.get_parsed(name, expected, |value| {
+        value
+            .parse()
+            .ok()
+            .filter(|distance| allowed.contains(distance))
+    })
+}
+
+/// `[count*]block` separated by commas, e.g. `bedrock,3*stone,grass`
+fn parse_flat_layers(layers: &str) -> Option<Vec<(String, u32)>> {
+    layers
+        .split(',')
+        .map(|layer| {
+            let layer = layer.trim();
+            let (count, name) = match layer.split_once('*') {
+                Some((count, name)) => (count.trim().parse().ok()?, name.trim()),
+                None => (1, layer),
+            };
+            if name.is_empty() {
+                return None;
+            }
+
+            let name = match name.contains(':') {
+                true => name.to_string(),
+                false => format!("minecraft:{name}"),
+            };
+            Some((name, count))
+        })
+        .collect()
+}
+
+/// Same rules as vanilla: empty is random, numbers are used as is and
+/// anything else is hashed (Java's String.hashCode, like every other
+/// Minecraft server out there)
+fn parse_seed(seed: &str) -> u64 {
+    if seed.is_empty() {
+        return rand::random();
+    }
+
+    if let Ok(seed) = seed.parse::<i64>() {
+        return seed as u64;
+    }
+
+    seed.encode_utf16()
+        .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32)) as i64 as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn config(properties: &[(&str, &str)]) -> Config {
+        Config {
+            config: properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn flat_layers() {
+        assert_eq!(
+            parse_flat_layers("bedrock, 3*stone ,minecraft:grass"),
+            Some(vec![
+                ("minecraft:bedrock".to_string(), 1),
+                ("minecraft:stone".to_string(), 3),
+                ("minecraft:grass".to_string(), 1),
+            ])
+        );
+        assert_eq!(parse_flat_layers("x*stone"), None);
+        assert_eq!(parse_flat_layers("-1*stone"), None);
+        assert_eq!(parse_flat_layers("stone,"), None);
+    }
+
+    #[test]
+    fn level_type() {
+        assert_eq!(LevelType::from_config(&config(&[])), Ok(LevelType::Default));
+        assert_eq!(
+            LevelType::from_config(&config(&[("level-type", "VOID")])),
+            Ok(LevelType::Void)
+        );
+        assert_eq!(
+            LevelType::from_config(&config(&[("level-type", "flat")])),
+            Ok(LevelType::Flat(
+                parse_flat_layers(DEFAULT_FLAT_LAYERS).unwrap()
+            ))
+        );
+        assert_eq!(
+            LevelType::from_config(&config(&[
+                ("level-type", "flat"),
+                ("flat-layers", "2*dirt")
+            ])),
+            Ok(LevelType::Flat(vec![("minecraft:dirt".to_string(), 2)]))
+        );
+
+        let err = LevelType::from_config(&config(&[("level-type", "amplified")])).unwrap_err();
+        assert_eq!(err.name, "level-type");
+        assert_eq!(err.value, "amplified");
+        let err =
+            LevelType::from_config(&config(&[("level-type", "flat"), ("flat-layers", "a*b")]))
+                .unwrap_err();
+        assert_eq!(err.name, "flat-layers");
+    }
+
+    #[test]
+    fn difficulty_names() {
+        assert_eq!(Difficulty::from_name("Hard"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("0"), Some(Difficulty::Peaceful));
+        assert_eq!(Difficulty::from_name("4"), None);
+    }
+}
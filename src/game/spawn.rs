@@ -0,0 +1,274 @@
+/// game/spawn.rs
+/// =============
+///
+/// Getting a player from "logged in" to standing in the world:
+///
+///     server: StartGame, BiomeDefinitionList, AvailableEntityIdentifiers,
+///             CreativeContent
+///     client: RequestChunkRadius
+///     server: ChunkRadiusUpdate, the chunks around spawn,
+///             PlayStatus(PlayerSpawn)
+///     client: SetLocalPlayerAsInitialized
+///
+/// The client can't do anything with a packet from further down the list
+/// before the ones above it, so the connection goes through these in order
+/// while it's Spawning (see `SpawnState`).
+///
+/// Reference: https://github.com/pmmp/PocketMine-MP (src/network/mcpe/handler/PreSpawnPacketHandler.php)
+use crate::nbt::{Nbt, Tag};
+use crate::protocol::v622::{
+    AvailableEntityIdentifiers, BiomeDefinitionList, BlockCoordinates, CreativeContent,
+    EducationSharedResourceURI, GameMode, PermissionLevel, StartGame,
+    StartGameChatRestrictionLevel, StartGameDimension, StartGameEditorWorldType,
+    StartGameMovementAuthority, Vec2f, Vec3f,
+};
+use crate::raknet::enums::Gamemode;
+use crate::server::VoxelServer;
+
+use super::block::BlockPalette;
+use super::item::ItemPalette;
+use super::settings::{LevelSettings, LevelType};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpawnState {
+    /// StartGame's out, the client asks for a chunk radius next
+    WaitingForRadius,
+    /// The chunks around spawn are being sent, PlayerSpawn's next
+    SendingChunks,
+    /// PlayerSpawn's out, the client says when it's ready
+    WaitingForInitialized,
+}
+
+/// `spawn` and `current_tick` are the world's
+pub fn start_game(
+    settings: &LevelSettings,
+    blocks: &BlockPalette,
+    items: &ItemPalette,
+    entity_id: i64,
+    spawn: (i32, i32, i32),
+    current_tick: u64,
+) -> StartGame {
+    let (x, y, z) = spawn;
+    let gamemode = game_mode(settings.gamemode);
+
+    StartGame {
+        entity_id,
+        runtime_entity_id: entity_id as u64,
+        player_gamemode: gamemode,
+        player_position: Vec3f {
+            x: x as f32 + 0.5,
+            y: y as f32 + 1.62,
+            z: z as f32 + 0.5,
+        },
+        rotation: Vec2f { x: 0.0, z: 0.0 },
+        seed: settings.seed,
+        biome_type: 0,
+        biome_name: "plains".to_string(),
+        dimension: StartGameDimension::Overworld,
+        generator: generator_id(&settings.level_type),
+        world_gamemode: gamemode,
+        difficulty: settings.difficulty as i32,
+        spawn_position: BlockCoordinates { x, y, z },
+        // cheats turn achievements off in vanilla too
+        achievements_disabled: settings.allow_cheats,
+        editor_world_type: StartGameEditorWorldType::NotEditor,
+        created_in_editor: false,
+        exported_from_editor: false,
+        day_cycle_stop_time: -1,
+        edu_offer: 0,
+        edu_features_enabled: false,
+        edu_product_uuid: String::new(),
+        rain_level: 0.0,
+        lightning_level: 0.0,
+        has_confirmed_platform_locked_content: false,
+        is_multiplayer: true,
+        broadcast_to_lan: true,
+        // public
+        xbox_live_broadcast_mode: 4,
+        platform_broadcast_mode: 4,
+        enable_commands: settings.allow_cheats,
+        is_texturepacks_required: settings.texturepack_required,
+        gamerules: vec![],
+        experiments: vec![],
+        experiments_previously_used: false,
+        bonus_chest: false,
+        map_enabled: false,
+        permission_level: PermissionLevel::Member,
+        server_chunk_tick_range: settings.tick_distance,
+        has_locked_behavior_pack: false,
+        has_locked_resource_pack: false,
+        is_from_locked_world_template: false,
+        msa_gamertags_only: false,
+        is_from_world_template: false,
+        is_world_template_option_locked: false,
+        only_spawn_v1_villagers: false,
+        persona_disabled: false,
+        custom_skins_disabled: false,
+        emote_chat_muted: false,
+        game_version: VoxelServer::VERSION.to_string(),
+        limited_world_width: 0,
+        limited_world_length: 0,
+        is_new_nether: true,
+        edu_resource_uri: EducationSharedResourceURI {
+            button_name: String::new(),
+            link_uri: String::new(),
+        },
+        experimental_gameplay_override: false,
+        chat_restriction_level: StartGameChatRestrictionLevel::None,
+        disable_player_interactions: false,
+        level_id: String::new(),
+        world_name: settings.level_name.clone(),
+        premium_world_template_id: String::new(),
+        is_trial: false,
+        movement_authority: StartGameMovementAuthority::Client,
+        rewind_history_size: 0,
+        server_authoritative_block_breaking: false,
+        current_tick: current_tick as i64,
+        enchantment_seed: 0,
+        block_properties: vec![],
+        itemstates: items.itemstates(),
+        multiplayer_correlation_id: String::new(),
+        server_authoritative_inventory: false,
+        engine: VoxelServer::VERSION.to_string(),
+        property_data: Nbt::new(Tag::Compound(Default::default())),
+        block_pallette_checksum: 0,
+        world_template_id: 0,
+        client_side_generation: false,
+        block_network_ids_are_hashes: blocks.hashed_ids,
+        server_controlled_sound: false,
+    }
+}
+
+/// See biome.rs
+pub fn biome_definitions(definitions: &Nbt) -> BiomeDefinitionList {
+    BiomeDefinitionList {
+        nbt: definitions.clone(),
+    }
+}
+
+/// Entities the client is allowed to see, which for now is just players
+pub fn entity_identifiers() -> AvailableEntityIdentifiers {
+    let player: Tag = [
+        ("id", Tag::from("minecraft:player")),
+        ("bid", Tag::from("")),
+        ("rid", Tag::Int(1)),
+        ("hasspawnegg", false.into()),
+        ("summonable", false.into()),
+        ("experimental", false.into()),
+    ]
+    .into_iter()
+    .collect();
+
+    AvailableEntityIdentifiers {
+        nbt: Nbt::new([("idlist", Tag::List(vec![player]))].into_iter().collect()),
+    }
+}
+
+/// See item.rs
+pub fn creative_content(items: &ItemPalette, blocks: &BlockPalette) -> CreativeContent {
+    CreativeContent {
+        items: items.creative_items(blocks),
+    }
+}
+
+/// Vanilla's generator ids, which the client uses for things like where
+/// the horizon is
+fn generator_id(level_type: &LevelType) -> i32 {
+    match level_type {
+        LevelType::Default => 1,
+        LevelType::Flat(_) => 2,
+        LevelType::Void => 5,
+    }
+}
+
+fn game_mode(gamemode: Gamemode) -> GameMode {
+    match gamemode {
+        Gamemode::Survival => GameMode::Survival,
+        Gamemode::Creative => GameMode::Creative,
+        Gamemode::Adventure => GameMode::Adventure,
+        Gamemode::Spectator => GameMode::Spectator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game::generator::{Flat, Overworld, WorldGenerator};
+    use crate::game::settings::Difficulty;
+
+    fn settings(level_type: LevelType) -> LevelSettings {
+        LevelSettings {
+            level_name: "spawn".to_string(),
+            seed: 1,
+            gamemode: Gamemode::Survival,
+            difficulty: Difficulty::Easy,
+            allow_cheats: false,
+            texturepack_required: false,
+            view_distance: 8,
+            tick_distance: 4,
+            sub_chunk_requests: false,
+            level_type,
+        }
+    }
+
+    #[test]
+    fn start_game() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let items = ItemPalette::load(Path::new("no-such-file"));
+        let start = |level_type| {
+            super::start_game(
+                &settings(level_type),
+                &blocks,
+                &items,
+                7,
+                (10, -60, 20),
+                1234,
+            )
+        };
+
+        let packet = start(LevelType::Default);
+        assert_eq!(packet.generator, 1);
+        assert_eq!(packet.itemstates, items.itemstates());
+        assert_eq!(packet.current_tick, 1234);
+        assert_eq!(
+            packet.spawn_position,
+            BlockCoordinates {
+                x: 10,
+                y: -60,
+                z: 20
+            }
+        );
+        // eyes, in the middle of the block
+        assert_eq!(
+            packet.player_position,
+            Vec3f {
+                x: 10.5,
+                y: -58.38,
+                z: 20.5
+            }
+        );
+
+        assert_eq!(start(LevelType::Flat(vec![])).generator, 2);
+        assert_eq!(start(LevelType::Void).generator, 5);
+    }
+
+    #[test]
+    fn spawn_height() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+
+        let layers = [
+            ("minecraft:bedrock".to_string(), 1),
+            ("minecraft:dirt".to_string(), 3),
+        ];
+        assert_eq!(Flat::new(&layers, &blocks).spawn_height(5, 5), Some(-60));
+        assert_eq!(Flat::new(&[], &blocks).spawn_height(0, 0), None);
+
+        // never under water
+        let overworld = Overworld::new(1, &blocks);
+        for x in (-2000..2000).step_by(100) {
+            assert!(overworld.spawn_height(x, x).unwrap() >= 63);
+        }
+    }
+}
@@ -0,0 +1,613 @@
+/// game/world.rs
+/// =============
+///
+/// The chunks everyone's playing in, each dimension's separately (see
+/// dimension.rs). Chunks are loaded the first time someone needs them and
+/// shared from then on, players hold on to them through an `Arc` while
+/// they're being sent. Chunks that have never been saved are generated
+/// (generator/), which takes a while, so until they're done asking for
+/// them gets nothing.
+///
+/// Anything that changes a chunk marks it dirty, and dirty chunks are
+/// written back to the world on disk (level_db.rs) when it's saved,
+/// except ones with blocks we don't know, which are never written. After
+/// a save, chunks nobody can see any more are unloaded.
+/// Blocks changing are also kept track of until the next tick, so the
+/// players who can see them can be told.
+///
+/// Chunks near players are ticked too, see block_tick.rs.
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+use log::{debug, info, warn};
+use rand::Rng;
+
+use super::block::BlockPalette;
+use super::block_tick::BlockBehaviours;
+use super::chunk::{Chunk, ScheduledTick};
+use super::dimension::Dimension;
+use super::generator::GeneratorPool;
+use super::level_db::LevelDb;
+
+/// Spawn y when there's no ground to stand on (void)
+const VOID_SPAWN_Y: i32 = 64;
+
+pub type SharedWorld = Arc<RwLock<World>>;
+
+/// One dimension's chunks
+#[derive(Default)]
+struct Chunks {
+    loaded: HashMap<(i32, i32), Arc<Chunk>>,
+    /// Waiting on the generator
+    generating: HashSet<(i32, i32)>,
+    /// Changed since they were last saved
+    dirty: HashSet<(i32, i32)>,
+}
+
+/// A block that's changed, in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockUpdate {
+    pub dimension: Dimension,
+    pub position: (i32, i32, i32),
+    pub block: u32,
+}
+
+pub struct World {
+    blocks: Arc<BlockPalette>,
+    behaviours: Arc<BlockBehaviours>,
+    /// Saving doesn't need the world locked, just this
+    level_db: Arc<Mutex<LevelDb>>,
+    generator: GeneratorPool,
+    dimensions: HashMap<Dimension, Chunks>,
+    /// Since the last `take_block_updates`
+    block_updates: Vec<BlockUpdate>,
+    /// The randomTickSpeed game rule
+    random_tick_speed: u32,
+    /// Game ticks since the world was made, it's saved in level.dat
+    pub current_tick: u64,
+    /// Where new players start, in the overworld. Saved in level.dat too.
+    pub spawn: (i32, i32, i32),
+}
+
+impl World {
+    pub fn new(blocks: Arc<BlockPalette>, mut level_db: LevelDb, generator: GeneratorPool) -> Self {
+        let spawn = level_db.spawn().unwrap_or_else(|| {
+            let y = generator.spawn_height(Dimension::Overworld, 0, 0);
+            let spawn = (0, y.unwrap_or(VOID_SPAWN_Y), 0);
+            level_db.set_spawn(spawn);
+            spawn
+        });
+
+        Self {
+            spawn,
+            behaviours: Arc::new(BlockBehaviours::new(&blocks)),
+            blocks,
+            random_tick_speed: level_db.random_tick_speed(),
+            current_tick: level_db.current_tick(),
+            level_db: Arc::new(Mutex::new(level_db)),
+            generator,
+            dimensions: HashMap::new(),
+            block_updates: vec![],
+        }
+    }
+
+    pub fn blocks(&self) -> &Arc<BlockPalette> {
+        &self.blocks
+    }
+
+    fn chunks(&mut self, dimension: Dimension) -> &mut Chunks {
+        self.dimensions.entry(dimension).or_default()
+    }
+
+    /// A loaded chunk, to be changed. It's marked dirty, and anyone still
+    /// holding on to it keeps the old one.
+    fn chunk_mut(&mut self, dimension: Dimension, position: (i32, i32)) -> Option<&mut Chunk> {
+        let chunks = self.dimensions.get_mut(&dimension)?;
+        let chunk = chunks.loaded.get_mut(&position)?;
+        chunks.dirty.insert(position);
+        Some(Arc::make_mut(chunk))
+    }
+
+    /// Once every game tick. `players` are where everyone is (their
+    /// dimension and chunk), chunks within `tick_distance` of them tick.
+    pub fn tick(&mut self, players: &[(Dimension, (i32, i32))], tick_distance: i32) {
+        self.current_tick += 1;
+        self.collect_generated();
+
+        let ticking = self.ticking_chunks(players, tick_distance);
+        let behaviours = self.behaviours.clone();
+        for &(dimension, position) in &ticking {
+            self.run_scheduled_ticks(&behaviours, dimension, position);
+        }
+        if self.random_tick_speed > 0 {
+            for &(dimension, position) in &ticking {
+                self.run_random_ticks(&behaviours, dimension, position);
+            }
+        }
+    }
+
+    /// Loaded chunks in a circle around each player
+    fn ticking_chunks(
+        &self,
+        players: &[(Dimension, (i32, i32))],
+        tick_distance: i32,
+    ) -> Vec<(Dimension, (i32, i32))> {
+        let mut ticking = HashSet::new();
+
+        for &(dimension, (center_x, center_z)) in players {
+            let Some(chunks) = self.dimensions.get(&dimension) else {
+                continue;
+            };
+            for x in -tick_distance..=tick_distance {
+                for z in -tick_distance..=tick_distance {
+                    let position = (center_x + x, center_z + z);
+                    if x * x + z * z <= tick_distance * tick_distance
+                        && chunks.loaded.contains_key(&position)
+                    {
+                        ticking.insert((dimension, position));
+                    }
+                }
+            }
+        }
+
+        ticking.into_iter().collect()
+    }
+
+    /// Ticks that are due, for blocks that are still the ones that asked
+    fn run_scheduled_ticks(
+        &mut self,
+        behaviours: &BlockBehaviours,
+        dimension: Dimension,
+        position: (i32, i32),
+    ) {
+        let current_tick = self.current_tick;
+        let due = |tick: &ScheduledTick| tick.tick <= current_tick;
+
+        let loaded = self
+            .dimensions
+            .get(&dimension)
+            .and_then(|c| c.loaded.get(&position));
+        if !loaded.is_some_and(|chunk| chunk.scheduled_ticks.iter().any(due)) {
+            return;
+        }
+        let Some(chunk) = self.chunk_mut(dimension, position) else {
+            return;
+        };
+        let (ready, later): (Vec<_>, Vec<_>) = std::mem::take(&mut chunk.scheduled_ticks)
+            .into_iter()
+            .partition(due);
+        chunk.scheduled_ticks = later;
+
+        for tick in ready {
+            if self.block(dimension, tick.position) == Some(tick.block) {
+                behaviours.scheduled_tick(self, dimension, tick.position, tick.block);
+            }
+        }
+    }
+
+    /// randomTickSpeed blocks in each sub chunk
+    fn run_random_ticks(
+        &mut self,
+        behaviours: &BlockBehaviours,
+        dimension: Dimension,
+        position: (i32, i32),
+    ) {
+        let Some(chunk) = self
+            .dimensions
+            .get(&dimension)
+            .and_then(|c| c.loaded.get(&position))
+        else {
+            return;
+        };
+        // the palette says whether there's anything in a sub chunk that
+        // ticks, which is hardly ever. Only the first layer's looked at,
+        // the second's only ever water (waterlogging) which doesn't tick.
+        let sub_chunks: Vec<i32> = chunk
+            .sections()
+            .filter(|y_index| {
+                chunk
+                    .sub_chunk(*y_index)
+                    .and_then(|s| s.layers().first())
+                    .is_some_and(|l| l.palette().iter().any(|b| behaviours.random_ticks(*b)))
+            })
+            .collect();
+
+        let mut rng = rand::thread_rng();
+        for y_index in sub_chunks {
+            for _ in 0..self.random_tick_speed {
+                let block_position = (
+                    position.0 * 16 + rng.gen_range(0..16),
+                    y_index * 16 + rng.gen_range(0..16),
+                    position.1 * 16 + rng.gen_range(0..16),
+                );
+                match self.block(dimension, block_position) {
+                    Some(block) if behaviours.random_ticks(block) => {
+                        behaviours.random_tick(self, dimension, block_position, block)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// `None` if its chunk isn't loaded
+    pub fn block(&self, dimension: Dimension, (x, y, z): (i32, i32, i32)) -> Option<u32> {
+        let chunk = self
+            .dimensions
+            .get(&dimension)?
+            .loaded
+            .get(&(x >> 4, z >> 4))?;
+        Some(chunk.block((x & 15) as u8, y, (z & 15) as u8))
+    }
+
+    /// Only in loaded chunks, false if it isn't. Players are told about
+    /// it next tick.
+    pub fn set_block(
+        &mut self,
+        dimension: Dimension,
+        position: (i32, i32, i32),
+        block: u32,
+    ) -> bool {
+        let (x, y, z) = position;
+        let Some(chunk) = self.chunk_mut(dimension, (x >> 4, z >> 4)) else {
+            return false;
+        };
+        if y < chunk.min_y() || y >= chunk.max_y() {
+            return false;
+        }
+        chunk.set_block((x & 15) as u8, y, (z & 15) as u8, block);
+
+        self.block_updates.push(BlockUpdate {
+            dimension,
+            position,
+            block,
+        });
+        true
+    }
+
+    /// Every block that's changed since last time
+    pub fn take_block_updates(&mut self) -> Vec<BlockUpdate> {
+        std::mem::take(&mut self.block_updates)
+    }
+
+    /// Ticks the block `delay` ticks from now, if it's still the same
+    /// block then
+    pub fn schedule_tick(&mut self, dimension: Dimension, position: (i32, i32, i32), delay: u64) {
+        let Some(block) = self.block(dimension, position) else {
+            return;
+        };
+        let tick = self.current_tick + delay;
+        let (x, _, z) = position;
+        if let Some(chunk) = self.chunk_mut(dimension, (x >> 4, z >> 4)) {
+            chunk.scheduled_ticks.push(ScheduledTick {
+                position,
+                block,
+                tick,
+            });
+        }
+    }
+
+    pub fn has_scheduled_tick(&self, dimension: Dimension, position: (i32, i32, i32)) -> bool {
+        let (x, _, z) = position;
+        self.dimensions
+            .get(&dimension)
+            .and_then(|c| c.loaded.get(&(x >> 4, z >> 4)))
+            .is_some_and(|chunk| chunk.scheduled_ticks.iter().any(|t| t.position == position))
+    }
+
+    /// Loads the chunk if nobody's needed it yet, `None` while it's being
+    /// generated
+    pub fn chunk(&mut self, dimension: Dimension, x: i32, z: i32) -> Option<Arc<Chunk>> {
+        let chunks = self.chunks(dimension);
+        if let Some(chunk) = chunks.loaded.get(&(x, z)) {
+            return Some(chunk.clone());
+        }
+        if chunks.generating.contains(&(x, z)) {
+            return None;
+        }
+
+        let (air, biome) = (self.blocks.air, dimension.default_biome());
+        let mut chunk = Chunk::new(x, z, dimension.sections(), air, biome);
+        let loaded = self
+            .level_db
+            .lock()
+            .unwrap()
+            .load_chunk(dimension, &mut chunk, &self.blocks);
+        match loaded {
+            Ok(true) if chunk.read_only => {
+                warn!("Chunk {x}, {z} in {dimension} has blocks we don't know, it won't be saved")
+            }
+            Ok(true) => {}
+            Ok(false) => {
+                self.chunks(dimension).generating.insert((x, z));
+                self.generator.generate(dimension, chunk);
+                return None;
+            }
+            // whatever could be read is shown, but never saved over
+            // what's there
+            Err(e) => {
+                warn!("Couldn't load chunk {x}, {z} in {dimension}: {e}");
+                chunk.read_only = true;
+            }
+        }
+
+        let chunk = Arc::new(chunk);
+        self.chunks(dimension).loaded.insert((x, z), chunk.clone());
+        Some(chunk)
+    }
+
+    /// Generated chunks are picked up once a tick, and saved like changed
+    /// ones
+    fn collect_generated(&mut self) {
+        for (dimension, chunk) in self.generator.finished() {
+            let position = (chunk.x, chunk.z);
+            let chunks = self.chunks(dimension);
+            chunks.generating.remove(&position);
+            chunks.loaded.insert(position, Arc::new(chunk));
+            chunks.dirty.insert(position);
+        }
+    }
+
+    /// Replaces a chunk, it's saved with the rest next time
+    pub fn set_chunk(&mut self, dimension: Dimension, chunk: Chunk) {
+        let position = (chunk.x, chunk.z);
+        let chunks = self.chunks(dimension);
+        chunks.loaded.insert(position, Arc::new(chunk));
+        chunks.dirty.insert(position);
+    }
+
+    /// Writes every changed chunk and level.dat. The world's only locked
+    /// to pick out what's changed, the chunks are shared so they can be
+    /// written without it.
+    pub fn save(world: &SharedWorld) {
+        let (dirty, level_db, blocks, current_tick) = {
+            let mut world = world.write().unwrap();
+            let mut dirty = vec![];
+            for (dimension, chunks) in &mut world.dimensions {
+                for position in std::mem::take(&mut chunks.dirty) {
+                    // it'd be written back without the blocks we don't know
+                    if let Some(chunk) = chunks.loaded.get(&position).filter(|c| !c.read_only) {
+                        dirty.push((*dimension, chunk.clone()));
+                    }
+                }
+            }
+            let level_db = world.level_db.clone();
+            (dirty, level_db, world.blocks.clone(), world.current_tick)
+        };
+
+        let saved = {
+            let mut level_db = level_db.lock().unwrap();
+            let saving = dirty
+                .iter()
+                .map(|(dimension, chunk)| (*dimension, &**chunk));
+            let saved = level_db.save_chunks(saving, &blocks, current_tick);
+
+            level_db.set_current_tick(current_tick);
+            if let Err(e) = level_db.save_level_dat() {
+                warn!("Couldn't save level.dat: {e}");
+            }
+            saved
+        };
+
+        match saved {
+            Ok(()) if !dirty.is_empty() => info!("Saved {} chunks", dirty.len()),
+            Ok(()) => {}
+            Err(e) => {
+                warn!("Couldn't save chunks: {e}");
+                // the database is unlocked by now, loading a chunk locks
+                // the world then it, never the other way round
+                let mut world = world.write().unwrap();
+                for (dimension, chunk) in dirty {
+                    world.chunks(dimension).dirty.insert((chunk.x, chunk.z));
+                }
+            }
+        }
+    }
+
+    /// Drops the chunks `in_view` says nobody can see. Ones with changes
+    /// that haven't been saved yet stay until they have been.
+    pub fn unload(&mut self, in_view: impl Fn(Dimension, (i32, i32)) -> bool) {
+        let mut unloaded = 0;
+        for (dimension, chunks) in &mut self.dimensions {
+            let before = chunks.loaded.len();
+            chunks.loaded.retain(|position, _| {
+                chunks.dirty.contains(position) || in_view(*dimension, *position)
+            });
+            unloaded += before - chunks.loaded.len();
+        }
+
+        if unloaded > 0 {
+            debug!("Unloaded {unloaded} chunks");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::game::block::BlockState;
+    use crate::game::generator;
+    use crate::game::settings::{Difficulty, LevelSettings, LevelType};
+    use crate::leveldb::test_dir;
+    use crate::nbt::Tag;
+    use crate::raknet::enums::Gamemode;
+
+    /// The built in blocks and fire of every age
+    fn blocks() -> BlockPalette {
+        let builtin = BlockPalette::load(Path::new("no-such-file"), true);
+        let mut states: Vec<BlockState> = (0..builtin.len() as u32)
+            .filter_map(|id| builtin.state(id).cloned())
+            .collect();
+        for age in 0..=15 {
+            let age = [("age".to_string(), Tag::Int(age))].into_iter().collect();
+            states.push(BlockState::new("minecraft:fire", age));
+        }
+        BlockPalette::new(states, true)
+    }
+
+    fn fire(blocks: &BlockPalette, age: i32) -> u32 {
+        let fire = blocks.default_state("fire").unwrap();
+        blocks.with_property(fire, "age", Tag::Int(age)).unwrap()
+    }
+
+    /// Opening it again is loading what was saved
+    fn world(name: &str, blocks: BlockPalette, fresh: bool) -> SharedWorld {
+        let level = LevelSettings {
+            level_name: name.to_string(),
+            seed: 1,
+            gamemode: Gamemode::Creative,
+            difficulty: Difficulty::Normal,
+            allow_cheats: false,
+            texturepack_required: false,
+            view_distance: 8,
+            tick_distance: 4,
+            sub_chunk_requests: false,
+            level_type: LevelType::Void,
+        };
+        let blocks = Arc::new(blocks);
+        let dir = if fresh {
+            test_dir(name)
+        } else {
+            std::env::temp_dir().join(format!("voxel-test-{name}"))
+        };
+        let level_db = LevelDb::open(&dir, &level).unwrap();
+        let generator = GeneratorPool::new(
+            generator::from_settings(&level.level_type, level.seed, &blocks),
+            1,
+        );
+
+        let mut world = World::new(blocks, level_db, generator);
+        // so they don't get in the way of scheduled ones
+        world.random_tick_speed = 0;
+        Arc::new(RwLock::new(world))
+    }
+
+    /// A chunk with a block in it, already loaded
+    fn set_chunk(world: &SharedWorld, position: (i32, i32), block: u32) {
+        let mut world = world.write().unwrap();
+        let dimension = Dimension::Overworld;
+        let mut chunk = Chunk::new(
+            position.0,
+            position.1,
+            dimension.sections(),
+            world.blocks.air,
+            dimension.default_biome(),
+        );
+        chunk.set_block(0, 0, 0, block);
+        world.set_chunk(dimension, chunk);
+    }
+
+    #[test]
+    fn unload_after_save() {
+        let blocks = BlockPalette::load(Path::new("no-such-file"), true);
+        let world = world("unload_after_save", blocks, true);
+        let stone = world.read().unwrap().blocks.default_state("stone").unwrap();
+        set_chunk(&world, (0, 0), stone);
+        set_chunk(&world, (1, 0), stone);
+        let loaded = |world: &SharedWorld| {
+            let world = world.read().unwrap();
+            let mut loaded: Vec<_> = world.dimensions[&Dimension::Overworld]
+                .loaded
+                .keys()
+                .copied()
+                .collect();
+            loaded.sort();
+            loaded
+        };
+
+        // not until they're saved
+        world.write().unwrap().unload(|_, _| false);
+        assert_eq!(loaded(&world), [(0, 0), (1, 0)]);
+
+        World::save(&world);
+        world.write().unwrap().unload(|_, chunk| chunk == (1, 0));
+        assert_eq!(loaded(&world), [(1, 0)]);
+
+        // and it comes back from disk as it was
+        let mut world = world.write().unwrap();
+        let chunk = world.chunk(Dimension::Overworld, 0, 0).unwrap();
+        assert_eq!(chunk.block(0, 0, 0), stone);
+    }
+
+    /// Ticks the world with a player at 0, 0
+    fn tick(world: &SharedWorld, ticks: u64) {
+        for _ in 0..ticks {
+            world
+                .write()
+                .unwrap()
+                .tick(&[(Dimension::Overworld, (0, 0))], 2);
+        }
+    }
+
+    #[test]
+    fn scheduled_ticks() {
+        let world = world("scheduled_ticks", blocks(), true);
+        let blocks = world.read().unwrap().blocks.clone();
+        set_chunk(&world, (0, 0), blocks.air);
+        let (a, b, c) = ((1, 0, 1), (2, 0, 2), (3, 0, 3));
+        {
+            let mut world = world.write().unwrap();
+            for position in [a, b, c] {
+                world.set_block(Dimension::Overworld, position, fire(&blocks, 0));
+            }
+            world.schedule_tick(Dimension::Overworld, a, 5);
+            world.schedule_tick(Dimension::Overworld, b, 3);
+            world.schedule_tick(Dimension::Overworld, c, 3);
+            // c's tick is for the fire that was there
+            world.set_block(Dimension::Overworld, c, fire(&blocks, 10));
+        }
+        let block = |position| world.read().unwrap().block(Dimension::Overworld, position);
+
+        tick(&world, 2);
+        assert_eq!(block(a), Some(fire(&blocks, 0)));
+        assert_eq!(block(b), Some(fire(&blocks, 0)));
+
+        // soonest first, each on the tick it's due
+        tick(&world, 1);
+        assert_eq!(block(a), Some(fire(&blocks, 0)));
+        assert_eq!(block(b), Some(fire(&blocks, 1)));
+        assert_eq!(block(c), Some(fire(&blocks, 10)));
+
+        tick(&world, 2);
+        assert_eq!(block(a), Some(fire(&blocks, 1)));
+
+        // fire asks again each time, c's was dropped
+        let world = world.read().unwrap();
+        assert!(world.has_scheduled_tick(Dimension::Overworld, a));
+        assert!(world.has_scheduled_tick(Dimension::Overworld, b));
+        assert!(!world.has_scheduled_tick(Dimension::Overworld, c));
+    }
+
+    #[test]
+    fn scheduled_ticks_saved() {
+        let scheduled = {
+            let world = world("scheduled_ticks_saved", blocks(), true);
+            let blocks = world.read().unwrap().blocks.clone();
+            set_chunk(&world, (0, 0), blocks.air);
+            tick(&world, 7);
+            {
+                let mut world = world.write().unwrap();
+                world.set_block(Dimension::Overworld, (1, 0, 1), fire(&blocks, 3));
+                world.set_block(Dimension::Overworld, (-4, 0, -4), fire(&blocks, 0));
+                world.schedule_tick(Dimension::Overworld, (1, 0, 1), 40);
+                world.schedule_tick(Dimension::Overworld, (-4, 0, -4), 1);
+            }
+            World::save(&world);
+
+            let mut world = world.write().unwrap();
+            let chunk = world.chunk(Dimension::Overworld, 0, 0).unwrap();
+            chunk.scheduled_ticks.clone()
+        };
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].tick, 47);
+
+        let world = world("scheduled_ticks_saved", blocks(), false);
+        let mut world = world.write().unwrap();
+        assert_eq!(world.current_tick, 7);
+        let chunk = world.chunk(Dimension::Overworld, 0, 0).unwrap();
+        assert_eq!(chunk.scheduled_ticks, scheduled);
+        assert_eq!(chunk.block(1, 0, 1), fire(world.blocks(), 3));
+    }
+}
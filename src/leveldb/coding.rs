@@ -0,0 +1,164 @@
+/// leveldb/coding.rs
+/// =================
+///
+/// The few ways LevelDB stores numbers: fixed width little endian ints,
+/// and unsigned varints (7 bits at a time, the same as the protocol's).
+/// Decoding works on slices and moves them along, anything that runs out
+/// of bytes is `None` and the caller calls it corrupt.
+///
+/// Also the CRC-32C (Castagnoli) every record and block is checked with,
+/// "masked" before it's stored because CRCs of data that has CRCs in it
+/// apparently go wrong.
+///
+/// Reference: https://github.com/google/leveldb (util/coding.h, util/crc32c.h)
+use crate::raknet::objects::datatypes::to_u64_varint_bytes;
+
+pub fn put_fixed32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_fixed64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_varint(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&to_u64_varint_bytes(value));
+}
+
+/// Varint length, then the bytes
+pub fn put_length_prefixed(buf: &mut Vec<u8>, data: &[u8]) {
+    put_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+pub fn get_bytes<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Some(bytes)
+}
+
+pub fn get_fixed32(input: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(get_bytes(input, 4)?.try_into().ok()?))
+}
+
+pub fn get_fixed64(input: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(get_bytes(input, 8)?.try_into().ok()?))
+}
+
+pub fn get_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for i in 0..10 {
+        let byte = *get_bytes(input, 1)?.first()?;
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+pub fn get_length_prefixed<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = get_varint(input)?;
+    get_bytes(input, usize::try_from(len).ok()?)
+}
+
+const CRC32C_POLY: u32 = 0x82f63b78;
+const MASK_DELTA: u32 = 0xa282ead8;
+
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32C of everything in `parts`, one after the other
+pub fn crc32c(parts: &[&[u8]]) -> u32 {
+    let crc = parts
+        .iter()
+        .flat_map(|p| p.iter())
+        .fold(!0u32, |crc, byte| {
+            CRC32C_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+        });
+
+    !crc
+}
+
+pub fn mask_crc(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(MASK_DELTA)
+}
+
+pub fn unmask_crc(masked: u32) -> u32 {
+    masked.wrapping_sub(MASK_DELTA).rotate_left(15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_round_trip() {
+        let varints = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut buf = vec![];
+        put_fixed32(&mut buf, 0xdeadbeef);
+        put_fixed64(&mut buf, u64::MAX - 1);
+        for value in varints {
+            put_varint(&mut buf, value);
+        }
+        put_length_prefixed(&mut buf, b"hello");
+
+        let mut input = &buf[..];
+        assert_eq!(get_fixed32(&mut input), Some(0xdeadbeef));
+        assert_eq!(get_fixed64(&mut input), Some(u64::MAX - 1));
+        for value in varints {
+            assert_eq!(get_varint(&mut input), Some(value));
+        }
+        assert_eq!(get_length_prefixed(&mut input), Some(&b"hello"[..]));
+        assert!(input.is_empty());
+        assert_eq!(get_fixed32(&mut input), None);
+    }
+
+    #[test]
+    fn layouts() {
+        let mut buf = vec![];
+        put_fixed32(&mut buf, 0x04030201);
+        put_varint(&mut buf, 300);
+        assert_eq!(buf, [1, 2, 3, 4, 0xac, 0x02]);
+
+        // a length that runs past the end
+        assert_eq!(get_length_prefixed(&mut &[5, b'a'][..]), None);
+        assert_eq!(get_varint(&mut &[0x80][..]), None);
+    }
+
+    #[test]
+    fn crc32c_known_values() {
+        // the usual check value, then util/crc32c_test.cc's
+        assert_eq!(crc32c(&[b"123456789"]), 0xe3069283);
+        assert_eq!(crc32c(&[&[0; 32]]), 0x8a9136aa);
+        assert_eq!(crc32c(&[&[0xff; 32]]), 0x62a8ab43);
+        assert_eq!(crc32c(&[b"hello ", b"world"]), crc32c(&[b"hello world"]));
+
+        let crc = crc32c(&[b"foo"]);
+        assert_ne!(mask_crc(crc), crc);
+        assert_eq!(unmask_crc(mask_crc(crc)), crc);
+    }
+}
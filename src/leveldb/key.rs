@@ -0,0 +1,97 @@
+/// leveldb/key.rs
+/// ==============
+///
+/// Tables don't store our keys as they are, every key gets 8 bytes on the
+/// end: the sequence number of the write (which goes up with every write)
+/// and whether it's a value or a deletion, as `sequence << 8 | kind`.
+///
+/// They're sorted by key, and the newest write first for the same key,
+/// so the first entry at or after `(key, newest possible)` is the one
+/// that counts.
+use std::cmp::Ordering;
+
+pub const DELETION: u8 = 0;
+pub const VALUE: u8 = 1;
+
+pub const MAX_SEQUENCE: u64 = (1 << 56) - 1;
+
+pub fn internal_key(user_key: &[u8], sequence: u64, kind: u8) -> Vec<u8> {
+    let mut key = Vec::with_capacity(user_key.len() + 8);
+    key.extend_from_slice(user_key);
+    key.extend_from_slice(&(sequence << 8 | kind as u64).to_le_bytes());
+    key
+}
+
+/// Sorts before any write of `user_key`
+pub fn lookup_key(user_key: &[u8]) -> Vec<u8> {
+    internal_key(user_key, MAX_SEQUENCE, VALUE)
+}
+
+/// The key, sequence number and kind
+pub fn split(key: &[u8]) -> Option<(&[u8], u64, u8)> {
+    let split_at = key.len().checked_sub(8)?;
+    let (user_key, tag) = key.split_at(split_at);
+    let tag = u64::from_le_bytes(tag.try_into().ok()?);
+
+    Some((user_key, tag >> 8, tag as u8))
+}
+
+pub fn user_key(key: &[u8]) -> &[u8] {
+    &key[..key.len().saturating_sub(8)]
+}
+
+fn tag(key: &[u8]) -> u64 {
+    key.len()
+        .checked_sub(8)
+        .map_or(0, |i| u64::from_le_bytes(key[i..].try_into().unwrap()))
+}
+
+pub fn compare(a: &[u8], b: &[u8]) -> Ordering {
+    // newest first
+    user_key(a)
+        .cmp(user_key(b))
+        .then_with(|| tag(b).cmp(&tag(a)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_round_trip() {
+        let key = internal_key(b"abc", 5, VALUE);
+        assert_eq!(key.len(), 11);
+        assert_eq!(split(&key), Some((&b"abc"[..], 5, VALUE)));
+        assert_eq!(user_key(&key), b"abc");
+        assert_eq!(split(b"short"), None);
+    }
+
+    #[test]
+    fn newest_first() {
+        let mut keys = [
+            internal_key(b"b", 1, VALUE),
+            internal_key(b"a", 1, VALUE),
+            internal_key(b"ab", 3, VALUE),
+            internal_key(b"a", 7, DELETION),
+        ];
+        keys.sort_by(|a, b| compare(a, b));
+
+        let sorted: Vec<_> = keys
+            .iter()
+            .map(|k| split(k).map(|(k, sequence, _)| (k.to_vec(), sequence)))
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                Some((b"a".to_vec(), 7)),
+                Some((b"a".to_vec(), 1)),
+                Some((b"ab".to_vec(), 3)),
+                Some((b"b".to_vec(), 1)),
+            ]
+        );
+
+        let lookup = lookup_key(b"a");
+        assert_eq!(compare(&lookup, &keys[0]), Ordering::Less);
+        assert_eq!(compare(&lookup_key(b"ab"), &keys[1]), Ordering::Greater);
+    }
+}
@@ -0,0 +1,184 @@
+/// leveldb/log.rs
+/// ==============
+///
+/// The log format, used for both the write-ahead log (every write goes
+/// here first, so nothing's lost if we die before it makes it into a
+/// table) and the MANIFEST (which tables make up the database).
+///
+/// A file is 32KiB blocks of records. Records never straddle a block,
+/// anything that doesn't fit is split into fragments:
+///
+///     u32   masked CRC-32C of the type and data
+///     u16   length of data
+///     u8    type (1 full, 2 first, 3 middle, 4 last)
+///     ...   data
+///
+/// and a block with less than a header's worth left is padded with zeros.
+///
+/// Reference: https://github.com/google/leveldb (doc/log_format.md)
+use std::fs::File;
+use std::io::{self, Write};
+
+use super::coding::{crc32c, mask_crc, unmask_crc};
+
+const BLOCK_SIZE: usize = 32768;
+const HEADER_SIZE: usize = 7;
+
+const FULL: u8 = 1;
+const FIRST: u8 = 2;
+const MIDDLE: u8 = 3;
+const LAST: u8 = 4;
+
+pub struct LogWriter {
+    file: File,
+    /// How far into the current block we are
+    block_offset: usize,
+}
+
+impl LogWriter {
+    /// `file` should be empty
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            block_offset: 0,
+        }
+    }
+
+    pub fn add_record(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let mut out = Vec::with_capacity(data.len() + HEADER_SIZE);
+        let mut first = true;
+
+        loop {
+            let left = BLOCK_SIZE - self.block_offset;
+            if left < HEADER_SIZE {
+                out.resize(out.len() + left, 0);
+                self.block_offset = 0;
+                continue;
+            }
+
+            let len = data.len().min(left - HEADER_SIZE);
+            let (fragment, rest) = data.split_at(len);
+            let kind = match (first, rest.is_empty()) {
+                (true, true) => FULL,
+                (true, false) => FIRST,
+                (false, false) => MIDDLE,
+                (false, true) => LAST,
+            };
+
+            out.extend_from_slice(&mask_crc(crc32c(&[&[kind], fragment])).to_le_bytes());
+            out.extend_from_slice(&(len as u16).to_le_bytes());
+            out.push(kind);
+            out.extend_from_slice(fragment);
+            self.block_offset += HEADER_SIZE + len;
+
+            data = rest;
+            first = false;
+            if data.is_empty() {
+                break;
+            }
+        }
+
+        self.file.write_all(&out)
+    }
+
+    /// Makes sure it's actually on disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Every record in a log file. A torn or corrupt record ends it, which
+/// is what a crash in the middle of a write looks like, so whatever came
+/// before is all there is. The flag's whether it ended cleanly.
+pub fn read_records(data: &[u8]) -> (Vec<Vec<u8>>, bool) {
+    let mut records = vec![];
+    let mut partial: Option<Vec<u8>> = None;
+
+    for block in data.chunks(BLOCK_SIZE) {
+        let mut offset = 0;
+        while block.len() - offset >= HEADER_SIZE {
+            let header = &block[offset..offset + HEADER_SIZE];
+            let crc = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let len = u16::from_le_bytes(header[4..6].try_into().unwrap()) as usize;
+            let kind = header[6];
+
+            // preallocated space that was never written
+            if kind == 0 && len == 0 {
+                break;
+            }
+
+            let start = offset + HEADER_SIZE;
+            let Some(fragment) = block.get(start..start + len) else {
+                return (records, false);
+            };
+            if unmask_crc(crc) != crc32c(&[&[kind], fragment]) {
+                return (records, false);
+            }
+            offset = start + len;
+
+            match (kind, partial.as_mut()) {
+                (FULL, None) => records.push(fragment.to_vec()),
+                (FIRST, None) => partial = Some(fragment.to_vec()),
+                (MIDDLE, Some(record)) => record.extend_from_slice(fragment),
+                (LAST, Some(record)) => {
+                    record.extend_from_slice(fragment);
+                    records.extend(partial.take());
+                }
+                _ => return (records, false),
+            }
+        }
+    }
+
+    (records, partial.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(name: &str, records: &[Vec<u8>]) -> Vec<u8> {
+        let path = super::super::test_dir(name).join("000001.log");
+        let mut writer = LogWriter::new(File::create(&path).unwrap());
+        for record in records {
+            writer.add_record(record).unwrap();
+        }
+
+        fs::read(&path).unwrap()
+    }
+
+    fn records() -> Vec<Vec<u8>> {
+        vec![
+            // leaves less than a header, so the block's padded
+            vec![0; BLOCK_SIZE - HEADER_SIZE - 3],
+            b"small".to_vec(),
+            // over a few blocks
+            (0..100_000).map(|i| i as u8).collect(),
+            vec![],
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = write("log_round_trip", &records());
+        assert_eq!(&data[BLOCK_SIZE - 3..BLOCK_SIZE], [0, 0, 0]);
+        assert_eq!(read_records(&data), (records(), true));
+    }
+
+    #[test]
+    fn torn_tail() {
+        let data = write("log_torn_tail", &records());
+
+        // dying halfway through the big one
+        let (read, clean) = read_records(&data[..data.len() - 20]);
+        assert_eq!(read, records()[..2]);
+        assert!(!clean);
+
+        // or a bit flipped in the small one, which starts the second block
+        let mut data = data;
+        data[BLOCK_SIZE + HEADER_SIZE] ^= 1;
+        let (read, clean) = read_records(&data);
+        assert_eq!(read, records()[..1]);
+        assert!(!clean);
+    }
+}
@@ -0,0 +1,172 @@
+/// leveldb/manifest.rs
+/// ===================
+///
+/// The MANIFEST is a log of edits to the set of tables (added here,
+/// deleted there) and a few counters. CURRENT has the name of the one
+/// that's in use. Each edit is a list of tagged fields:
+///
+///     1  comparator name           string
+///     2  log number                varint
+///     3  next file number          varint
+///     4  last sequence number      varint
+///     5  compact pointer           level, key (we skip these)
+///     6  deleted table             level, file number
+///     7  new table                 level, file number, size, smallest
+///                                  and largest key
+///     9  previous log number       varint
+///
+/// Reference: https://github.com/google/leveldb (db/version_edit.cc)
+use super::coding::{get_length_prefixed, get_varint, put_length_prefixed, put_varint};
+
+const COMPARATOR: u64 = 1;
+const LOG_NUMBER: u64 = 2;
+const NEXT_FILE: u64 = 3;
+const LAST_SEQUENCE: u64 = 4;
+const COMPACT_POINTER: u64 = 5;
+const DELETED_FILE: u64 = 6;
+const NEW_FILE: u64 = 7;
+const PREV_LOG_NUMBER: u64 = 9;
+
+#[derive(Debug, Clone)]
+pub struct FileMeta {
+    pub number: u64,
+    pub size: u64,
+    /// Internal keys, see key.rs
+    pub smallest: Vec<u8>,
+    pub largest: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct VersionEdit {
+    pub comparator: Option<String>,
+    pub log_number: Option<u64>,
+    pub prev_log_number: Option<u64>,
+    pub next_file: Option<u64>,
+    pub last_sequence: Option<u64>,
+    /// Level and file number
+    pub deleted: Vec<(usize, u64)>,
+    pub added: Vec<(usize, FileMeta)>,
+}
+
+impl VersionEdit {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+
+        if let Some(comparator) = &self.comparator {
+            put_varint(&mut buf, COMPARATOR);
+            put_length_prefixed(&mut buf, comparator.as_bytes());
+        }
+        for (tag, value) in [
+            (LOG_NUMBER, self.log_number),
+            (PREV_LOG_NUMBER, self.prev_log_number),
+            (NEXT_FILE, self.next_file),
+            (LAST_SEQUENCE, self.last_sequence),
+        ] {
+            if let Some(value) = value {
+                put_varint(&mut buf, tag);
+                put_varint(&mut buf, value);
+            }
+        }
+        for (level, number) in &self.deleted {
+            put_varint(&mut buf, DELETED_FILE);
+            put_varint(&mut buf, *level as u64);
+            put_varint(&mut buf, *number);
+        }
+        for (level, file) in &self.added {
+            put_varint(&mut buf, NEW_FILE);
+            put_varint(&mut buf, *level as u64);
+            put_varint(&mut buf, file.number);
+            put_varint(&mut buf, file.size);
+            put_length_prefixed(&mut buf, &file.smallest);
+            put_length_prefixed(&mut buf, &file.largest);
+        }
+
+        buf
+    }
+
+    pub fn decode(mut input: &[u8]) -> Option<Self> {
+        let input = &mut input;
+        let mut edit = Self::default();
+
+        while !input.is_empty() {
+            match get_varint(input)? {
+                COMPARATOR => {
+                    let name = get_length_prefixed(input)?;
+                    edit.comparator = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                LOG_NUMBER => edit.log_number = Some(get_varint(input)?),
+                PREV_LOG_NUMBER => edit.prev_log_number = Some(get_varint(input)?),
+                NEXT_FILE => edit.next_file = Some(get_varint(input)?),
+                LAST_SEQUENCE => edit.last_sequence = Some(get_varint(input)?),
+                COMPACT_POINTER => {
+                    get_varint(input)?;
+                    get_length_prefixed(input)?;
+                }
+                DELETED_FILE => {
+                    let level = get_varint(input)? as usize;
+                    edit.deleted.push((level, get_varint(input)?));
+                }
+                NEW_FILE => {
+                    let level = get_varint(input)? as usize;
+                    edit.added.push((
+                        level,
+                        FileMeta {
+                            number: get_varint(input)?,
+                            size: get_varint(input)?,
+                            smallest: get_length_prefixed(input)?.to_vec(),
+                            largest: get_length_prefixed(input)?.to_vec(),
+                        },
+                    ));
+                }
+                _ => return None,
+            }
+        }
+
+        Some(edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_round_trip() {
+        let edit = VersionEdit {
+            comparator: Some("leveldb.BytewiseComparator".to_string()),
+            log_number: Some(9),
+            prev_log_number: Some(0),
+            next_file: Some(12),
+            last_sequence: Some(1 << 40),
+            deleted: vec![(0, 4), (1, 7)],
+            added: vec![(
+                1,
+                FileMeta {
+                    number: 11,
+                    size: 4096,
+                    smallest: b"a\x01\0\0\0\0\0\0\0".to_vec(),
+                    largest: b"z\x01\0\0\0\0\0\0\0".to_vec(),
+                },
+            )],
+        };
+
+        let decoded = VersionEdit::decode(&edit.encode()).unwrap();
+        assert_eq!(format!("{decoded:?}"), format!("{edit:?}"));
+    }
+
+    #[test]
+    fn bad_edits() {
+        // compact pointers are skipped
+        let mut buf = vec![];
+        put_varint(&mut buf, COMPACT_POINTER);
+        put_varint(&mut buf, 1);
+        put_length_prefixed(&mut buf, b"key");
+        put_varint(&mut buf, LOG_NUMBER);
+        put_varint(&mut buf, 3);
+        assert_eq!(VersionEdit::decode(&buf).unwrap().log_number, Some(3));
+
+        // a tag we don't know, and one that's cut short
+        assert!(VersionEdit::decode(&[8, 0]).is_none());
+        assert!(VersionEdit::decode(&[NEW_FILE as u8, 1, 2]).is_none());
+    }
+}
@@ -0,0 +1,726 @@
+/// leveldb/mod.rs
+/// ==============
+///
+/// Just enough LevelDB to open, read and write Bedrock worlds. Mojang
+/// use their own fork (zlib compression, see table.rs), so the usual
+/// crates can't read worlds anyway.
+///
+/// A database is a directory of:
+///
+///     CURRENT          the name of the MANIFEST in use
+///     MANIFEST-000005  which tables there are (manifest.rs)
+///     000007.log       writes that aren't in a table yet (log.rs)
+///     000006.ldb       sorted tables (table.rs)
+///     LOCK             locked by whoever has the database open
+///
+/// Writes go to the log and an in-memory table (the memtable), once
+/// that's big enough it's written out as a level 0 table. Level 0 tables
+/// can overlap, so every one of them gets checked, newest first. Once
+/// there are a few of them they're merged with the level 1 tables they
+/// overlap into new level 1 tables, which don't overlap each other.
+/// That's all the compacting we do, deeper levels are only ever read.
+/// Vanilla sorts the rest out the next time it opens the world.
+///
+/// Reference: https://github.com/google/leveldb (doc/impl.md)
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ::log::warn;
+
+mod coding;
+mod key;
+mod log;
+mod manifest;
+mod table;
+
+use self::log::{read_records, LogWriter};
+use coding::{
+    get_fixed32, get_fixed64, get_length_prefixed, put_fixed32, put_fixed64, put_length_prefixed,
+};
+use manifest::{FileMeta, VersionEdit};
+use table::{Table, TableBuilder};
+
+const COMPARATOR: &str = "leveldb.BytewiseComparator";
+const LEVELS: usize = 7;
+
+/// How big the memtable gets before it's written out
+const WRITE_BUFFER_SIZE: usize = 4 << 20;
+/// How many level 0 tables before they're merged into level 1
+const LEVEL0_COMPACTION_TRIGGER: usize = 4;
+/// Level 1 tables are split at about this size
+const MAX_FILE_SIZE: usize = 2 << 20;
+
+#[derive(Debug)]
+pub enum DbError {
+    Io(io::Error),
+    Corrupt(String),
+    /// Something else (vanilla, or another server) has it open
+    Locked,
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Io(e) => write!(f, "{e}"),
+            DbError::Corrupt(e) => write!(f, "corrupt database: {e}"),
+            DbError::Locked => write!(f, "database is in use by something else"),
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
+/// Writes that happen all at once (or not at all, if we die halfway
+/// through writing the log). `None` is a deletion.
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.ops.push((key, Some(value)));
+    }
+
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.ops.push((key, None));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// u64 sequence number of the first write, u32 count, then per write
+    /// its kind and key (and value) length prefixed
+    fn encode(&self, sequence: u64) -> Vec<u8> {
+        let mut buf = vec![];
+        put_fixed64(&mut buf, sequence);
+        put_fixed32(&mut buf, self.ops.len() as u32);
+
+        for (k, value) in &self.ops {
+            match value {
+                Some(value) => {
+                    buf.push(key::VALUE);
+                    put_length_prefixed(&mut buf, k);
+                    put_length_prefixed(&mut buf, value);
+                }
+                None => {
+                    buf.push(key::DELETION);
+                    put_length_prefixed(&mut buf, k);
+                }
+            }
+        }
+
+        buf
+    }
+
+    /// The sequence number and the batch
+    fn decode(mut input: &[u8]) -> Option<(u64, Self)> {
+        let input = &mut input;
+        let sequence = get_fixed64(input)?;
+        let count = get_fixed32(input)?;
+
+        let mut batch = Self::default();
+        for _ in 0..count {
+            let (kind, rest) = input.split_first()?;
+            *input = rest;
+            let k = get_length_prefixed(input)?.to_vec();
+            match *kind {
+                key::VALUE => batch.put(k, get_length_prefixed(input)?.to_vec()),
+                key::DELETION => batch.delete(k),
+                _ => return None,
+            }
+        }
+
+        Some((sequence, batch))
+    }
+}
+
+struct TableFile {
+    meta: FileMeta,
+    table: Table,
+}
+
+impl TableFile {
+    fn overlaps(&self, smallest: &[u8], largest: &[u8]) -> bool {
+        key::user_key(&self.meta.smallest) <= largest
+            && key::user_key(&self.meta.largest) >= smallest
+    }
+}
+
+enum FileKind {
+    Log,
+    Table,
+    Manifest,
+    /// CURRENT, LOCK, LOG and anything else we leave alone
+    Other,
+}
+
+fn parse_file_name(name: &str) -> (FileKind, u64) {
+    let number = |n: &str| n.parse().ok();
+
+    let parsed = if let Some(n) = name.strip_prefix("MANIFEST-") {
+        number(n).map(|n| (FileKind::Manifest, n))
+    } else if let Some(n) = name.strip_suffix(".log") {
+        number(n).map(|n| (FileKind::Log, n))
+    } else if let Some(n) = name.strip_suffix(".ldb").or(name.strip_suffix(".sst")) {
+        number(n).map(|n| (FileKind::Table, n))
+    } else {
+        None
+    };
+
+    parsed.unwrap_or((FileKind::Other, 0))
+}
+
+pub struct Db {
+    dir: PathBuf,
+    /// Newest write per key since the last level 0 table, with its
+    /// sequence number
+    memtable: BTreeMap<Vec<u8>, (u64, Option<Vec<u8>>)>,
+    memtable_size: usize,
+    log: LogWriter,
+    log_number: u64,
+    manifest: LogWriter,
+    next_file: u64,
+    last_sequence: u64,
+    /// Level 0 newest first, the rest by key
+    levels: Vec<Vec<TableFile>>,
+    /// Held until we're dropped, the lock goes with it
+    _lock: File,
+}
+
+impl Db {
+    /// Opens the database in `dir`, or makes a new one
+    pub fn open(dir: &Path) -> Result<Self, DbError> {
+        fs::create_dir_all(dir)?;
+        let lock = File::create(dir.join("LOCK"))?;
+        lock.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => DbError::Locked,
+            TryLockError::Error(e) => DbError::Io(e),
+        })?;
+
+        let mut log_number = 0;
+        let mut prev_log_number = 0;
+        let mut next_file = 2;
+        let mut last_sequence = 0;
+        let mut files: Vec<Vec<FileMeta>> = (0..LEVELS).map(|_| vec![]).collect();
+
+        match fs::read_to_string(dir.join("CURRENT")) {
+            Ok(current) => {
+                let path = dir.join(current.trim_end());
+                let (records, clean) = read_records(&fs::read(&path)?);
+                if !clean {
+                    warn!("{} ends with a partial edit, ignoring it", path.display());
+                }
+
+                for record in records {
+                    let edit = VersionEdit::decode(&record)
+                        .ok_or_else(|| DbError::Corrupt(format!("{}: bad edit", path.display())))?;
+
+                    if let Some(comparator) = edit.comparator.filter(|c| c != COMPARATOR) {
+                        return Err(DbError::Corrupt(format!("unknown comparator {comparator}")));
+                    }
+                    log_number = edit.log_number.unwrap_or(log_number);
+                    prev_log_number = edit.prev_log_number.unwrap_or(prev_log_number);
+                    next_file = edit.next_file.unwrap_or(next_file);
+                    last_sequence = edit.last_sequence.unwrap_or(last_sequence);
+
+                    for (level, number) in edit.deleted {
+                        if let Some(level) = files.get_mut(level) {
+                            level.retain(|f| f.number != number);
+                        }
+                    }
+                    for (level, file) in edit.added {
+                        match files.get_mut(level) {
+                            Some(level) => level.push(file),
+                            None => return Err(DbError::Corrupt(format!("level {level}"))),
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut names = vec![];
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let (kind, number) = parse_file_name(&name);
+            next_file = next_file.max(number + 1);
+            names.push((kind, number, name));
+        }
+
+        // whatever hadn't made it into a table
+        let mut logs: Vec<_> = names
+            .iter()
+            .filter(|(kind, number, _)| {
+                matches!(kind, FileKind::Log)
+                    && (*number >= log_number || *number == prev_log_number)
+            })
+            .collect();
+        logs.sort_by_key(|(_, number, _)| *number);
+
+        let mut memtable = BTreeMap::new();
+        for (_, _, name) in logs {
+            let (records, clean) = read_records(&fs::read(dir.join(name))?);
+            if !clean {
+                warn!("{name} ends with a partial write, ignoring it");
+            }
+
+            for record in records {
+                let Some((sequence, batch)) = WriteBatch::decode(&record)
+                    .filter(|(sequence, _)| *sequence <= key::MAX_SEQUENCE)
+                else {
+                    warn!("Skipping a bad write in {name}");
+                    continue;
+                };
+                // the writes are sequence, sequence + 1, ... (and an empty
+                // batch has none)
+                last_sequence =
+                    last_sequence.max((sequence + batch.ops.len() as u64).saturating_sub(1));
+                for (i, (k, value)) in batch.ops.into_iter().enumerate() {
+                    memtable.insert(k, (sequence + i as u64, value));
+                }
+            }
+        }
+
+        let mut levels = vec![];
+        for level in files {
+            let mut tables = vec![];
+            for meta in level {
+                let table = Table::open(table_path(dir, meta.number))?;
+                tables.push(TableFile { meta, table });
+            }
+            levels.push(tables);
+        }
+        levels[0].sort_by_key(|f| std::cmp::Reverse(f.meta.number));
+        for level in &mut levels[1..] {
+            level.sort_by(|a, b| key::compare(&a.meta.smallest, &b.meta.smallest));
+        }
+
+        // a fresh MANIFEST with everything in one edit, and a new log
+        let manifest_number = next_file;
+        let log_number = next_file + 1;
+        let mut db = Self {
+            dir: dir.to_path_buf(),
+            memtable,
+            memtable_size: 0,
+            log: LogWriter::new(File::create(dir.join(format!("{log_number:06}.log")))?),
+            log_number,
+            manifest: LogWriter::new(File::create(
+                dir.join(format!("MANIFEST-{manifest_number:06}")),
+            )?),
+            next_file: next_file + 2,
+            last_sequence,
+            levels,
+            _lock: lock,
+        };
+        if let Some(meta) = db.write_memtable()? {
+            db.add_level0(meta)?;
+        }
+
+        let mut edit = db.counters();
+        edit.comparator = Some(COMPARATOR.to_string());
+        edit.added = db
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, files)| files.iter().map(move |f| (level, f.meta.clone())))
+            .collect();
+        db.manifest.add_record(&edit.encode())?;
+        db.manifest.sync()?;
+
+        let temp = dir.join(format!("{manifest_number:06}.dbtmp"));
+        fs::write(&temp, format!("MANIFEST-{manifest_number:06}\n"))?;
+        fs::rename(&temp, dir.join("CURRENT"))?;
+
+        db.remove_obsolete_files(manifest_number);
+        Ok(db)
+    }
+
+    /// The log number, next file and last sequence, which every edit
+    /// has
+    fn counters(&self) -> VersionEdit {
+        VersionEdit {
+            log_number: Some(self.log_number),
+            prev_log_number: Some(0),
+            next_file: Some(self.next_file),
+            last_sequence: Some(self.last_sequence),
+            ..Default::default()
+        }
+    }
+
+    fn new_file_number(&mut self) -> u64 {
+        self.next_file += 1;
+        self.next_file - 1
+    }
+
+    fn remove_obsolete_files(&self, manifest_number: u64) {
+        let live: Vec<u64> = self
+            .levels
+            .iter()
+            .flatten()
+            .map(|f| f.meta.number)
+            .collect();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let obsolete = match parse_file_name(&name) {
+                (FileKind::Log, number) => number != self.log_number,
+                (FileKind::Manifest, number) => number != manifest_number,
+                (FileKind::Table, number) => !live.contains(&number),
+                (FileKind::Other, _) => false,
+            };
+
+            if obsolete {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    pub fn get(&self, k: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        if let Some((_, value)) = self.memtable.get(k) {
+            return Ok(value.clone());
+        }
+
+        for file in &self.levels[0] {
+            if file.overlaps(k, k) {
+                if let Some(value) = file.table.get(k)? {
+                    return Ok(value);
+                }
+            }
+        }
+
+        for level in &self.levels[1..] {
+            let i = level.partition_point(|f| key::user_key(&f.meta.largest) < k);
+            if let Some(file) = level.get(i).filter(|f| f.overlaps(k, k)) {
+                if let Some(value) = file.table.get(k)? {
+                    return Ok(value);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn write(&mut self, batch: WriteBatch) -> Result<(), DbError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let sequence = self.last_sequence + 1;
+        self.log.add_record(&batch.encode(sequence))?;
+        self.last_sequence += batch.ops.len() as u64;
+
+        for (i, (k, value)) in batch.ops.into_iter().enumerate() {
+            self.memtable_size += k.len() + value.as_ref().map_or(0, Vec::len);
+            self.memtable.insert(k, (sequence + i as u64, value));
+        }
+
+        if self.memtable_size >= WRITE_BUFFER_SIZE {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure everything written so far is on disk
+    pub fn sync(&mut self) -> Result<(), DbError> {
+        Ok(self.log.sync()?)
+    }
+
+    /// Writes the memtable out as a level 0 table and starts a new log
+    pub fn flush(&mut self) -> Result<(), DbError> {
+        let Some(meta) = self.write_memtable()? else {
+            return Ok(());
+        };
+
+        let old_log = self.log_number;
+        self.log_number = self.new_file_number();
+        self.log = LogWriter::new(File::create(
+            self.dir.join(format!("{:06}.log", self.log_number)),
+        )?);
+
+        let mut edit = self.counters();
+        edit.added.push((0, meta.clone()));
+        self.manifest.add_record(&edit.encode())?;
+        self.manifest.sync()?;
+
+        let _ = fs::remove_file(self.dir.join(format!("{old_log:06}.log")));
+        self.add_level0(meta)?;
+
+        if self.levels[0].len() >= LEVEL0_COMPACTION_TRIGGER {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// The table, if there was anything in the memtable
+    fn write_memtable(&mut self) -> Result<Option<FileMeta>, DbError> {
+        if self.memtable.is_empty() {
+            return Ok(None);
+        }
+
+        let memtable = std::mem::take(&mut self.memtable);
+        self.memtable_size = 0;
+
+        let entries = memtable.into_iter().map(|(k, (sequence, value))| {
+            let kind = if value.is_some() {
+                key::VALUE
+            } else {
+                key::DELETION
+            };
+            (
+                key::internal_key(&k, sequence, kind),
+                value.unwrap_or_default(),
+            )
+        });
+        let mut tables = self.write_tables(entries, usize::MAX)?;
+
+        Ok(tables.pop())
+    }
+
+    fn add_level0(&mut self, meta: FileMeta) -> Result<(), DbError> {
+        let table = Table::open(table_path(&self.dir, meta.number))?;
+        self.levels[0].insert(0, TableFile { meta, table });
+        Ok(())
+    }
+
+    /// Writes entries (internal keys, in order) to as many tables as it
+    /// takes, starting a new one at `max_size`
+    fn write_tables(
+        &mut self,
+        entries: impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+        max_size: usize,
+    ) -> Result<Vec<FileMeta>, DbError> {
+        let mut tables = vec![];
+        let mut builder: Option<(TableBuilder, Vec<u8>, Vec<u8>)> = None;
+
+        for (k, value) in entries {
+            let (table, _, largest) =
+                builder.get_or_insert_with(|| (TableBuilder::new(), k.clone(), vec![]));
+            table.add(&k, &value);
+            *largest = k;
+
+            if table.size() >= max_size {
+                tables.push(self.finish_table(builder.take().unwrap())?);
+            }
+        }
+        if let Some(table) = builder {
+            tables.push(self.finish_table(table)?);
+        }
+
+        Ok(tables)
+    }
+
+    fn finish_table(
+        &mut self,
+        (table, smallest, largest): (TableBuilder, Vec<u8>, Vec<u8>),
+    ) -> Result<FileMeta, DbError> {
+        let number = self.new_file_number();
+        let size = table.finish(&table_path(&self.dir, number))?;
+
+        Ok(FileMeta {
+            number,
+            size,
+            smallest,
+            largest,
+        })
+    }
+
+    /// Merges every level 0 table, and the level 1 tables they overlap,
+    /// into new level 1 tables
+    fn compact(&mut self) -> Result<(), DbError> {
+        let level0 = std::mem::take(&mut self.levels[0]);
+        let smallest = level0
+            .iter()
+            .map(|f| key::user_key(&f.meta.smallest).to_vec())
+            .min()
+            .unwrap_or_default();
+        let largest = level0
+            .iter()
+            .map(|f| key::user_key(&f.meta.largest).to_vec())
+            .max()
+            .unwrap_or_default();
+        let (level1, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.levels[1])
+            .into_iter()
+            .partition(|f| f.overlaps(&smallest, &largest));
+
+        // newest write of every key
+        let mut merged: BTreeMap<Vec<u8>, (u64, u8, Vec<u8>)> = BTreeMap::new();
+        for file in level0.iter().chain(&level1) {
+            for (k, value) in file.table.entries()? {
+                let Some((user_key, sequence, kind)) = key::split(&k) else {
+                    continue;
+                };
+                if merged
+                    .get(user_key)
+                    .is_none_or(|(newest, _, _)| sequence > *newest)
+                {
+                    merged.insert(user_key.to_vec(), (sequence, kind, value));
+                }
+            }
+        }
+
+        // a deletion's only needed while there's something under it
+        let deeper: Vec<&TableFile> = self.levels[2..].iter().flatten().collect();
+        let entries: Vec<_> = merged
+            .into_iter()
+            .filter(|(k, (_, kind, _))| {
+                *kind == key::VALUE || deeper.iter().any(|f| f.overlaps(k, k))
+            })
+            .map(|(k, (sequence, kind, value))| (key::internal_key(&k, sequence, kind), value))
+            .collect();
+        let outputs = self.write_tables(entries.into_iter(), MAX_FILE_SIZE)?;
+
+        let mut edit = self.counters();
+        for (level, files) in [(0, &level0), (1, &level1)] {
+            edit.deleted
+                .extend(files.iter().map(|f| (level, f.meta.number)));
+        }
+        edit.added = outputs.iter().map(|meta| (1, meta.clone())).collect();
+        self.manifest.add_record(&edit.encode())?;
+        self.manifest.sync()?;
+
+        let mut level1_files = rest;
+        for meta in outputs {
+            let table = Table::open(table_path(&self.dir, meta.number))?;
+            level1_files.push(TableFile { meta, table });
+        }
+        level1_files.sort_by(|a, b| key::compare(&a.meta.smallest, &b.meta.smallest));
+        self.levels[1] = level1_files;
+
+        // closing them first, Windows won't delete open files
+        let obsolete: Vec<u64> = level0
+            .into_iter()
+            .chain(level1)
+            .map(|f| f.meta.number)
+            .collect();
+        for number in obsolete {
+            let _ = fs::remove_file(table_path(&self.dir, number));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tables are .ldb, but older ones can still be .sst
+fn table_path(dir: &Path, number: u64) -> PathBuf {
+    let path = dir.join(format!("{number:06}.ldb"));
+    let sst = dir.join(format!("{number:06}.sst"));
+    if !path.exists() && sst.exists() {
+        sst
+    } else {
+        path
+    }
+}
+
+/// A fresh directory for a test to put a database in
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voxel-test-{name}"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// There's no world written by vanilla to test against here, so these
+/// only show we can read back what we write, and what's laid out by hand
+/// from the format docs.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key{i:05}").into_bytes()
+    }
+
+    #[test]
+    fn batch_layout() {
+        // db/write_batch_test.cc's batch, at sequence 100
+        let mut data = vec![100, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0];
+        data.extend_from_slice(b"\x01\x03foo\x03bar");
+        data.extend_from_slice(b"\x00\x03box");
+        data.extend_from_slice(b"\x01\x03baz\x03boo");
+
+        let (sequence, batch) = WriteBatch::decode(&data).unwrap();
+        assert_eq!(sequence, 100);
+        assert_eq!(
+            batch.ops,
+            [
+                (b"foo".to_vec(), Some(b"bar".to_vec())),
+                (b"box".to_vec(), None),
+                (b"baz".to_vec(), Some(b"boo".to_vec())),
+            ]
+        );
+        assert_eq!(batch.encode(100), data);
+        assert!(WriteBatch::decode(&data[..data.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = test_dir("db_reopen");
+        let mut db = Db::open(&dir).unwrap();
+
+        // enough flushes to merge level 0 into level 1 too
+        for round in 0..LEVEL0_COMPACTION_TRIGGER as u32 + 1 {
+            let mut batch = WriteBatch::default();
+            for i in 0..1000 {
+                batch.put(key(i), format!("{round} {i}").into_bytes());
+            }
+            batch.delete(key(round));
+            db.write(batch).unwrap();
+            db.flush().unwrap();
+        }
+        let mut batch = WriteBatch::default();
+        batch.put(key(0), b"in the log".to_vec());
+        db.write(batch).unwrap();
+        db.sync().unwrap();
+        drop(db);
+
+        let db = Db::open(&dir).unwrap();
+        assert_eq!(db.get(&key(0)).unwrap(), Some(b"in the log".to_vec()));
+        assert_eq!(db.get(&key(1)).unwrap(), Some(b"4 1".to_vec()));
+        assert_eq!(db.get(&key(4)).unwrap(), None);
+        assert_eq!(db.get(&key(999)).unwrap(), Some(b"4 999".to_vec()));
+        assert_eq!(db.get(&key(1000)).unwrap(), None);
+    }
+
+    #[test]
+    fn empty_batch_in_log() {
+        let dir = test_dir("db_empty_batch");
+        drop(Db::open(&dir).unwrap());
+
+        let mut log = LogWriter::new(File::create(dir.join("000100.log")).unwrap());
+        log.add_record(&WriteBatch::default().encode(1)).unwrap();
+        drop(log);
+
+        let mut db = Db::open(&dir).unwrap();
+        assert_eq!(db.last_sequence, 0);
+        let mut batch = WriteBatch::default();
+        batch.put(key(0), b"value".to_vec());
+        db.write(batch).unwrap();
+        assert_eq!(db.get(&key(0)).unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    fn locked() {
+        let dir = test_dir("db_locked");
+        let db = Db::open(&dir).unwrap();
+        assert!(matches!(Db::open(&dir), Err(DbError::Locked)));
+
+        drop(db);
+        assert!(Db::open(&dir).is_ok());
+    }
+}
@@ -0,0 +1,533 @@
+/// leveldb/table.rs
+/// ================
+///
+/// Sorted tables (.ldb, or .sst from older versions), immutable once
+/// they're written:
+///
+///     ...   data blocks, the entries in order
+///     ...   meta index block (bloom filters, we don't write or use them)
+///     ...   index block, per data block a key >= everything in it and
+///           where the block is
+///     ...   footer (48 bytes): the meta index and index block handles as
+///           varint offset + size, zero padding and a magic number
+///
+/// A block is its entries, each key stored as how much it shares with
+/// the previous one plus the rest:
+///
+///     varint  shared key bytes
+///     varint  unshared key bytes
+///     varint  value length
+///     ...     unshared key bytes, then the value
+///
+/// then every so often a "restart" where the whole key's stored, their
+/// offsets as u32s and how many there are as a u32. Blocks on disk are
+/// followed by a compression type byte and a masked CRC-32C of the block
+/// and that byte.
+///
+/// Mojang's fork adds zlib to the compression types (that's all it
+/// changes), vanilla writes raw deflate and so do we.
+///
+/// Reference: https://github.com/google/leveldb (doc/table_format.md),
+///            https://github.com/Mojang/leveldb-mcpe
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use super::coding::{
+    crc32c, get_bytes, get_varint, mask_crc, put_fixed32, put_fixed64, put_varint, unmask_crc,
+};
+use super::key::{self, DELETION, VALUE};
+use super::DbError;
+
+const FOOTER_SIZE: usize = 48;
+const MAGIC: u64 = 0xdb4775248b80fb57;
+const TRAILER_SIZE: usize = 5;
+
+const NO_COMPRESSION: u8 = 0;
+const SNAPPY: u8 = 1;
+const ZLIB: u8 = 2;
+const ZLIB_RAW: u8 = 4;
+
+/// Uncompressed, before a data block's cut off
+const BLOCK_SIZE: usize = 4096;
+const RESTART_INTERVAL: usize = 16;
+
+/// Key and value
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone, Copy)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        put_varint(buf, self.offset);
+        put_varint(buf, self.size);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Self {
+            offset: get_varint(input)?,
+            size: get_varint(input)?,
+        })
+    }
+}
+
+fn corrupt(path: &Path, what: &str) -> DbError {
+    DbError::Corrupt(format!("{}: {what}", path.display()))
+}
+
+fn decompress(kind: u8, contents: &[u8]) -> Result<Vec<u8>, String> {
+    let mut raw = vec![];
+    match kind {
+        NO_COMPRESSION => return Ok(contents.to_vec()),
+        SNAPPY => {
+            return snap::raw::Decoder::new()
+                .decompress_vec(contents)
+                .map_err(|e| e.to_string())
+        }
+        ZLIB => ZlibDecoder::new(contents).read_to_end(&mut raw),
+        ZLIB_RAW => DeflateDecoder::new(contents).read_to_end(&mut raw),
+        other => return Err(format!("unknown compression type {other}")),
+    }
+    .map_err(|e| e.to_string())?;
+
+    Ok(raw)
+}
+
+/// A block's entries, keys put back together
+fn parse_block(data: &[u8]) -> Option<Vec<Entry>> {
+    let restarts = u32::from_le_bytes(data.get(data.len().checked_sub(4)?..)?.try_into().ok()?);
+    let end = data
+        .len()
+        .checked_sub(4)?
+        .checked_sub(restarts as usize * 4)?;
+
+    let mut input = &data[..end];
+    let mut entries = vec![];
+    let mut last_key: Vec<u8> = vec![];
+    while !input.is_empty() {
+        let shared = get_varint(&mut input)? as usize;
+        let unshared = get_varint(&mut input)? as usize;
+        let value_len = get_varint(&mut input)? as usize;
+
+        let mut key = last_key.get(..shared)?.to_vec();
+        key.extend_from_slice(get_bytes(&mut input, unshared)?);
+        let value = get_bytes(&mut input, value_len)?.to_vec();
+
+        last_key.clone_from(&key);
+        entries.push((key, value));
+    }
+
+    Some(entries)
+}
+
+pub struct Table {
+    path: PathBuf,
+    /// Kept open for as long as the table's in use, reads seek around it
+    file: Mutex<File>,
+    len: u64,
+    index: Vec<(Vec<u8>, BlockHandle)>,
+    /// The last block read and its offset. A chunk's keys are next to
+    /// each other, so loading one reads the same block over and over.
+    last_block: Mutex<Option<(u64, Arc<Vec<Entry>>)>>,
+}
+
+impl Table {
+    pub fn open(path: PathBuf) -> Result<Self, DbError> {
+        let mut file = File::open(&path)?;
+        let len = file.metadata()?.len();
+        if len < FOOTER_SIZE as u64 {
+            return Err(corrupt(&path, "too short to be a table"));
+        }
+
+        let mut footer = [0; FOOTER_SIZE];
+        file.seek(SeekFrom::Start(len - FOOTER_SIZE as u64))?;
+        file.read_exact(&mut footer)?;
+
+        let mut input = &footer[..];
+        let handles = BlockHandle::decode(&mut input)
+            .and_then(|meta_index| Some((meta_index, BlockHandle::decode(&mut input)?)));
+        let magic = u64::from_le_bytes(footer[40..].try_into().unwrap());
+        let (Some((_, index_handle)), MAGIC) = (handles, magic) else {
+            return Err(corrupt(&path, "bad footer"));
+        };
+
+        let index = Self::read_block(&path, &mut file, len, index_handle)?
+            .into_iter()
+            .map(|(key, value)| Some((key, BlockHandle::decode(&mut &value[..])?)))
+            .collect::<Option<_>>()
+            .ok_or_else(|| corrupt(&path, "bad index block"))?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            len,
+            index,
+            last_block: Mutex::new(None),
+        })
+    }
+
+    /// `len` is the file's, so a bad handle can't have us allocate or
+    /// read past the blocks
+    fn read_block(
+        path: &Path,
+        file: &mut File,
+        len: u64,
+        handle: BlockHandle,
+    ) -> Result<Vec<Entry>, DbError> {
+        let end = handle
+            .offset
+            .checked_add(handle.size)
+            .and_then(|end| end.checked_add(TRAILER_SIZE as u64));
+        if end.is_none_or(|end| end > len - FOOTER_SIZE as u64) {
+            return Err(corrupt(path, "block handle past the end of the file"));
+        }
+
+        let mut data = vec![0; handle.size as usize + TRAILER_SIZE];
+        file.seek(SeekFrom::Start(handle.offset))?;
+        file.read_exact(&mut data)?;
+
+        let (contents, trailer) = data.split_at(handle.size as usize);
+        let crc = u32::from_le_bytes(trailer[1..].try_into().unwrap());
+        if unmask_crc(crc) != crc32c(&[contents, &trailer[..1]]) {
+            return Err(corrupt(path, "block checksum doesn't match"));
+        }
+
+        let raw = decompress(trailer[0], contents).map_err(|e| corrupt(path, &e))?;
+        parse_block(&raw).ok_or_else(|| corrupt(path, "bad block"))
+    }
+
+    fn block(&self, handle: BlockHandle) -> Result<Arc<Vec<Entry>>, DbError> {
+        let mut last_block = self.last_block.lock().unwrap();
+        if let Some((offset, block)) = &*last_block {
+            if *offset == handle.offset {
+                return Ok(block.clone());
+            }
+        }
+
+        let mut file = self.file.lock().unwrap();
+        let block = Arc::new(Self::read_block(&self.path, &mut file, self.len, handle)?);
+        *last_block = Some((handle.offset, block.clone()));
+        Ok(block)
+    }
+
+    /// `None` if the table doesn't know about the key, `Some(None)` if it
+    /// was deleted
+    pub fn get(&self, user_key: &[u8]) -> Result<Option<Option<Vec<u8>>>, DbError> {
+        let lookup = key::lookup_key(user_key);
+        let first = self
+            .index
+            .partition_point(|(last, _)| key::compare(last, &lookup) == Ordering::Less);
+
+        for (_, handle) in &self.index[first..] {
+            let block = self.block(*handle)?;
+            let Some((found, value)) = block
+                .iter()
+                .find(|(k, _)| key::compare(k, &lookup) != Ordering::Less)
+            else {
+                continue;
+            };
+
+            return match key::split(found) {
+                Some((k, _, VALUE)) if k == user_key => Ok(Some(Some(value.clone()))),
+                Some((k, _, DELETION)) if k == user_key => Ok(Some(None)),
+                _ => Ok(None),
+            };
+        }
+
+        Ok(None)
+    }
+
+    /// Everything in it, in order
+    pub fn entries(&self) -> Result<Vec<Entry>, DbError> {
+        let mut entries = vec![];
+        for (_, handle) in &self.index {
+            entries.extend(self.block(*handle)?.iter().cloned());
+        }
+
+        Ok(entries)
+    }
+}
+
+struct BlockBuilder {
+    buf: Vec<u8>,
+    restarts: Vec<u32>,
+    restart_interval: usize,
+    /// Since the last restart
+    counter: usize,
+    last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+    fn new(restart_interval: usize) -> Self {
+        Self {
+            buf: vec![],
+            restarts: vec![0],
+            restart_interval,
+            counter: 0,
+            last_key: vec![],
+        }
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8]) {
+        let shared = if self.counter < self.restart_interval {
+            key.iter()
+                .zip(&self.last_key)
+                .take_while(|(a, b)| a == b)
+                .count()
+        } else {
+            self.restarts.push(self.buf.len() as u32);
+            self.counter = 0;
+            0
+        };
+
+        put_varint(&mut self.buf, shared as u64);
+        put_varint(&mut self.buf, (key.len() - shared) as u64);
+        put_varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(&key[shared..]);
+        self.buf.extend_from_slice(value);
+
+        self.last_key = key.to_vec();
+        self.counter += 1;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn size(&self) -> usize {
+        self.buf.len() + self.restarts.len() * 4 + 4
+    }
+
+    /// The finished block, and a new one to start over with
+    fn finish(&mut self) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.buf);
+        for restart in &self.restarts {
+            put_fixed32(&mut block, *restart);
+        }
+        put_fixed32(&mut block, self.restarts.len() as u32);
+
+        *self = Self::new(self.restart_interval);
+        block
+    }
+}
+
+/// Builds a table in memory, keys have to be added in order
+pub struct TableBuilder {
+    out: Vec<u8>,
+    data: BlockBuilder,
+    index: BlockBuilder,
+}
+
+impl TableBuilder {
+    pub fn new() -> Self {
+        Self {
+            out: vec![],
+            data: BlockBuilder::new(RESTART_INTERVAL),
+            index: BlockBuilder::new(1),
+        }
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        self.data.add(key, value);
+        if self.data.size() >= BLOCK_SIZE {
+            self.flush_data();
+        }
+    }
+
+    /// Roughly how big the file will be
+    pub fn size(&self) -> usize {
+        self.out.len() + self.data.size()
+    }
+
+    fn flush_data(&mut self) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        let last_key = self.data.last_key.clone();
+        let block = self.data.finish();
+        let handle = self.write_block(&block);
+
+        let mut encoded = vec![];
+        handle.encode(&mut encoded);
+        self.index.add(&last_key, &encoded);
+    }
+
+    fn write_block(&mut self, block: &[u8]) -> BlockHandle {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        let compressed = encoder.write_all(block).and_then(|_| encoder.finish());
+
+        // not worth it unless it saves at least an eighth
+        let (contents, kind) = match &compressed {
+            Ok(c) if c.len() < block.len() - block.len() / 8 => (&c[..], ZLIB_RAW),
+            _ => (block, NO_COMPRESSION),
+        };
+
+        let handle = BlockHandle {
+            offset: self.out.len() as u64,
+            size: contents.len() as u64,
+        };
+        self.out.extend_from_slice(contents);
+        self.out.push(kind);
+        put_fixed32(&mut self.out, mask_crc(crc32c(&[contents, &[kind]])));
+
+        handle
+    }
+
+    /// Writes it out, returns the file size
+    pub fn finish(mut self, path: &Path) -> Result<u64, DbError> {
+        self.flush_data();
+
+        let meta_index = BlockBuilder::new(RESTART_INTERVAL).finish();
+        let meta_index = self.write_block(&meta_index);
+        let index = self.index.finish();
+        let index = self.write_block(&index);
+
+        let mut footer = vec![];
+        meta_index.encode(&mut footer);
+        index.encode(&mut footer);
+        footer.resize(FOOTER_SIZE - 8, 0);
+        put_fixed64(&mut footer, MAGIC);
+        self.out.extend_from_slice(&footer);
+
+        let mut file = File::create(path)?;
+        file.write_all(&self.out)?;
+        file.sync_data()?;
+
+        Ok(self.out.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+
+    /// Sorted, every tenth key's a deletion
+    fn entries() -> Vec<Entry> {
+        (0..5000)
+            .map(|i| {
+                let k = format!("key{i:05}").into_bytes();
+                match i % 10 {
+                    0 => (key::internal_key(&k, i, DELETION), vec![]),
+                    _ => (
+                        key::internal_key(&k, i, VALUE),
+                        format!("value {i}").into_bytes(),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = crate::leveldb::test_dir("table_round_trip").join("000001.ldb");
+        let mut builder = TableBuilder::new();
+        for (k, value) in entries() {
+            builder.add(&k, &value);
+        }
+        let size = builder.finish(&path).unwrap();
+        assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+
+        let table = Table::open(path).unwrap();
+        assert!(table.index.len() > 1);
+        assert_eq!(table.entries().unwrap(), entries());
+        assert_eq!(
+            table.get(b"key00001").unwrap(),
+            Some(Some(b"value 1".to_vec()))
+        );
+        assert_eq!(
+            table.get(b"key04999").unwrap(),
+            Some(Some(b"value 4999".to_vec()))
+        );
+        assert_eq!(table.get(b"key00010").unwrap(), Some(None));
+        assert_eq!(table.get(b"key").unwrap(), None);
+        assert_eq!(table.get(b"key99999").unwrap(), None);
+    }
+
+    #[test]
+    fn not_a_table() {
+        let path = crate::leveldb::test_dir("table_not_a_table").join("000001.ldb");
+        std::fs::write(&path, [0; FOOTER_SIZE]).unwrap();
+        assert!(matches!(Table::open(path), Err(DbError::Corrupt(_))));
+    }
+
+    /// Vanilla's written blocks both ways over the years
+    #[test]
+    fn compression_types() {
+        let raw = b"some block contents, some block contents".to_vec();
+
+        let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+        zlib.write_all(&raw).unwrap();
+        let zlib = zlib.finish().unwrap();
+        let mut deflate = DeflateEncoder::new(vec![], Compression::default());
+        deflate.write_all(&raw).unwrap();
+        let deflate = deflate.finish().unwrap();
+
+        assert_eq!(decompress(ZLIB, &zlib), Ok(raw.clone()));
+        assert_eq!(decompress(ZLIB_RAW, &deflate), Ok(raw.clone()));
+        assert_eq!(decompress(NO_COMPRESSION, &raw), Ok(raw.clone()));
+        assert!(decompress(ZLIB, &deflate).is_err());
+        assert!(decompress(3, &raw).is_err());
+    }
+
+    #[test]
+    fn bad_block_handle() {
+        let path = crate::leveldb::test_dir("table_bad_block_handle").join("000001.ldb");
+        let mut builder = TableBuilder::new();
+        for (k, value) in entries() {
+            builder.add(&k, &value);
+        }
+        builder.finish(&path).unwrap();
+        let table = Table::open(path).unwrap();
+
+        // huge, past the end, and running into the footer
+        let mut file = table.file.lock().unwrap();
+        for (offset, size) in [(0, u64::MAX), (table.len, 10), (0, table.len - 50)] {
+            let read = Table::read_block(
+                &table.path,
+                &mut file,
+                table.len,
+                BlockHandle { offset, size },
+            );
+            assert!(matches!(read, Err(DbError::Corrupt(_))), "{offset} {size}");
+        }
+    }
+
+    #[test]
+    fn last_block() {
+        let path = crate::leveldb::test_dir("table_last_block").join("000001.ldb");
+        let mut builder = TableBuilder::new();
+        for (k, value) in entries() {
+            builder.add(&k, &value);
+        }
+        builder.finish(&path).unwrap();
+        let table = Table::open(path).unwrap();
+
+        table.get(b"key00001").unwrap();
+        let first = table.last_block.lock().unwrap().clone().unwrap();
+        assert_eq!(first.0, table.index[0].1.offset);
+
+        // the same block isn't read again
+        table.get(b"key00002").unwrap();
+        let again = table.last_block.lock().unwrap().clone().unwrap();
+        assert!(Arc::ptr_eq(&first.1, &again.1));
+
+        assert_eq!(
+            table.get(b"key04999").unwrap(),
+            Some(Some(b"value 4999".to_vec()))
+        );
+        let last = table.last_block.lock().unwrap().clone().unwrap();
+        assert_eq!(last.0, table.index.last().unwrap().1.offset);
+    }
+}
@@ -1,11 +1,20 @@
 mod config;
+mod dissector;
+mod game;
+#[allow(dead_code)] // not everything's used (yet)
+mod leveldb;
+#[allow(dead_code)] // not everything's used (yet)
+mod nbt;
+pub mod protocol;
+mod query;
 mod raknet;
+mod resource_packs;
 mod server;
-// pub mod protocol;
+mod status;
 
-use log::LevelFilter;
+use log::{error, LevelFilter};
 use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Root};
+use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::Config;
 
@@ -25,6 +34,11 @@ async fn main() {
     // ).await;
 
     // server.mainloop().await;
+    if std::env::args().nth(1).as_deref() == Some("dissect") {
+        dissector::run_cli();
+        return;
+    }
+
     let config = config::Config::parse();
 
     let stdout = ConsoleAppender::builder()
@@ -32,13 +46,31 @@ async fn main() {
             "{h({l})} {d(%d-%m-%Y %H:%M:%S)} [{f}:{L:<3}] {m}\n",
         )))
         .build();
+    // dissecting every datagram is slow, so only when asked for
+    let log_packets = config
+        .get_optional("log-packets")
+        .is_some_and(|v| v == "true");
     let logconfig = Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .logger(Logger::builder().build(
+            dissector::LOG_TARGET,
+            if log_packets {
+                LevelFilter::Trace
+            } else {
+                LevelFilter::Off
+            },
+        ))
         .build(Root::builder().appender("stdout").build(LevelFilter::Trace))
         .unwrap();
     let _handle = log4rs::init_config(logconfig).unwrap();
 
-    let mut server = server::VoxelServer::init().await;
+    let server = match server::VoxelServer::init(&config).await {
+        Ok(server) => server,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
     server.run(config).await;
 
     // let mut raknet_server = raknet::server::RakNetListener::new().await; // later, make config reference for raknet, and VoxelServer owner
@@ -0,0 +1,375 @@
+/// nbt/codec.rs
+/// ============
+///
+/// Reading and writing tags. Both flavours are little endian and only
+/// differ in how ints, longs and lengths are stored:
+///
+///                     little endian      network
+///     int             i32                zigzag varint
+///     long            i64                zigzag varint (64 bit)
+///     string length   u16                varint
+///     list/array len  i32                zigzag varint
+///
+/// Reference: https://github.com/Sandertv/gophertunnel (minecraft/nbt)
+use std::fmt;
+
+use super::{Compound, Nbt, Tag};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+
+/// Anything nested deeper than this is someone trying to blow our stack
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NbtFlavour {
+    LittleEndian,
+    Network,
+}
+
+/// Why some NBT couldn't be read
+#[derive(Debug)]
+pub enum NbtError {
+    Decode(DecodeError),
+    /// Negative, or longer than what's left
+    BadLength(i32),
+    TooDeep,
+    UnknownTag(u8),
+}
+
+impl fmt::Display for NbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbtError::Decode(e) => write!(f, "{}", e),
+            NbtError::BadLength(len) => {
+                write!(f, "length {} doesn't fit in what's left of the buffer", len)
+            }
+            NbtError::TooDeep => write!(f, "nested more than {} deep", MAX_DEPTH),
+            NbtError::UnknownTag(id) => write!(f, "unknown tag type {}", id),
+        }
+    }
+}
+
+impl From<DecodeError> for NbtError {
+    fn from(e: DecodeError) -> Self {
+        NbtError::Decode(e)
+    }
+}
+
+/// For NBT inside packets
+impl From<NbtError> for DecodeError {
+    fn from(e: NbtError) -> Self {
+        match e {
+            NbtError::Decode(e) => e,
+            e => DecodeError::Invalid(format!("NBT {}", e)),
+        }
+    }
+}
+
+pub fn read(buf: &mut MsgBuffer, flavour: NbtFlavour) -> Result<Nbt, NbtError> {
+    let id = buf.read_byte()?;
+    if id == 0 {
+        return Ok(Nbt::empty());
+    }
+
+    let name = flavour.read_string(buf)?;
+    let tag = flavour.read_payload(buf, id, 0)?;
+    Ok(Nbt { name, tag })
+}
+
+pub fn write(buf: &mut MsgBuffer, nbt: &Nbt, flavour: NbtFlavour) {
+    buf.write_byte(nbt.tag.id());
+    if nbt.tag == Tag::End {
+        return;
+    }
+
+    flavour.write_string(buf, &nbt.name);
+    flavour.write_payload(buf, &nbt.tag);
+}
+
+impl NbtFlavour {
+    fn read_int(self, buf: &mut MsgBuffer) -> Result<i32, DecodeError> {
+        match self {
+            NbtFlavour::LittleEndian => buf.read_i32_le_bytes(),
+            NbtFlavour::Network => buf.read_zigzag32(),
+        }
+    }
+
+    fn write_int(self, buf: &mut MsgBuffer, value: i32) {
+        match self {
+            NbtFlavour::LittleEndian => buf.write(&value.to_le_bytes()),
+            NbtFlavour::Network => buf.write_zigzag32(value),
+        }
+    }
+
+    fn read_long(self, buf: &mut MsgBuffer) -> Result<i64, DecodeError> {
+        match self {
+            NbtFlavour::LittleEndian => Ok(i64::from_le_bytes(buf.read_array()?)),
+            NbtFlavour::Network => buf.read_zigzag64(),
+        }
+    }
+
+    fn write_long(self, buf: &mut MsgBuffer, value: i64) {
+        match self {
+            NbtFlavour::LittleEndian => buf.write(&value.to_le_bytes()),
+            NbtFlavour::Network => buf.write_zigzag64(value),
+        }
+    }
+
+    fn read_len(self, buf: &mut MsgBuffer) -> Result<usize, NbtError> {
+        let len = self.read_int(buf)?;
+        if len < 0 || len as usize > buf.len_rest() {
+            return Err(NbtError::BadLength(len));
+        }
+        Ok(len as usize)
+    }
+
+    fn read_string(self, buf: &mut MsgBuffer) -> Result<String, DecodeError> {
+        match self {
+            NbtFlavour::LittleEndian => {
+                let len = i16::from_le_bytes(buf.read_array()?) as u16 as usize;
+                Ok(String::from_utf8_lossy(&buf.read_vec(len)?).to_string())
+            }
+            NbtFlavour::Network => buf.read_varint_string(),
+        }
+    }
+
+    fn write_string(self, buf: &mut MsgBuffer, value: &str) {
+        match self {
+            NbtFlavour::LittleEndian => {
+                buf.write_u16_le_bytes(value.len() as u16);
+                buf.write(value.as_bytes());
+            }
+            NbtFlavour::Network => buf.write_varint_string(value),
+        }
+    }
+
+    fn read_payload(self, buf: &mut MsgBuffer, id: u8, depth: usize) -> Result<Tag, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+
+        Ok(match id {
+            1 => Tag::Byte(buf.read_byte()? as i8),
+            2 => Tag::Short(i16::from_le_bytes(buf.read_array()?)),
+            3 => Tag::Int(self.read_int(buf)?),
+            4 => Tag::Long(self.read_long(buf)?),
+            5 => Tag::Float(f32::from_le_bytes(buf.read_array()?)),
+            6 => Tag::Double(f64::from_le_bytes(buf.read_array()?)),
+            7 => {
+                let len = self.read_len(buf)?;
+                Tag::ByteArray(buf.read_vec(len)?)
+            }
+            8 => Tag::String(self.read_string(buf)?),
+            9 => {
+                let element = buf.read_byte()?;
+                let len = self.read_len(buf)?;
+                let mut list = vec![];
+                if element != 0 {
+                    for _ in 0..len {
+                        list.push(self.read_payload(buf, element, depth + 1)?);
+                    }
+                }
+                Tag::List(list)
+            }
+            10 => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = buf.read_byte()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.read_string(buf)?;
+                    compound.insert(name, self.read_payload(buf, id, depth + 1)?);
+                }
+                Tag::Compound(compound)
+            }
+            11 => {
+                let len = self.read_len(buf)?;
+                Tag::IntArray(
+                    (0..len)
+                        .map(|_| self.read_int(buf))
+                        .collect::<Result<_, DecodeError>>()?,
+                )
+            }
+            12 => {
+                let len = self.read_len(buf)?;
+                Tag::LongArray(
+                    (0..len)
+                        .map(|_| self.read_long(buf))
+                        .collect::<Result<_, DecodeError>>()?,
+                )
+            }
+            other => return Err(NbtError::UnknownTag(other)),
+        })
+    }
+
+    fn write_payload(self, buf: &mut MsgBuffer, tag: &Tag) {
+        match tag {
+            Tag::End => {}
+            Tag::Byte(v) => buf.write_byte(*v as u8),
+            Tag::Short(v) => buf.write(&v.to_le_bytes()),
+            Tag::Int(v) => self.write_int(buf, *v),
+            Tag::Long(v) => self.write_long(buf, *v),
+            Tag::Float(v) => buf.write(&v.to_le_bytes()),
+            Tag::Double(v) => buf.write(&v.to_le_bytes()),
+            Tag::ByteArray(v) => {
+                self.write_int(buf, v.len() as i32);
+                buf.write(v);
+            }
+            Tag::String(v) => self.write_string(buf, v),
+            Tag::List(list) => {
+                // an empty list is a list of End tags
+                buf.write_byte(list.first().map_or(0, Tag::id));
+                self.write_int(buf, list.len() as i32);
+                for element in list {
+                    self.write_payload(buf, element);
+                }
+            }
+            Tag::Compound(compound) => {
+                for (name, value) in compound {
+                    buf.write_byte(value.id());
+                    self.write_string(buf, name);
+                    self.write_payload(buf, value);
+                }
+                buf.write_byte(0);
+            }
+            Tag::IntArray(v) => {
+                self.write_int(buf, v.len() as i32);
+                for i in v {
+                    self.write_int(buf, *i);
+                }
+            }
+            Tag::LongArray(v) => {
+                self.write_int(buf, v.len() as i32);
+                for i in v {
+                    self.write_long(buf, *i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One of every tag
+    fn everything() -> Nbt {
+        let nested: Tag = [("inner", Tag::from("value"))].into_iter().collect();
+        let tag: Tag = [
+            ("byte", Tag::Byte(-1)),
+            ("short", Tag::Short(-300)),
+            ("int", Tag::Int(i32::MIN)),
+            ("long", Tag::Long(i64::MAX)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-0.25)),
+            ("byte_array", Tag::ByteArray(vec![0, 1, 255])),
+            ("string", Tag::from("héllo")),
+            ("list", Tag::List(vec![Tag::Int(1), Tag::Int(-1)])),
+            ("empty_list", Tag::List(vec![])),
+            ("compound", nested),
+            ("int_array", Tag::IntArray(vec![i32::MAX, 0, -7])),
+            ("long_array", Tag::LongArray(vec![i64::MIN, 42])),
+        ]
+        .into_iter()
+        .collect();
+
+        Nbt {
+            name: "root".to_string(),
+            tag,
+        }
+    }
+
+    fn round_trip(nbt: &Nbt, flavour: NbtFlavour) -> Nbt {
+        let mut buf = MsgBuffer::new();
+        write(&mut buf, nbt, flavour);
+        let mut buf = MsgBuffer::from(buf.get_bytes().to_vec());
+        let read = read(&mut buf, flavour).unwrap();
+        assert_eq!(buf.len_rest(), 0, "{flavour:?} left bytes behind");
+        read
+    }
+
+    fn read_bytes(bytes: &[u8], flavour: NbtFlavour) -> Result<Nbt, NbtError> {
+        read(&mut MsgBuffer::from(bytes.to_vec()), flavour)
+    }
+
+    #[test]
+    fn round_trips() {
+        for flavour in [NbtFlavour::LittleEndian, NbtFlavour::Network] {
+            assert_eq!(round_trip(&everything(), flavour), everything());
+            assert_eq!(round_trip(&Nbt::empty(), flavour), Nbt::empty());
+        }
+    }
+
+    #[test]
+    fn flavours_differ_in_ints() {
+        let nbt = Nbt::new([("a", Tag::Int(-1))].into_iter().collect());
+
+        let mut buf = MsgBuffer::new();
+        write(&mut buf, &nbt, NbtFlavour::LittleEndian);
+        assert_eq!(
+            buf.get_bytes()[..],
+            [10, 0, 0, 3, 1, 0, b'a', 0xff, 0xff, 0xff, 0xff, 0]
+        );
+
+        let mut buf = MsgBuffer::new();
+        write(&mut buf, &nbt, NbtFlavour::Network);
+        assert_eq!(buf.get_bytes()[..], [10, 0, 3, 1, b'a', 1, 0]);
+    }
+
+    #[test]
+    fn cut_short() {
+        for flavour in [NbtFlavour::LittleEndian, NbtFlavour::Network] {
+            let mut buf = MsgBuffer::new();
+            write(&mut buf, &everything(), flavour);
+            let bytes = buf.get_bytes();
+
+            for len in 1..bytes.len() {
+                // lengths that are there but what they're the length of
+                // isn't are BadLength, the rest Decode
+                assert!(
+                    read_bytes(&bytes[..len], flavour).is_err(),
+                    "{flavour:?} read {len} of {} bytes",
+                    bytes.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bad_lengths() {
+        // a byte array of -1, then one of 100 with nothing after it
+        let negative = [7, 0, 1];
+        let too_long = [7, 0, 200, 1];
+
+        assert!(matches!(
+            read_bytes(&negative, NbtFlavour::Network),
+            Err(NbtError::BadLength(-1))
+        ));
+        assert!(matches!(
+            read_bytes(&too_long, NbtFlavour::Network),
+            Err(NbtError::BadLength(100))
+        ));
+    }
+
+    #[test]
+    fn unknown_tag() {
+        assert!(matches!(
+            read_bytes(&[10, 0, 13, 0], NbtFlavour::Network),
+            Err(NbtError::UnknownTag(13))
+        ));
+    }
+
+    #[test]
+    fn too_deep() {
+        // lists of lists of lists...
+        let mut bytes = vec![9, 0];
+        bytes.extend([9, 2].repeat(MAX_DEPTH + 1));
+        // so the innermost length fits
+        bytes.push(0);
+
+        assert!(matches!(
+            read_bytes(&bytes, NbtFlavour::Network),
+            Err(NbtError::TooDeep)
+        ));
+    }
+}
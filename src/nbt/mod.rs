@@ -0,0 +1,211 @@
+/// nbt/mod.rs
+/// ==========
+///
+/// Named Binary Tag, the game's format for anything that doesn't fit in a
+/// packet field: block entities, item user data, level.dat and so on.
+///
+/// Bedrock has two flavours of it (three if you count Java's big endian
+/// one, which we don't need):
+///  - little endian, used on disk (level.dat, LevelDB values)
+///  - network, little endian too but ints, longs and lengths are varints
+///
+/// Reference: https://wiki.vg/Bedrock_NBT_Format
+use std::collections::BTreeMap;
+
+mod codec;
+
+pub use codec::{read, write, NbtFlavour};
+
+/// Compound tags, sorted by key. Order doesn't matter to the game and it
+/// makes hashing compounds (e.g. block states) predictable.
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    /// Only ever shows up on its own, as an "empty" root tag
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    /// Every element has to be the same type, which the writer assumes
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::End => 0,
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Looks up a key if this is a compound
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        self.as_compound()?.get(key)
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Any whole number tag, bytes are how booleans get stored so this
+    /// covers those too
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(v) => Some(v as f64),
+            Tag::Double(v) => Some(v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        self.as_i64().map(|v| v != 0)
+    }
+}
+
+/// A root tag and its name, which is nearly always empty
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nbt {
+    pub name: String,
+    pub tag: Tag,
+}
+
+impl Nbt {
+    pub fn new(tag: Tag) -> Self {
+        Self {
+            name: String::new(),
+            tag,
+        }
+    }
+
+    /// What gets sent when a field has no NBT to speak of
+    pub fn empty() -> Self {
+        Self::new(Tag::End)
+    }
+}
+
+impl Default for Nbt {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+// Conversions both ways, so building a compound is mostly `.into()`s and
+// reading one is `TryFrom`s (or the `as_*` helpers above)
+
+macro_rules! convert {
+    ($($rust:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$rust> for Tag {
+                fn from(value: $rust) -> Self {
+                    Tag::$variant(value)
+                }
+            }
+
+            impl TryFrom<Tag> for $rust {
+                type Error = Tag;
+
+                fn try_from(tag: Tag) -> Result<Self, Tag> {
+                    match tag {
+                        Tag::$variant(value) => Ok(value),
+                        other => Err(other),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+convert! {
+    i8 => Byte,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+    Vec<u8> => ByteArray,
+    String => String,
+    Vec<Tag> => List,
+    Compound => Compound,
+    Vec<i32> => IntArray,
+    Vec<i64> => LongArray,
+}
+
+impl From<bool> for Tag {
+    fn from(value: bool) -> Self {
+        Tag::Byte(value as i8)
+    }
+}
+
+impl TryFrom<Tag> for bool {
+    type Error = Tag;
+
+    fn try_from(tag: Tag) -> Result<Self, Tag> {
+        match tag.as_bool() {
+            Some(value) => Ok(value),
+            None => Err(tag),
+        }
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(value: &str) -> Self {
+        Tag::String(value.to_string())
+    }
+}
+
+impl<K: Into<String>, V: Into<Tag>> FromIterator<(K, V)> for Tag {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Tag::Compound(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
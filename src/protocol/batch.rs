@@ -0,0 +1,368 @@
+/// protocol/batch.rs
+/// =================
+///
+/// Game packets never travel alone, they're bundled into a batch that
+/// goes in the body of a 0xfe frame:
+///
+///     0xfe [algorithm] compressed(
+///         varint(len) packet, varint(len) packet, ...
+///     )
+///
+/// Compression only starts after NetworkSettings is sent. Newer clients
+/// (1.20.60+) also expect a byte in front saying which algorithm was used,
+/// so that small batches can skip compression entirely.
+///
+/// Reference: https://wiki.bedrock.dev/ (Bedrock protocol docs)
+use std::fmt;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::config::{Config, ConfigError};
+use crate::raknet::objects::{to_i32_varint_bytes, MsgBuffer};
+
+/// Biggest batch we're willing to decompress, so someone can't send a
+/// tiny zip bomb and have us allocate gigabytes
+pub const MAX_BATCH_SIZE: usize = 12 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompressionAlgorithm {
+    Zlib = 0,
+    Snappy = 1,
+    None = 0xff,
+}
+
+impl CompressionAlgorithm {
+    /// For the config error when it's none of them
+    pub const EXPECTED: &'static str = "zlib, snappy or none";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "zlib" => Some(CompressionAlgorithm::Zlib),
+            "snappy" => Some(CompressionAlgorithm::Snappy),
+            "none" => Some(CompressionAlgorithm::None),
+            _ => None,
+        }
+    }
+
+    /// NetworkSettings sends the algorithm as a u16
+    pub fn network_id(&self) -> u16 {
+        match self {
+            CompressionAlgorithm::None => 0xffff,
+            other => *other as u16,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(CompressionAlgorithm::Zlib),
+            1 => Some(CompressionAlgorithm::Snappy),
+            0xff => Some(CompressionAlgorithm::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    UnknownAlgorithm(u8),
+    Corrupt(String),
+    TooLarge(usize),
+    BadLength,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::UnknownAlgorithm(id) => write!(f, "unknown compression algorithm {id}"),
+            BatchError::Corrupt(e) => write!(f, "couldn't decompress batch: {e}"),
+            BatchError::TooLarge(size) => {
+                write!(f, "batch is too large ({size} > {MAX_BATCH_SIZE} bytes)")
+            }
+            BatchError::BadLength => write!(f, "packet length doesn't fit in the batch"),
+        }
+    }
+}
+
+/// What we tell the client in NetworkSettings, straight from the config
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CompressionSettings {
+    pub algorithm: CompressionAlgorithm,
+    pub threshold: u16,
+}
+
+impl CompressionSettings {
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        Ok(Self {
+            algorithm: config.get_parsed(
+                "compression-algorithm",
+                CompressionAlgorithm::EXPECTED,
+                CompressionAlgorithm::from_name,
+            )?,
+            threshold: config.get_parsed(
+                "compression-threshold",
+                "a number from 0 to 65535",
+                |t| t.parse().ok(),
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchCodec {
+    /// `None` until compression has been negotiated
+    pub settings: Option<CompressionSettings>,
+    /// Whether batches start with the algorithm byte (protocol 649+)
+    pub algorithm_header: bool,
+}
+
+impl BatchCodec {
+    pub fn enable_compression(&mut self, settings: CompressionSettings, algorithm_header: bool) {
+        self.settings = Some(settings);
+        self.algorithm_header = algorithm_header;
+    }
+
+    /// Takes whole game packets (header included) and gives back the
+    /// body of the 0xfe frame
+    pub fn encode(&self, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut batch = vec![];
+        for packet in packets {
+            batch.extend(to_i32_varint_bytes(packet.len() as i32));
+            batch.extend_from_slice(packet);
+        }
+
+        let settings = match self.settings {
+            Some(settings) => settings,
+            None => return batch,
+        };
+
+        let mut algorithm = settings.algorithm;
+        if batch.len() < settings.threshold as usize && self.algorithm_header {
+            algorithm = CompressionAlgorithm::None;
+        }
+
+        let mut out = vec![];
+        if self.algorithm_header {
+            out.push(algorithm as u8);
+        }
+
+        match algorithm {
+            CompressionAlgorithm::Zlib => {
+                // older clients can't be told a batch isn't compressed, so
+                // small batches still go through deflate, just without
+                // actually compressing
+                let level = if batch.len() < settings.threshold as usize {
+                    Compression::none()
+                } else {
+                    Compression::default()
+                };
+                let mut encoder = DeflateEncoder::new(out, level);
+                encoder.write_all(&batch).unwrap();
+                encoder.finish().unwrap()
+            }
+            CompressionAlgorithm::Snappy => {
+                out.extend(snap::raw::Encoder::new().compress_vec(&batch).unwrap());
+                out
+            }
+            CompressionAlgorithm::None => {
+                out.extend(batch);
+                out
+            }
+        }
+    }
+
+    /// The body of a 0xfe frame to a list of whole game packets
+    pub fn decode(&self, payload: &[u8]) -> Result<Vec<Vec<u8>>, BatchError> {
+        let batch = match self.settings {
+            None => payload.to_vec(),
+            Some(settings) => {
+                let (algorithm, body) = if self.algorithm_header {
+                    let (&id, body) = payload.split_first().ok_or(BatchError::BadLength)?;
+                    let algorithm = CompressionAlgorithm::from_id(id)
+                        .ok_or(BatchError::UnknownAlgorithm(id))?;
+                    (algorithm, body)
+                } else {
+                    (settings.algorithm, payload)
+                };
+                decompress(algorithm, body)?
+            }
+        };
+
+        split_batch(&batch)
+    }
+}
+
+pub fn decompress(algorithm: CompressionAlgorithm, body: &[u8]) -> Result<Vec<u8>, BatchError> {
+    match algorithm {
+        CompressionAlgorithm::Zlib => {
+            let mut out = vec![];
+            DeflateDecoder::new(body)
+                .take(MAX_BATCH_SIZE as u64 + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| BatchError::Corrupt(e.to_string()))?;
+
+            if out.len() > MAX_BATCH_SIZE {
+                return Err(BatchError::TooLarge(out.len()));
+            }
+            Ok(out)
+        }
+        CompressionAlgorithm::Snappy => {
+            // snappy tells us the size upfront, so check before allocating
+            let size =
+                snap::raw::decompress_len(body).map_err(|e| BatchError::Corrupt(e.to_string()))?;
+            if size > MAX_BATCH_SIZE {
+                return Err(BatchError::TooLarge(size));
+            }
+
+            snap::raw::Decoder::new()
+                .decompress_vec(body)
+                .map_err(|e| BatchError::Corrupt(e.to_string()))
+        }
+        CompressionAlgorithm::None => Ok(body.to_vec()),
+    }
+}
+
+/// Splits an uncompressed batch into its packets
+pub fn split_batch(batch: &[u8]) -> Result<Vec<Vec<u8>>, BatchError> {
+    let mut buf = MsgBuffer::from(batch.to_vec());
+    let mut packets = vec![];
+
+    while !buf.at_end() {
+        let size = buf
+            .read_u32_varint_bytes()
+            .map_err(|_| BatchError::BadLength)? as usize;
+        if size == 0 {
+            return Err(BatchError::BadLength);
+        }
+        packets.push(buf.read_vec(size).map_err(|_| BatchError::BadLength)?);
+    }
+
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codec(
+        algorithm: CompressionAlgorithm,
+        threshold: u16,
+        algorithm_header: bool,
+    ) -> BatchCodec {
+        let mut codec = BatchCodec::default();
+        codec.enable_compression(
+            CompressionSettings {
+                algorithm,
+                threshold,
+            },
+            algorithm_header,
+        );
+        codec
+    }
+
+    fn packets() -> Vec<Vec<u8>> {
+        vec![vec![0x01, 0x02, 0x03], vec![0x42; 300], vec![0x09]]
+    }
+
+    #[test]
+    fn round_trips() {
+        for algorithm in [
+            CompressionAlgorithm::Zlib,
+            CompressionAlgorithm::Snappy,
+            CompressionAlgorithm::None,
+        ] {
+            for algorithm_header in [true, false] {
+                let codec = codec(algorithm, 0, algorithm_header);
+                let batch = codec.encode(&packets());
+                assert_eq!(codec.decode(&batch).unwrap(), packets(), "{algorithm:?}");
+            }
+        }
+
+        // before NetworkSettings
+        let codec = BatchCodec::default();
+        assert_eq!(codec.decode(&codec.encode(&packets())).unwrap(), packets());
+    }
+
+    #[test]
+    fn threshold() {
+        let small = vec![vec![0x01; 10]];
+        let big = vec![vec![0x01; 1000]];
+
+        for algorithm in [CompressionAlgorithm::Zlib, CompressionAlgorithm::Snappy] {
+            let codec = codec(algorithm, 256, true);
+            let batch = codec.encode(&small);
+            assert_eq!(batch[0], CompressionAlgorithm::None as u8);
+            assert_eq!(codec.decode(&batch).unwrap(), small);
+
+            let batch = codec.encode(&big);
+            assert_eq!(batch[0], algorithm as u8);
+            assert!(batch.len() < 1000);
+            assert_eq!(codec.decode(&batch).unwrap(), big);
+        }
+
+        // no header, so small batches still have to be deflate, just stored
+        let codec = codec(CompressionAlgorithm::Zlib, 256, false);
+        let batch = codec.encode(&small);
+        assert!(batch.len() > 12);
+        assert_eq!(codec.decode(&batch).unwrap(), small);
+    }
+
+    #[test]
+    fn bomb() {
+        let huge = vec![0; MAX_BATCH_SIZE + 1];
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::best());
+        encoder.write_all(&huge).unwrap();
+        let body = encoder.finish().unwrap();
+        assert!(matches!(
+            decompress(CompressionAlgorithm::Zlib, &body),
+            Err(BatchError::TooLarge(_))
+        ));
+
+        let body = snap::raw::Encoder::new().compress_vec(&huge).unwrap();
+        assert!(matches!(
+            decompress(CompressionAlgorithm::Snappy, &body),
+            Err(BatchError::TooLarge(size)) if size == MAX_BATCH_SIZE + 1
+        ));
+
+        assert!(matches!(
+            decompress(CompressionAlgorithm::Zlib, &[0xff, 0xff, 0xff]),
+            Err(BatchError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn bad_lengths() {
+        assert_eq!(split_batch(&[]).unwrap(), Vec::<Vec<u8>>::new());
+        assert_eq!(
+            split_batch(&[2, 0xaa, 0xbb]).unwrap(),
+            vec![vec![0xaa, 0xbb]]
+        );
+
+        // varint that never ends
+        assert!(matches!(split_batch(&[0x80]), Err(BatchError::BadLength)));
+        assert!(matches!(
+            split_batch(&[0x80; 6]),
+            Err(BatchError::BadLength)
+        ));
+        // longer than what's left
+        assert!(matches!(
+            split_batch(&[5, 1, 2]),
+            Err(BatchError::BadLength)
+        ));
+        assert!(matches!(
+            split_batch(&[1, 0xaa, 0xff, 0xff, 0xff, 0xff, 0x0f]),
+            Err(BatchError::BadLength)
+        ));
+        assert!(matches!(split_batch(&[0]), Err(BatchError::BadLength)));
+
+        let codec = codec(CompressionAlgorithm::None, 0, true);
+        assert!(matches!(codec.decode(&[]), Err(BatchError::BadLength)));
+        assert!(matches!(
+            codec.decode(&[7, 1]),
+            Err(BatchError::UnknownAlgorithm(7))
+        ));
+    }
+}
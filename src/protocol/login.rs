@@ -0,0 +1,633 @@
+/// protocol/login.rs
+/// =================
+///
+/// Making sense of the Login packet. The identity is a chain of JWTs
+/// (ES384), each one signed by the key in the previous one's
+/// `identityPublicKey`:
+///
+///     [self signed by the client] -> [signed by Mojang] -> [signed by Xbox Live]
+///
+/// If Mojang's key shows up along the way, the player's logged in to
+/// Xbox Live. One token has the player's name and XUID (`extraData`),
+/// and it only counts if it's in the part of the chain Mojang signed,
+/// anyone can sign a token of their own and stick it on the end. The
+/// last token's `identityPublicKey` signs the client data JWT (skin,
+/// device, ...).
+///
+/// Reference: https://github.com/pmmp/PocketMine-MP (ProcessLoginTask)
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use p384::ecdsa::signature::{Signer, Verifier};
+use p384::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p384::pkcs8::{DecodePublicKey, EncodePublicKey};
+use serde_json::Value;
+
+/// What Mojang signs the Xbox Live part of the chain with
+pub const MOJANG_ROOT_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAECRXueJeTDqNRRgJi/vlRufByu/2G0i2Ebt6YMar5QX/R0DIIyrJMcUpruK4QveTfJSTp3Shlq4Gk34cD/4GUWwkv0DVuzeuB+tXija7HBxii03NHDbPAD0AKnLr2wdAp";
+
+// clocks are never quite in sync
+const CLOCK_LEEWAY: i64 = 60;
+/// Self signed, Mojang, Xbox Live. Anything longer is someone adding
+/// their own.
+const MAX_CHAIN_LENGTH: usize = 3;
+
+#[derive(Debug)]
+pub enum LoginError {
+    Malformed(String),
+    BadSignature,
+    KeyMismatch,
+    Expired,
+    NotAuthenticated,
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginError::Malformed(e) => write!(f, "malformed login: {e}"),
+            LoginError::BadSignature => write!(f, "token signature doesn't check out"),
+            LoginError::KeyMismatch => write!(f, "token wasn't signed by the previous key"),
+            LoginError::Expired => write!(f, "token expired (or isn't valid yet)"),
+            LoginError::NotAuthenticated => write!(f, "not logged in to Xbox Live"),
+        }
+    }
+}
+
+fn malformed(what: &str) -> LoginError {
+    LoginError::Malformed(what.to_string())
+}
+
+pub struct Jwt {
+    pub header: Value,
+    pub payload: Value,
+    signed: String,
+    signature: Vec<u8>,
+}
+
+impl Jwt {
+    pub fn parse(token: &str) -> Result<Self, LoginError> {
+        let parts: Vec<&str> = token.trim().split('.').collect();
+        if parts.len() != 3 {
+            return Err(malformed("token doesn't have 3 parts"));
+        }
+
+        let decode_json = |part: &str| -> Result<Value, LoginError> {
+            let bytes = URL_SAFE_NO_PAD
+                .decode(part.trim_end_matches('='))
+                .map_err(|_| malformed("token isn't base64"))?;
+            serde_json::from_slice(&bytes).map_err(|_| malformed("token isn't JSON"))
+        };
+
+        Ok(Self {
+            header: decode_json(parts[0])?,
+            payload: decode_json(parts[1])?,
+            signed: format!("{}.{}", parts[0], parts[1]),
+            signature: URL_SAFE_NO_PAD
+                .decode(parts[2].trim_end_matches('='))
+                .map_err(|_| malformed("signature isn't base64"))?,
+        })
+    }
+
+    /// The key this token says it was signed with
+    pub fn x5u(&self) -> Result<&str, LoginError> {
+        self.header["x5u"]
+            .as_str()
+            .ok_or_else(|| malformed("token has no x5u"))
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), LoginError> {
+        if self.header["alg"].as_str() != Some("ES384") {
+            return Err(malformed("token isn't ES384"));
+        }
+
+        // raw r || s, not DER
+        let signature =
+            Signature::from_slice(&self.signature).map_err(|_| LoginError::BadSignature)?;
+        key.verify(self.signed.as_bytes(), &signature)
+            .map_err(|_| LoginError::BadSignature)
+    }
+
+    pub fn check_time(&self) -> Result<(), LoginError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        if let Some(exp) = self.payload["exp"].as_i64() {
+            if now > exp + CLOCK_LEEWAY {
+                return Err(LoginError::Expired);
+            }
+        }
+        if let Some(nbf) = self.payload["nbf"].as_i64() {
+            if now < nbf - CLOCK_LEEWAY {
+                return Err(LoginError::Expired);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes an ES384 token with our key in `x5u`, the way the client does
+pub fn sign_jwt(key: &SigningKey, payload: &Value) -> String {
+    let header = serde_json::json!({
+        "alg": "ES384",
+        "x5u": encode_key(key.verifying_key()),
+    });
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(payload.to_string())
+    );
+    let signature: Signature = key.sign(signed.as_bytes());
+
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    )
+}
+
+/// Keys are sent around as base64 DER (SubjectPublicKeyInfo)
+pub fn encode_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.to_public_key_der().unwrap().as_bytes())
+}
+
+pub fn parse_key(key: &str) -> Result<VerifyingKey, LoginError> {
+    let der = STANDARD
+        .decode(key)
+        .map_err(|_| malformed("key isn't base64"))?;
+    VerifyingKey::from_public_key_der(&der).map_err(|_| malformed("key isn't a P-384 key"))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SkinImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SkinData {
+    pub skin_id: String,
+    pub play_fab_id: String,
+    pub skin_resource_patch: String,
+    pub skin_image: SkinImage,
+    pub cape_id: String,
+    pub cape_image: SkinImage,
+    pub geometry_data: String,
+    pub geometry_data_engine_version: String,
+    pub animation_data: String,
+    pub arm_size: String,
+    pub skin_color: String,
+    pub premium: bool,
+    pub persona: bool,
+    pub cape_on_classic: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginData {
+    pub xuid: String,
+    pub display_name: String,
+    pub identity: String,
+    pub title_id: Option<String>,
+    /// Whether Mojang signed the identity, i.e. logged in to Xbox Live
+    pub authenticated: bool,
+    /// Base64 DER, needed later for encryption
+    pub identity_public_key: String,
+    pub device_os: i64,
+    pub device_id: String,
+    pub device_model: String,
+    pub game_version: String,
+    pub language_code: String,
+    pub server_address: String,
+    pub client_random_id: i64,
+    pub skin: SkinData,
+}
+
+pub struct LoginVerifier {
+    root_keys: Vec<String>,
+    online_mode: bool,
+}
+
+impl LoginVerifier {
+    pub fn new(online_mode: bool) -> Self {
+        Self::with_root_keys(vec![MOJANG_ROOT_KEY.to_string()], online_mode)
+    }
+
+    /// For when you want to trust something other than Mojang
+    /// (e.g. testing with your own keys)
+    pub fn with_root_keys(root_keys: Vec<String>, online_mode: bool) -> Self {
+        Self {
+            root_keys,
+            online_mode,
+        }
+    }
+
+    pub fn verify(&self, identity: &str, client: &str) -> Result<LoginData, LoginError> {
+        let chain = parse_chain(identity)?;
+        if chain.is_empty() {
+            return Err(malformed("empty chain"));
+        }
+        if chain.len() > MAX_CHAIN_LENGTH {
+            return Err(malformed("chain is too long"));
+        }
+
+        let mut current_key: Option<String> = None;
+        // which token Mojang's key signed
+        let mut mojang_token = None;
+        // and whether it came from Mojang
+        let mut extra_data: Option<(Value, bool)> = None;
+
+        for (i, token) in chain.iter().enumerate() {
+            let jwt = Jwt::parse(token)?;
+            let x5u = jwt.x5u()?.to_string();
+
+            // the first token is signed by the client itself, every other
+            // one has to be signed by whatever the previous one vouched for
+            if let Some(key) = &current_key {
+                if *key != x5u {
+                    return Err(LoginError::KeyMismatch);
+                }
+            }
+
+            jwt.verify(&parse_key(&x5u)?)?;
+            jwt.check_time()?;
+
+            if mojang_token.is_none() && self.root_keys.contains(&x5u) {
+                mojang_token = Some(i);
+            }
+            // Mojang's token, or the one signed by the key it hands out
+            // (Xbox Live's). The one after that's signed by the player.
+            let signed_by_mojang = mojang_token.is_some_and(|m| i <= m + 1);
+
+            current_key = Some(
+                jwt.payload["identityPublicKey"]
+                    .as_str()
+                    .ok_or_else(|| malformed("token has no identityPublicKey"))?
+                    .to_string(),
+            );
+            if let Some(extra) = jwt.payload.get("extraData") {
+                if extra_data.is_some() {
+                    return Err(malformed("more than one token has extraData"));
+                }
+                extra_data = Some((extra.clone(), signed_by_mojang));
+            }
+        }
+
+        let (extra, authenticated) = extra_data.ok_or_else(|| malformed("no extraData"))?;
+        // a chain Mojang signed with an identity it didn't is up to no good,
+        // whatever online-mode is
+        if mojang_token.is_some() && !authenticated {
+            return Err(LoginError::NotAuthenticated);
+        }
+        if self.online_mode && !authenticated {
+            return Err(LoginError::NotAuthenticated);
+        }
+
+        let identity_public_key = current_key.unwrap();
+        let extra = &extra;
+        let display_name = get_str(extra, "displayName")?;
+        if display_name.is_empty() {
+            return Err(malformed("empty display name"));
+        }
+
+        // and the client data is signed by the player's own key
+        let client_jwt = Jwt::parse(client)?;
+        client_jwt.verify(&parse_key(&identity_public_key)?)?;
+        let data = &client_jwt.payload;
+
+        Ok(LoginData {
+            xuid: get_str(extra, "XUID").unwrap_or_default(),
+            display_name,
+            identity: get_str(extra, "identity")?,
+            title_id: extra["titleId"].as_str().map(|s| s.to_string()),
+            authenticated,
+            identity_public_key,
+            device_os: data["DeviceOS"].as_i64().unwrap_or(0),
+            device_id: get_str(data, "DeviceId").unwrap_or_default(),
+            device_model: get_str(data, "DeviceModel").unwrap_or_default(),
+            game_version: get_str(data, "GameVersion").unwrap_or_default(),
+            language_code: get_str(data, "LanguageCode").unwrap_or_default(),
+            server_address: get_str(data, "ServerAddress").unwrap_or_default(),
+            client_random_id: data["ClientRandomId"].as_i64().unwrap_or(0),
+            skin: parse_skin(data)?,
+        })
+    }
+}
+
+/// `{"chain": [...]}`, or on newer versions the same thing as a string
+/// under `Certificate`
+fn parse_chain(identity: &str) -> Result<Vec<String>, LoginError> {
+    let mut json: Value =
+        serde_json::from_str(identity).map_err(|_| malformed("identity isn't JSON"))?;
+    if let Some(certificate) = json["Certificate"].as_str() {
+        json =
+            serde_json::from_str(certificate).map_err(|_| malformed("certificate isn't JSON"))?;
+    }
+
+    json["chain"]
+        .as_array()
+        .ok_or_else(|| malformed("identity has no chain"))?
+        .iter()
+        .map(|token| {
+            token
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| malformed("chain has something that isn't a token"))
+        })
+        .collect()
+}
+
+fn get_str(json: &Value, key: &str) -> Result<String, LoginError> {
+    json[key]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| LoginError::Malformed(format!("missing {key}")))
+}
+
+fn get_base64(json: &Value, key: &str) -> Result<Vec<u8>, LoginError> {
+    STANDARD
+        .decode(json[key].as_str().unwrap_or_default())
+        .map_err(|_| LoginError::Malformed(format!("{key} isn't base64")))
+}
+
+fn get_base64_str(json: &Value, key: &str) -> Result<String, LoginError> {
+    Ok(String::from_utf8_lossy(&get_base64(json, key)?).to_string())
+}
+
+fn get_dimension(json: &Value, key: &str) -> Result<u32, LoginError> {
+    u32::try_from(json[key].as_u64().unwrap_or(0))
+        .map_err(|_| LoginError::Malformed(format!("{key} is too big")))
+}
+
+fn parse_skin(data: &Value) -> Result<SkinData, LoginError> {
+    let skin_image = SkinImage {
+        width: get_dimension(data, "SkinImageWidth")?,
+        height: get_dimension(data, "SkinImageHeight")?,
+        data: get_base64(data, "SkinData")?,
+    };
+    let cape_image = SkinImage {
+        width: get_dimension(data, "CapeImageWidth")?,
+        height: get_dimension(data, "CapeImageHeight")?,
+        data: get_base64(data, "CapeData")?,
+    };
+
+    // RGBA, anything else is either broken or someone up to no good
+    for image in [&skin_image, &cape_image] {
+        let size = (image.width as u64)
+            .checked_mul(image.height as u64)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| malformed("skin image dimensions are too big"))?;
+        if image.data.len() as u64 != size {
+            return Err(malformed("skin image size doesn't match its dimensions"));
+        }
+    }
+
+    Ok(SkinData {
+        skin_id: get_str(data, "SkinId")?,
+        play_fab_id: get_str(data, "PlayFabId").unwrap_or_default(),
+        skin_resource_patch: get_base64_str(data, "SkinResourcePatch")?,
+        skin_image,
+        cape_id: get_str(data, "CapeId").unwrap_or_default(),
+        cape_image,
+        geometry_data: get_base64_str(data, "SkinGeometryData")?,
+        geometry_data_engine_version: get_base64_str(data, "SkinGeometryDataEngineVersion")?,
+        animation_data: get_base64_str(data, "SkinAnimationData")?,
+        arm_size: get_str(data, "ArmSize").unwrap_or_default(),
+        skin_color: get_str(data, "SkinColor").unwrap_or_default(),
+        premium: data["PremiumSkin"].as_bool().unwrap_or(false),
+        persona: data["PersonaSkin"].as_bool().unwrap_or(false),
+        cape_on_classic: data["CapeOnClassicSkin"].as_bool().unwrap_or(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Stand-ins for the player's, Mojang's and Xbox Live's keys
+    struct Keys {
+        client: SigningKey,
+        mojang: SigningKey,
+        xbox: SigningKey,
+    }
+
+    impl Keys {
+        fn new() -> Self {
+            Self {
+                client: SigningKey::random(&mut OsRng),
+                mojang: SigningKey::random(&mut OsRng),
+                xbox: SigningKey::random(&mut OsRng),
+            }
+        }
+
+        fn verifier(&self, online_mode: bool) -> LoginVerifier {
+            LoginVerifier::with_root_keys(vec![key(&self.mojang)], online_mode)
+        }
+
+        /// What a real client sends, signed by `mojang` as it's handed in
+        fn chain(&self, mojang: &SigningKey) -> Vec<String> {
+            vec![
+                sign_jwt(
+                    &self.client,
+                    &json!({"certificateAuthority": true, "identityPublicKey": key(mojang)}),
+                ),
+                sign_jwt(
+                    mojang,
+                    &json!({"certificateAuthority": true, "identityPublicKey": key(&self.xbox)}),
+                ),
+                sign_jwt(
+                    &self.xbox,
+                    &json!({
+                        "identityPublicKey": key(&self.client),
+                        "extraData": extra_data("Steve", "1111"),
+                    }),
+                ),
+            ]
+        }
+
+        fn client_data(&self) -> String {
+            sign_jwt(&self.client, &json!({"SkinId": "Standard_Custom"}))
+        }
+    }
+
+    fn key(key: &SigningKey) -> String {
+        encode_key(key.verifying_key())
+    }
+
+    fn extra_data(name: &str, xuid: &str) -> Value {
+        json!({"displayName": name, "XUID": xuid, "identity": "a1b2c3"})
+    }
+
+    fn identity(chain: &[String]) -> String {
+        json!({ "chain": chain }).to_string()
+    }
+
+    #[test]
+    fn valid_chain() {
+        let keys = Keys::new();
+        let chain = keys.chain(&keys.mojang);
+
+        let login = keys
+            .verifier(true)
+            .verify(&identity(&chain), &keys.client_data())
+            .unwrap();
+        assert_eq!(login.display_name, "Steve");
+        assert_eq!(login.xuid, "1111");
+        assert!(login.authenticated);
+        assert_eq!(login.identity_public_key, key(&keys.client));
+    }
+
+    #[test]
+    fn self_signed() {
+        let keys = Keys::new();
+        let chain = [sign_jwt(
+            &keys.client,
+            &json!({
+                "identityPublicKey": key(&keys.client),
+                "extraData": extra_data("Steve", ""),
+            }),
+        )];
+
+        let login = keys
+            .verifier(false)
+            .verify(&identity(&chain), &keys.client_data())
+            .unwrap();
+        assert!(!login.authenticated);
+
+        let online = keys.verifier(true);
+        let result = online.verify(&identity(&chain), &keys.client_data());
+        assert!(matches!(result, Err(LoginError::NotAuthenticated)));
+    }
+
+    #[test]
+    fn forged_root() {
+        let keys = Keys::new();
+        let forger = SigningKey::random(&mut OsRng);
+        let chain = keys.chain(&forger);
+
+        let result = keys
+            .verifier(true)
+            .verify(&identity(&chain), &keys.client_data());
+        assert!(matches!(result, Err(LoginError::NotAuthenticated)));
+
+        // offline it's fine, it just isn't authenticated
+        let login = keys
+            .verifier(false)
+            .verify(&identity(&chain), &keys.client_data())
+            .unwrap();
+        assert!(!login.authenticated);
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let keys = Keys::new();
+        let mut chain = keys.chain(&keys.mojang);
+
+        let parts: Vec<&str> = chain[2].split('.').collect();
+        let payload = json!({
+            "identityPublicKey": key(&keys.client),
+            "extraData": extra_data("Notch", "2222"),
+        });
+        chain[2] = format!(
+            "{}.{}.{}",
+            parts[0],
+            URL_SAFE_NO_PAD.encode(payload.to_string()),
+            parts[2]
+        );
+
+        let result = keys
+            .verifier(true)
+            .verify(&identity(&chain), &keys.client_data());
+        assert!(matches!(result, Err(LoginError::BadSignature)));
+    }
+
+    #[test]
+    fn appended_token() {
+        let keys = Keys::new();
+        // signed with the player's own key, which the last real token
+        // vouches for
+        let forged = sign_jwt(
+            &keys.client,
+            &json!({
+                "identityPublicKey": key(&keys.client),
+                "extraData": extra_data("Notch", "2222"),
+            }),
+        );
+
+        let mut chain = keys.chain(&keys.mojang);
+        chain.push(forged.clone());
+        let result = keys
+            .verifier(true)
+            .verify(&identity(&chain), &keys.client_data());
+        assert!(matches!(result, Err(LoginError::Malformed(_))));
+
+        // short enough without the self signed one, but the real identity's
+        // still in there
+        let mut chain = keys.chain(&keys.mojang).split_off(1);
+        chain.push(forged);
+        let result = keys
+            .verifier(false)
+            .verify(&identity(&chain), &keys.client_data());
+        assert!(matches!(result, Err(LoginError::Malformed(_))));
+    }
+
+    #[test]
+    fn identity_after_mojang() {
+        let keys = Keys::new();
+        let player = SigningKey::random(&mut OsRng);
+        // Mojang's token and Xbox Live's without an identity, then one
+        // signed by a key Xbox Live handed out that has one
+        let chain = [
+            sign_jwt(&keys.mojang, &json!({"identityPublicKey": key(&keys.xbox)})),
+            sign_jwt(&keys.xbox, &json!({"identityPublicKey": key(&player)})),
+            sign_jwt(
+                &player,
+                &json!({
+                    "identityPublicKey": key(&keys.client),
+                    "extraData": extra_data("Notch", "2222"),
+                }),
+            ),
+        ];
+
+        for online_mode in [true, false] {
+            let result = keys
+                .verifier(online_mode)
+                .verify(&identity(&chain), &keys.client_data());
+            assert!(matches!(result, Err(LoginError::NotAuthenticated)));
+        }
+    }
+
+    #[test]
+    fn skin_dimensions() {
+        let skin = |width: u64, height: u64, pixels: &[u8]| {
+            parse_skin(&json!({
+                "SkinId": "Standard_Custom",
+                "SkinImageWidth": width,
+                "SkinImageHeight": height,
+                "SkinData": STANDARD.encode(pixels),
+            }))
+        };
+
+        assert!(skin(1, 2, &[0; 8]).is_ok());
+        assert!(matches!(skin(1, 2, &[0; 4]), Err(LoginError::Malformed(_))));
+        // 65536 squared times 4 is 0 in a u32
+        assert!(matches!(
+            skin(65536, 65536, &[]),
+            Err(LoginError::Malformed(_))
+        ));
+        assert!(matches!(
+            skin(u32::MAX as u64, u32::MAX as u64, &[]),
+            Err(LoginError::Malformed(_))
+        ));
+        assert!(matches!(
+            skin(1 << 32, 0, &[]),
+            Err(LoginError::Malformed(_))
+        ));
+    }
+}
@@ -1 +1,8 @@
-pub(crate) mod v622;
+pub mod batch;
+pub mod encryption;
+pub mod login;
+pub mod native;
+pub mod packets;
+// generated, so not everything's used and clippy doesn't get a say
+#[allow(dead_code, unused, clippy::all)]
+pub mod v622;
@@ -0,0 +1,91 @@
+/// protocol/native.rs
+/// ==================
+///
+/// The types protocol.json calls "native", i.e. the ones it doesn't
+/// describe and expects the implementation to know about. The simple ones
+/// (varints, zigzags, uuids...) are on MsgBuffer, this is for the rest.
+///
+/// Reference: https://github.com/PrismarineJS/bedrock-protocol (src/datatypes)
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub use crate::nbt::Nbt;
+use crate::nbt::{self, NbtFlavour, Tag};
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+
+pub fn read_nbt(buf: &mut MsgBuffer) -> Result<Nbt, DecodeError> {
+    nbt::read(buf, NbtFlavour::Network).map_err(DecodeError::from)
+}
+
+pub fn write_nbt(buf: &mut MsgBuffer, value: &Nbt) {
+    nbt::write(buf, value, NbtFlavour::Network);
+}
+
+pub fn read_lnbt(buf: &mut MsgBuffer) -> Result<Nbt, DecodeError> {
+    nbt::read(buf, NbtFlavour::LittleEndian).map_err(DecodeError::from)
+}
+
+pub fn write_lnbt(buf: &mut MsgBuffer, value: &Nbt) {
+    nbt::write(buf, value, NbtFlavour::LittleEndian);
+}
+
+/// Named tags one after another until an End, so really the inside of a
+/// compound without the compound around it
+pub fn read_nbt_loop(buf: &mut MsgBuffer) -> Result<Vec<Nbt>, DecodeError> {
+    let mut tags = vec![];
+    loop {
+        let tag = read_nbt(buf)?;
+        if tag.tag == Tag::End {
+            return Ok(tags);
+        }
+        tags.push(tag);
+    }
+}
+
+pub fn write_nbt_loop(buf: &mut MsgBuffer, value: &[Nbt]) {
+    for tag in value {
+        write_nbt(buf, tag);
+    }
+    buf.write_byte(0);
+}
+
+/// AvailableCommands sends enum value indices as small as they can be,
+/// which depends on how many values there are. Nothing's actually sent
+/// for this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumSize {
+    Byte,
+    Short,
+    Int,
+}
+
+impl EnumSize {
+    pub fn for_len(len: usize) -> Self {
+        if len <= 0xff {
+            EnumSize::Byte
+        } else if len <= 0xffff {
+            EnumSize::Short
+        } else {
+            EnumSize::Int
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnumSize::Byte => "byte",
+            EnumSize::Short => "short",
+            EnumSize::Int => "int",
+        }
+    }
+}
+
+// Shields are special cased in item data, and their network id is only
+// known once the item registry is sent
+static SHIELD_ITEM_ID: AtomicI32 = AtomicI32::new(0);
+
+pub fn shield_item_id() -> i32 {
+    SHIELD_ITEM_ID.load(Ordering::Relaxed)
+}
+
+pub fn set_shield_item_id(id: i32) {
+    SHIELD_ITEM_ID.store(id, Ordering::Relaxed);
+}
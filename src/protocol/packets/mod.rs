@@ -0,0 +1,6 @@
+/// Game packets, the ones that live inside 0xfe batches. The packets
+/// themselves are generated (see v622.rs), this is what they have in
+/// common.
+pub(crate) mod obj;
+
+pub use obj::{decode_header, GamePacket};
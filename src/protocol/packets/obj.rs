@@ -0,0 +1,30 @@
+use crate::raknet::objects::{to_i32_varint_bytes, DecodeError, MsgBuffer};
+use crate::raknet::packets::ToBuffer;
+
+pub trait GamePacket {
+    const ID: u32;
+
+    /// The whole packet, header included, ready to be put in a batch
+    fn encode(&self) -> Vec<u8>
+    where
+        Self: ToBuffer,
+    {
+        // sub_client_id << 12 | sub_sender_id << 10 | packet_id
+        let mut packet = to_i32_varint_bytes(Self::ID as i32);
+        packet.extend_from_slice(self.to_buffer().get_bytes());
+        packet
+    }
+}
+
+/// Splits the header off a game packet, gives back `(packet_id, body)`
+pub fn decode_header(packet: Vec<u8>) -> Result<(u32, MsgBuffer), DecodeError> {
+    let mut reader = MsgBuffer::from(packet);
+    let header = reader.read_i32_varint_bytes()?;
+    let (_sub_client_id, _sub_sender_id, packet_id) = (
+        (header & 0x3000) >> 12,
+        (header & 0xc00) >> 10,
+        header & 0x3ff,
+    );
+
+    Ok((packet_id as u32, reader))
+}
@@ -0,0 +1,98 @@
+/// protocol/v622.rs
+/// ================
+///
+/// Every type and packet in protocol.json (1.20.40, protocol 622),
+/// generated at build time by build/aubep.rs.
+use crate::protocol::native;
+use crate::protocol::packets::GamePacket;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
+use crate::raknet::packets::{FromBuffer, ToBuffer};
+
+include!(concat!(env!("OUT_DIR"), "/v622.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<P: FromBuffer + ToBuffer + PartialEq + std::fmt::Debug>(packet: P) {
+        let mut buf = packet.to_buffer();
+        assert_eq!(P::from_buffer(&mut buf).unwrap(), packet);
+        assert!(buf.at_end());
+    }
+
+    #[test]
+    fn login() {
+        // the tokens are in a length prefixed buffer of their own
+        round_trip(Login {
+            protocol_version: 622,
+            tokens: LoginTokens {
+                identity: r#"{"chain":[]}"#.to_string(),
+                client: "header.payload.signature".to_string(),
+            },
+        });
+    }
+
+    #[test]
+    fn move_player() {
+        // the teleport's only there in teleport mode
+        let teleport = MovePlayer {
+            runtime_id: 1,
+            position: Vec3f {
+                x: 0.5,
+                y: 65.62,
+                z: -0.5,
+            },
+            pitch: 10.0,
+            yaw: -90.0,
+            head_yaw: -90.0,
+            mode: MovePlayerMode::Teleport,
+            on_ground: true,
+            ridden_runtime_id: 0,
+            teleport: Some(MovePlayerTeleport {
+                cause: MovePlayerTeleportCause::Command,
+                source_entity_type: LegacyEntityType::from_value(0),
+            }),
+            tick: 1234,
+        };
+        round_trip(teleport.clone());
+        round_trip(MovePlayer {
+            mode: MovePlayerMode::Normal,
+            teleport: None,
+            ..teleport
+        });
+    }
+
+    #[test]
+    fn resource_pack_stack() {
+        let entry = |uuid: &str| ResourcePackIdVersionsEntry {
+            uuid: uuid.to_string(),
+            version: "1.0.0".to_string(),
+            name: String::new(),
+        };
+
+        round_trip(ResourcePackStack {
+            must_accept: true,
+            behavior_packs: vec![entry("a")],
+            resource_packs: vec![entry("b"), entry("c")],
+            game_version: "1.20.40".to_string(),
+            experiments: vec![Experiment {
+                name: "data_driven_items".to_string(),
+                enabled: true,
+            }],
+            experiments_previously_used: false,
+        });
+    }
+
+    #[test]
+    fn unrecognized_values_survive() {
+        round_trip(PlayStatus {
+            status: PlayStatusStatus::from_value(42),
+        });
+        round_trip(UpdateBlock {
+            position: BlockCoordinates { x: -1, y: 64, z: 1 },
+            block_runtime_id: 7,
+            flags: UpdateBlockFlags(UpdateBlockFlags::NETWORK | 0x100),
+            layer: 1,
+        });
+    }
+}
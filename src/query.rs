@@ -0,0 +1,264 @@
+/// query.rs
+/// ========
+///
+/// The GameSpy4 query protocol server lists use to get info on a server.
+/// Shares the game port with RakNet, query packets always start with
+/// 0xFE 0xFD so they can't be confused for anything RakNet sends.
+///
+/// Reference: https://wiki.vg/Query
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use rand::Rng;
+
+use crate::raknet::objects::{get_unix_milis, MsgBuffer};
+use crate::status::ServerStatus;
+
+pub const QUERY_MAGIC: [u8; 2] = [0xfe, 0xfd];
+
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+// challenge tokens are only good for this long
+const TOKEN_LIFETIME: u128 = 30_000;
+
+pub struct QueryHandler {
+    tokens: HashMap<SocketAddr, (i32, u128)>,
+    host_ip: String,
+}
+
+impl QueryHandler {
+    pub fn new(host_ip: String) -> Self {
+        Self {
+            tokens: HashMap::new(),
+            host_ip,
+        }
+    }
+
+    pub fn is_query(datagram: &[u8]) -> bool {
+        datagram.len() >= 7 && datagram[..2] == QUERY_MAGIC
+    }
+
+    /// Takes the whole datagram, magic included. `None` means we ignore it,
+    /// which is also what vanilla does with bad tokens.
+    pub fn handle(
+        &mut self,
+        datagram: &[u8],
+        client: SocketAddr,
+        status: &ServerStatus,
+    ) -> Option<Vec<u8>> {
+        let mut buf = MsgBuffer::from(datagram[2..].to_vec());
+        let packet_type = buf.read_byte().ok()?;
+        let session_id = buf.read_i32_be_bytes().ok()? & 0x0f0f0f0f;
+
+        let now = get_unix_milis();
+        self.tokens
+            .retain(|_, (_, issued)| now - *issued < TOKEN_LIFETIME);
+
+        match packet_type {
+            HANDSHAKE => {
+                let token = rand::thread_rng().gen_range(1..=i32::MAX);
+                self.tokens.insert(client, (token, now));
+
+                let mut resp = MsgBuffer::new();
+                resp.write_byte(HANDSHAKE);
+                resp.write_i32_be_bytes(session_id);
+                write_cstring(&mut resp, &token.to_string());
+                Some(resp.get_bytes().clone())
+            }
+            STAT => {
+                let token = buf.read_i32_be_bytes().ok()?;
+                match self.tokens.get(&client) {
+                    Some((expected, _)) if *expected == token => {}
+                    _ => return None,
+                }
+
+                // a full stat request is padded with 4 more bytes
+                let full = buf.len_rest() >= 4;
+
+                let mut resp = MsgBuffer::new();
+                resp.write_byte(STAT);
+                resp.write_i32_be_bytes(session_id);
+                if full {
+                    self.write_full_stat(&mut resp, status);
+                } else {
+                    self.write_basic_stat(&mut resp, status);
+                }
+                Some(resp.get_bytes().clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn write_basic_stat(&self, buf: &mut MsgBuffer, status: &ServerStatus) {
+        write_cstring(buf, &status.server_name);
+        write_cstring(buf, "SMP");
+        write_cstring(buf, &status.level_name);
+        write_cstring(buf, &status.online_players.to_string());
+        write_cstring(buf, &status.max_players.to_string());
+        buf.write_u16_le_bytes(status.port_v4);
+        write_cstring(buf, &self.host_ip);
+    }
+
+    fn write_full_stat(&self, buf: &mut MsgBuffer, status: &ServerStatus) {
+        // constant padding, it's just what the protocol looks like
+        buf.write(b"splitnum\x00\x80\x00");
+
+        let pairs = [
+            ("hostname", status.server_name.clone()),
+            ("gametype", "SMP".to_string()),
+            ("game_id", "MINECRAFTPE".to_string()),
+            ("version", status.version.clone()),
+            ("server_engine", "Voxel".to_string()),
+            // "<server mod name>: <plugin>; <plugin>", we don't have any
+            ("plugins", "Voxel".to_string()),
+            ("map", status.level_name.clone()),
+            ("numplayers", status.online_players.to_string()),
+            ("maxplayers", status.max_players.to_string()),
+            ("hostip", self.host_ip.clone()),
+            ("hostport", status.port_v4.to_string()),
+        ];
+        for (key, value) in pairs {
+            write_cstring(buf, key);
+            write_cstring(buf, &value);
+        }
+        buf.write_byte(0x00);
+
+        buf.write(b"\x01player_\x00\x00");
+        for (_, player) in &status.players {
+            write_cstring(buf, player);
+        }
+        buf.write_byte(0x00);
+    }
+}
+
+fn write_cstring(buf: &mut MsgBuffer, value: &str) {
+    buf.write(value.replace('\0', "").as_bytes());
+    buf.write_byte(0x00);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raknet::enums::Gamemode;
+
+    fn status() -> ServerStatus {
+        ServerStatus {
+            server_name: "A server".to_string(),
+            level_name: "world".to_string(),
+            protocol_version: 622,
+            version: "1.20.41".to_string(),
+            online_players: 2,
+            players: vec![
+                ("127.0.0.1:50000".parse().unwrap(), "Steve".to_string()),
+                ("127.0.0.1:50001".parse().unwrap(), "Alex".to_string()),
+            ],
+            max_players: 20,
+            gamemode: Gamemode::Survival,
+            port_v4: 19132,
+            port_v6: 19133,
+        }
+    }
+
+    fn request(packet_type: u8, session_id: i32, rest: &[u8]) -> Vec<u8> {
+        let mut request = QUERY_MAGIC.to_vec();
+        request.push(packet_type);
+        request.extend(session_id.to_be_bytes());
+        request.extend(rest);
+        request
+    }
+
+    /// The token from a handshake's response
+    fn handshake(query: &mut QueryHandler, client: SocketAddr) -> i32 {
+        let response = query
+            .handle(&request(HANDSHAKE, 0x01020304, &[]), client, &status())
+            .unwrap();
+        assert_eq!(response[..5], [HANDSHAKE, 1, 2, 3, 4]);
+        assert_eq!(response.last(), Some(&0));
+
+        let token = std::str::from_utf8(&response[5..response.len() - 1]).unwrap();
+        token.parse().unwrap()
+    }
+
+    fn stat(
+        query: &mut QueryHandler,
+        client: SocketAddr,
+        token: i32,
+        full: bool,
+    ) -> Option<Vec<u8>> {
+        let mut rest = token.to_be_bytes().to_vec();
+        if full {
+            rest.extend([0; 4]);
+        }
+        query.handle(&request(STAT, 0x01020304, &rest), client, &status())
+    }
+
+    #[test]
+    fn is_query() {
+        assert!(QueryHandler::is_query(&request(HANDSHAKE, 1, &[])));
+        assert!(!QueryHandler::is_query(&[0xfe, 0xfd, 0x09]));
+        // RakNet's unconnected ping
+        assert!(!QueryHandler::is_query(&[0x01; 33]));
+    }
+
+    #[test]
+    fn tokens() {
+        let mut query = QueryHandler::new("0.0.0.0".to_string());
+        let client = "10.0.0.1:1234".parse().unwrap();
+        let other = "10.0.0.2:1234".parse().unwrap();
+
+        // not before a handshake
+        assert_eq!(stat(&mut query, client, 1, false), None);
+
+        let token = handshake(&mut query, client);
+        assert!(token > 0);
+        assert!(stat(&mut query, client, token, false).is_some());
+        assert_eq!(stat(&mut query, client, token.wrapping_add(1), false), None);
+        // it's only good for whoever it was given to
+        assert_eq!(stat(&mut query, other, token, false), None);
+
+        // or for so long
+        query.tokens.get_mut(&client).unwrap().1 -= TOKEN_LIFETIME;
+        assert_eq!(stat(&mut query, client, token, false), None);
+        assert!(query.tokens.is_empty());
+    }
+
+    #[test]
+    fn basic_stat() {
+        let mut query = QueryHandler::new("10.0.0.10".to_string());
+        let client = "10.0.0.1:1234".parse().unwrap();
+        let token = handshake(&mut query, client);
+
+        let response = stat(&mut query, client, token, false).unwrap();
+        let mut expected = vec![STAT, 1, 2, 3, 4];
+        expected.extend(b"A server\0SMP\0world\x002\x0020\0");
+        // the port's the one little endian thing in it
+        expected.extend(19132u16.to_le_bytes());
+        expected.extend(b"10.0.0.10\0");
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn full_stat() {
+        let mut query = QueryHandler::new("10.0.0.10".to_string());
+        let client = "10.0.0.1:1234".parse().unwrap();
+        let token = handshake(&mut query, client);
+
+        let response = stat(&mut query, client, token, true).unwrap();
+        assert_eq!(response[..5], [STAT, 1, 2, 3, 4]);
+        let body = &response[5..];
+        assert!(body.starts_with(b"splitnum\x00\x80\x00"));
+
+        let (pairs, players) =
+            body[11..].split_at(body[11..].windows(2).position(|w| w == b"\0\0").unwrap() + 2);
+        let pairs: Vec<&[u8]> = pairs[..pairs.len() - 2].split(|b| *b == 0).collect();
+        let pairs: HashMap<&[u8], &[u8]> = pairs.chunks(2).map(|kv| (kv[0], kv[1])).collect();
+        assert_eq!(pairs[&b"hostname"[..]], b"A server");
+        assert_eq!(pairs[&b"version"[..]], b"1.20.41");
+        assert_eq!(pairs[&b"numplayers"[..]], b"2");
+        assert_eq!(pairs[&b"maxplayers"[..]], b"20");
+        assert_eq!(pairs[&b"hostport"[..]], b"19132");
+        assert_eq!(pairs.len(), 11);
+
+        assert_eq!(players, b"\x01player_\0\0Steve\0Alex\0\0");
+    }
+}
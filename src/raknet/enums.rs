@@ -23,9 +23,80 @@
 //     GamePacket = 0xfe,
 // }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Gamemode {
     Survival = 0,
     Creative,
     Adventure,
     Spectator,
 }
+
+impl Gamemode {
+    /// For the config error when it's none of them
+    pub const EXPECTED: &'static str = "survival, creative, adventure, spectator or 0 to 3";
+
+    /// The name or the number, like vanilla's server.properties takes.
+    /// Spectator's 3 going by the order here, or 6 going by `id`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "survival" | "0" => Some(Gamemode::Survival),
+            "creative" | "1" => Some(Gamemode::Creative),
+            "adventure" | "2" => Some(Gamemode::Adventure),
+            "spectator" | "3" | "6" => Some(Gamemode::Spectator),
+            _ => None,
+        }
+    }
+
+    /// What the game calls it (GameType), for the MOTD and level.dat.
+    /// 3 to 5 are modes nobody uses any more, so spectator's 6.
+    pub fn id(self) -> i32 {
+        match self {
+            Gamemode::Survival => 0,
+            Gamemode::Creative => 1,
+            Gamemode::Adventure => 2,
+            Gamemode::Spectator => 6,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gamemode::Survival => "Survival",
+            Gamemode::Creative => "Creative",
+            Gamemode::Adventure => "Adventure",
+            Gamemode::Spectator => "Spectator",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamemode_names() {
+        for gamemode in [
+            Gamemode::Survival,
+            Gamemode::Creative,
+            Gamemode::Adventure,
+            Gamemode::Spectator,
+        ] {
+            assert_eq!(Gamemode::from_name(gamemode.name()), Some(gamemode));
+            assert_eq!(
+                Gamemode::from_name(&(gamemode as u8).to_string()),
+                Some(gamemode)
+            );
+        }
+
+        assert_eq!(Gamemode::from_name("CREATIVE"), Some(Gamemode::Creative));
+        assert_eq!(Gamemode::from_name("6"), Some(Gamemode::Spectator));
+        assert_eq!(Gamemode::from_name("4"), None);
+        assert_eq!(Gamemode::from_name("hardcore"), None);
+    }
+
+    #[test]
+    fn gamemode_ids() {
+        assert_eq!(Gamemode::Survival.id(), 0);
+        assert_eq!(Gamemode::Adventure.id(), 2);
+        assert_eq!(Gamemode::Spectator.id(), 6);
+    }
+}
@@ -1,4 +1,4 @@
-mod enums;
+pub(crate) mod enums;
 pub mod objects;
 pub(crate) mod packets;
 pub mod server;
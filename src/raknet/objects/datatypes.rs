@@ -9,7 +9,7 @@
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::MsgBuffer;
+use super::{DecodeError, MsgBuffer};
 
 // long (64)
 pub fn from_i64_be_bytes(bytes: [u8; 8]) -> i64 {
@@ -61,42 +61,61 @@ pub fn to_f32_le_bytes(value: f32) -> [u8; 4] {
     value.to_le_bytes()
 }
 
-pub fn from_i32_varint_bytes(buf: &mut MsgBuffer) -> i32 {
-    // taken from JSPrismarine
-    // resource: https://protobuf.dev/programming-guides/encoding/
-
-    let mut value = 0;
-    let mut c = 0;
-    loop {
-        let b = buf.read_byte();
-        value |= (b & 0x7f) << c;
+// varints, 7 bits at a time with the top bit saying there's more
+// resource: https://protobuf.dev/programming-guides/encoding/
+pub fn from_u64_varint_bytes(buf: &mut MsgBuffer, max_bytes: u32) -> Result<u64, DecodeError> {
+    let mut value: u64 = 0;
+    for i in 0..max_bytes {
+        let b = buf.read_byte()? as u64;
+        value |= (b & 0x7f) << (i * 7);
 
         if (b & 0x80) == 0 {
-            return value as i32;
-        }
-
-        c += 7;
-        if c >= 28 {
-            panic!("Varint length not within constraints")
+            return Ok(value);
         }
     }
+    Err(DecodeError::VarintTooLong)
 }
 
-pub fn to_i32_varint_bytes(value: i32) -> Vec<u8> {
-    // Taken from nukkit
-    // resource: https://stackoverflow.com/questions/70212075/how-to-make-unsigned-right-shift-in-rust
-    let mut value = u32::from_ne_bytes(value.to_ne_bytes());
-
+pub fn to_u64_varint_bytes(mut value: u64) -> Vec<u8> {
+    // always at least one byte, zero is 0x00 and not nothing
     let mut vec = vec![];
-    while value != 0 {
-        let mut temp: u8 = (value & 0b01111111_u32) as u8; // cast here won't fail (probably)
+    loop {
+        let temp = (value & 0x7f) as u8;
         value >>= 7;
-        if value != 0 {
-            temp |= 0b10000000;
+        if value == 0 {
+            vec.push(temp);
+            return vec;
         }
-        vec.push(temp);
+        vec.push(temp | 0x80);
     }
-    vec
+}
+
+pub fn from_i32_varint_bytes(buf: &mut MsgBuffer) -> Result<i32, DecodeError> {
+    Ok(from_u64_varint_bytes(buf, 5)? as u32 as i32)
+}
+
+pub fn to_i32_varint_bytes(value: i32) -> Vec<u8> {
+    // negative numbers are sent as their u32 bits, so always 5 bytes
+    to_u64_varint_bytes(value as u32 as u64)
+}
+
+// zigzag moves the sign into the lowest bit so small negative numbers
+// stay small: 0, -1, 1, -2, 2 => 0, 1, 2, 3, 4
+// resource: https://lemire.me/blog/2022/11/25/making-all-your-integers-positive-with-zigzag-encoding/
+pub fn to_zigzag32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+pub fn from_zigzag32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+pub fn to_zigzag64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn from_zigzag64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
 }
 
 // triad (24)
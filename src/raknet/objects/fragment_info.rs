@@ -3,7 +3,7 @@
 ///
 /// Class that holds information on fragments. To be
 /// developed on more later. Refer to frame.rs.
-use super::msgbuffer::MsgBuffer;
+use super::msgbuffer::{DecodeError, MsgBuffer};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct FragmentInfo {
@@ -14,20 +14,25 @@ pub struct FragmentInfo {
 }
 
 impl FragmentInfo {
+    /// Bit in the frame flags saying it's a fragment
+    pub const FLAG: u8 = 0x10;
+
     pub fn new(flags: u8) -> Self {
         Self {
-            is_fragmented: (flags & 1) != 0,
+            is_fragmented: (flags & Self::FLAG) != 0,
             compound_size: None,
             compound_id: None,
             index: None,
         }
     }
 
-    pub fn extract(&mut self, buf: &mut MsgBuffer) {
+    pub fn extract(&mut self, buf: &mut MsgBuffer) -> Result<(), DecodeError> {
         if self.is_fragmented {
-            self.compound_size = Some(buf.read_i32_be_bytes());
-            self.compound_id = Some(buf.read_i16_be_bytes());
-            self.index = Some(buf.read_i32_be_bytes());
+            self.compound_size = Some(buf.read_i32_be_bytes()?);
+            self.compound_id = Some(buf.read_i16_be_bytes()?);
+            self.index = Some(buf.read_i32_be_bytes()?);
         }
+
+        Ok(())
     }
 }
@@ -1,4 +1,5 @@
 /// Various serialization/deserialisation objects RakNet uses
+#[allow(dead_code)] // not everything's used (yet)
 pub mod datatypes;
 pub mod fragment_info;
 pub mod msgbuffer;
@@ -6,5 +7,5 @@ pub mod reliability;
 
 pub use datatypes::*;
 pub use fragment_info::FragmentInfo;
-pub use msgbuffer::MsgBuffer;
+pub use msgbuffer::{DecodeError, MsgBuffer};
 pub use reliability::Reliability;
@@ -2,12 +2,15 @@
 /// ===========================
 ///
 /// A wrapper class to make it easier to read and
-/// write bytes.
-use std::io::Read;
+/// write bytes. Reads give back a DecodeError instead of panicking when
+/// there isn't enough left, since most of what we read came from whoever
+/// sent it.
+use std::fmt;
 use std::net::SocketAddr;
 
 use super::datatypes::*;
 
+#[allow(dead_code)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum PacketPriority {
     Immediate = 8,
@@ -44,6 +47,32 @@ impl PartialOrd for SendPacket {
     }
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    /// Wanted more bytes than were left
+    Truncated {
+        wanted: usize,
+        left: usize,
+    },
+    VarintTooLong,
+    /// A length or count that can't be right, e.g. a negative one
+    BadLength(i64),
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated { wanted, left } => {
+                write!(f, "wanted {wanted} bytes but only {left} were left")
+            }
+            DecodeError::VarintTooLong => write!(f, "varint is too long"),
+            DecodeError::BadLength(len) => write!(f, "bad length {len}"),
+            DecodeError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MsgBuffer {
     buffer: Vec<u8>,
@@ -78,25 +107,29 @@ impl MsgBuffer {
         self.len() - self.pos
     }
 
-    pub fn read(&mut self, num: u64, buf: &mut [u8]) -> usize {
-        let res = self.buffer[self.pos..].take(num).read(buf);
-        self.pos += num as usize;
+    /// The next `num` bytes, if there are that many
+    fn take(&mut self, num: usize) -> Result<&[u8], DecodeError> {
+        let left = self.len_rest();
+        if num > left {
+            return Err(DecodeError::Truncated { wanted: num, left });
+        }
+        self.pos += num;
 
-        res.expect("Failed to read")
+        Ok(&self.buffer[self.pos - num..self.pos])
     }
 
-    pub fn read_vec(&mut self, num: usize) -> Vec<u8> {
-        let res = self.buffer[self.pos..self.pos + num].to_vec();
-        self.pos += num;
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<(), DecodeError> {
+        buf.copy_from_slice(self.take(buf.len())?);
 
-        res
+        Ok(())
     }
 
-    pub fn read_byte(&mut self) -> u8 {
-        let result = self.buffer[self.pos];
-        self.pos += 1;
+    pub fn read_vec(&mut self, num: usize) -> Result<Vec<u8>, DecodeError> {
+        Ok(self.take(num)?.to_vec())
+    }
 
-        result
+    pub fn read_byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
     }
 
     pub fn write(&mut self, data: &[u8]) {
@@ -107,89 +140,98 @@ impl MsgBuffer {
         self.buffer.push(data);
     }
 
-    pub fn read_i64_be_bytes(&mut self) -> i64 {
+    pub fn read_i64_be_bytes(&mut self) -> Result<i64, DecodeError> {
         let mut result = [0u8; 8];
-        self.read(8, &mut result);
+        self.read(&mut result)?;
 
-        from_i64_be_bytes(result)
+        Ok(from_i64_be_bytes(result))
     }
 
     pub fn write_i64_be_bytes(&mut self, value: i64) {
         self.write(&to_i64_be_bytes(value));
     }
 
-    pub fn read_i32_be_bytes(&mut self) -> i32 {
+    pub fn read_i32_be_bytes(&mut self) -> Result<i32, DecodeError> {
         let mut result = [0u8; 4];
-        self.read(4, &mut result);
+        self.read(&mut result)?;
 
-        from_i32_be_bytes(result)
+        Ok(from_i32_be_bytes(result))
     }
 
     pub fn write_i32_be_bytes(&mut self, value: i32) {
         self.write(&to_i32_be_bytes(value));
     }
 
-    pub fn read_f32_le_bytes(&mut self) -> f32 {
+    pub fn read_i32_le_bytes(&mut self) -> Result<i32, DecodeError> {
+        let mut result = [0u8; 4];
+        self.read(&mut result)?;
+
+        Ok(from_i32_le_bytes(result))
+    }
+
+    #[allow(dead_code)]
+    pub fn read_f32_le_bytes(&mut self) -> Result<f32, DecodeError> {
         let mut result = [0u8; 4];
-        self.read(4, &mut result);
+        self.read(&mut result)?;
 
-        from_f32_le_bytes(result)
+        Ok(from_f32_le_bytes(result))
     }
 
     pub fn write_f32_le_bytes(&mut self, value: f32) {
         self.write(&to_f32_le_bytes(value));
     }
 
-    pub fn read_u24_le_bytes(&mut self) -> u32 {
+    pub fn read_u24_le_bytes(&mut self) -> Result<u32, DecodeError> {
         // we pretend it's a u24 but really we're using u32
         let mut result = [0u8; 3];
-        self.read(3, &mut result);
+        self.read(&mut result)?;
 
-        from_u24_le_bytes_to_u32(result)
+        Ok(from_u24_le_bytes_to_u32(result))
     }
 
     pub fn write_u24_le_bytes(&mut self, value: u32) {
         self.write(&to_u24_le_bytes(value));
     }
 
-    pub fn read_i16_be_bytes(&mut self) -> i16 {
+    pub fn read_i16_be_bytes(&mut self) -> Result<i16, DecodeError> {
         let mut result = [0u8; 2];
-        self.read(2, &mut result);
+        self.read(&mut result)?;
 
-        from_i16_be_bytes(result)
+        Ok(from_i16_be_bytes(result))
     }
 
     pub fn write_i16_be_bytes(&mut self, value: i16) {
         self.write(&to_i16_be_bytes(value));
     }
 
-    pub fn read_u16_be_bytes(&mut self) -> u16 {
+    pub fn read_u16_be_bytes(&mut self) -> Result<u16, DecodeError> {
         let mut result = [0u8; 2];
-        self.read(2, &mut result);
+        self.read(&mut result)?;
 
-        from_u16_be_bytes(result)
+        Ok(from_u16_be_bytes(result))
     }
 
     pub fn write_u16_be_bytes(&mut self, value: u16) {
         self.write(&to_u16_be_bytes(value));
     }
 
-    pub fn read_u16_le_bytes(&mut self) -> u16 {
+    #[allow(dead_code)]
+    pub fn read_u16_le_bytes(&mut self) -> Result<u16, DecodeError> {
         let mut result = [0u8; 2];
-        self.read(2, &mut result);
+        self.read(&mut result)?;
 
-        from_u16_le_bytes(result)
+        Ok(from_u16_le_bytes(result))
     }
 
     pub fn write_u16_le_bytes(&mut self, value: u16) {
         self.write(&to_u16_le_bytes(value));
     }
 
-    pub fn read_magic(&mut self) -> [u8; 16] {
+    pub fn read_magic(&mut self) -> Result<[u8; 16], DecodeError> {
         let mut magic = [0u8; 16];
-        self.read(16, &mut magic);
+        self.read(&mut magic)?;
 
-        magic
+        Ok(magic)
     }
 
     pub fn write_magic(&mut self, magic: &[u8; 16]) {
@@ -204,19 +246,29 @@ impl MsgBuffer {
         self.write(&str);
     }
 
-    pub fn read_address(&mut self) -> SocketAddr {
-        let ipver = self.read_byte();
-
-        if ipver == 0x04 {
-            let mut bytes = [0u8; 6]; // 7-1
-            self.read(6, &mut bytes);
-            from_address_bytes(ipver, &bytes.to_vec())
-        } else {
-            // if ipver == 0x06
-            // new changes from nukkit
-            let mut bytes = [0u8; 28]; // 29-1
-            self.read(28, &mut bytes);
-            from_address_bytes(ipver, &bytes.to_vec())
+    pub fn read_string(&mut self) -> Result<String, DecodeError> {
+        let str_len = self.read_u16_be_bytes()? as usize;
+        Ok(String::from_utf8_lossy(self.take(str_len)?).to_string())
+    }
+
+    pub fn read_address(&mut self) -> Result<SocketAddr, DecodeError> {
+        let ipver = self.read_byte()?;
+
+        match ipver {
+            0x04 => {
+                let mut bytes = [0u8; 6]; // 7-1
+                self.read(&mut bytes)?;
+                Ok(from_address_bytes(ipver, &bytes.to_vec()))
+            }
+            0x06 => {
+                // new changes from nukkit
+                let mut bytes = [0u8; 28]; // 29-1
+                self.read(&mut bytes)?;
+                Ok(from_address_bytes(ipver, &bytes.to_vec()))
+            }
+            _ => Err(DecodeError::Invalid(format!(
+                "unknown address family {ipver}"
+            ))),
         }
     }
 
@@ -228,19 +280,274 @@ impl MsgBuffer {
         self.buffer.extend_from_slice(other)
     }
 
-    pub fn read_i32_varint_bytes(&mut self) -> i32 {
+    /// Fixed size chunk, for `i32::from_le_bytes(buf.read_array())` and friends
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut result = [0u8; N];
+        self.read(&mut result)?;
+
+        Ok(result)
+    }
+
+    pub fn read_rest(&mut self) -> Vec<u8> {
+        let rest = self.buffer[self.pos..].to_vec();
+        self.pos = self.buffer.len();
+
+        rest
+    }
+
+    pub fn read_i32_varint_bytes(&mut self) -> Result<i32, DecodeError> {
         from_i32_varint_bytes(self)
     }
 
-    pub fn read_zigzag32(&mut self) -> i32 {
-        // https://gist.github.com/mfuerstenau/ba870a29e16536fdbaba
-        // https://lemire.me/blog/2022/11/25/making-all-your-integers-positive-with-zigzag-encoding/
-        let val = self.read_i32_be_bytes();
-        if val < 0 {
-            return -2 * val - 1;
+    pub fn write_i32_varint_bytes(&mut self, value: i32) {
+        self.write(&to_i32_varint_bytes(value));
+    }
+
+    pub fn read_u32_varint_bytes(&mut self) -> Result<u32, DecodeError> {
+        Ok(from_u64_varint_bytes(self, 5)? as u32)
+    }
+
+    pub fn write_u32_varint_bytes(&mut self, value: u32) {
+        self.write(&to_u64_varint_bytes(value as u64));
+    }
+
+    pub fn read_u64_varint_bytes(&mut self) -> Result<u64, DecodeError> {
+        from_u64_varint_bytes(self, 10)
+    }
+
+    pub fn write_u64_varint_bytes(&mut self, value: u64) {
+        self.write(&to_u64_varint_bytes(value));
+    }
+
+    pub fn read_zigzag32(&mut self) -> Result<i32, DecodeError> {
+        Ok(from_zigzag32(self.read_u32_varint_bytes()?))
+    }
+
+    pub fn write_zigzag32(&mut self, value: i32) {
+        self.write_u32_varint_bytes(to_zigzag32(value));
+    }
+
+    pub fn read_zigzag64(&mut self) -> Result<i64, DecodeError> {
+        Ok(from_zigzag64(self.read_u64_varint_bytes()?))
+    }
+
+    pub fn write_zigzag64(&mut self, value: i64) {
+        self.write_u64_varint_bytes(to_zigzag64(value));
+    }
+
+    /// Two little endian u64s, most significant half first
+    pub fn read_uuid(&mut self) -> Result<u128, DecodeError> {
+        let most = u64::from_le_bytes(self.read_array()?) as u128;
+        let least = u64::from_le_bytes(self.read_array()?) as u128;
+
+        Ok((most << 64) | least)
+    }
+
+    pub fn write_uuid(&mut self, value: u128) {
+        self.write(&((value >> 64) as u64).to_le_bytes());
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    pub fn read_vec3f(&mut self) -> Result<[f32; 3], DecodeError> {
+        Ok([
+            self.read_f32_le_bytes()?,
+            self.read_f32_le_bytes()?,
+            self.read_f32_le_bytes()?,
+        ])
+    }
+
+    pub fn write_vec3f(&mut self, value: [f32; 3]) {
+        for v in value {
+            self.write_f32_le_bytes(v);
+        }
+    }
+
+    /// An angle squished into a byte, 256 steps for a full turn
+    pub fn read_byterot(&mut self) -> Result<f32, DecodeError> {
+        Ok(self.read_byte()? as f32 * (360.0 / 256.0))
+    }
+
+    pub fn write_byterot(&mut self, value: f32) {
+        self.write_byte((value.rem_euclid(360.0) / (360.0 / 256.0)) as u8);
+    }
+
+    /// Most strings in the game protocol, unlike RakNet's u16 ones
+    pub fn read_varint_string(&mut self) -> Result<String, DecodeError> {
+        let str_len = self.read_u32_varint_bytes()? as usize;
+        Ok(String::from_utf8_lossy(self.take(str_len)?).to_string())
+    }
+
+    pub fn write_varint_string(&mut self, str: &str) {
+        self.write_u32_varint_bytes(str.len() as u32);
+        self.write(str.as_bytes());
+    }
+
+    /// Strings with an i32 LE length, like the ones in Login
+    pub fn read_le_string(&mut self) -> Result<String, DecodeError> {
+        let str_len = self.read_i32_le_bytes()?;
+        if str_len < 0 || str_len as usize > self.len_rest() {
+            return Err(DecodeError::BadLength(str_len as i64));
+        }
+        Ok(String::from_utf8_lossy(self.take(str_len as usize)?).to_string())
+    }
+
+    pub fn write_le_string(&mut self, str: &str) {
+        self.write(&to_i32_le_bytes(str.len() as i32));
+        self.write(str.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(bytes: &[u8]) -> MsgBuffer {
+        MsgBuffer::from(bytes.to_vec())
+    }
+
+    #[test]
+    fn varints() {
+        let cases: &[(u64, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (i32::MAX as u64, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (u32::MAX as u64, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (
+                u64::MAX,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ];
+
+        for &(value, bytes) in cases {
+            let mut buf = MsgBuffer::new();
+            buf.write_u64_varint_bytes(value);
+            assert_eq!(buf.get_bytes()[..], *bytes, "writing {value}");
+            assert_eq!(reader(bytes).read_u64_varint_bytes().unwrap(), value);
+
+            if let Ok(value) = u32::try_from(value) {
+                let mut buf = MsgBuffer::new();
+                buf.write_u32_varint_bytes(value);
+                assert_eq!(buf.get_bytes()[..], *bytes, "writing {value}");
+                assert_eq!(reader(bytes).read_u32_varint_bytes().unwrap(), value);
+            }
         }
-        2 * val
     }
 
-    // implement other zigzags
+    #[test]
+    fn signed_varints() {
+        // negative i32s are sent as their u32, so always 5 bytes
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        ];
+
+        for &(value, bytes) in cases {
+            let mut buf = MsgBuffer::new();
+            buf.write_i32_varint_bytes(value);
+            assert_eq!(buf.get_bytes()[..], *bytes, "writing {value}");
+            assert_eq!(reader(bytes).read_i32_varint_bytes().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn zigzags() {
+        let cases32: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (i32::MAX, &[0xfe, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ];
+        for &(value, bytes) in cases32 {
+            let mut buf = MsgBuffer::new();
+            buf.write_zigzag32(value);
+            assert_eq!(buf.get_bytes()[..], *bytes, "writing {value}");
+            assert_eq!(reader(bytes).read_zigzag32().unwrap(), value);
+        }
+
+        let cases64: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (
+                i64::MAX,
+                &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+            (
+                i64::MIN,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ];
+        for &(value, bytes) in cases64 {
+            let mut buf = MsgBuffer::new();
+            buf.write_zigzag64(value);
+            assert_eq!(buf.get_bytes()[..], *bytes, "writing {value}");
+            assert_eq!(reader(bytes).read_zigzag64().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn varints_too_long() {
+        // a sixth byte for 32 bits, an eleventh for 64
+        let six = [0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        let eleven = [
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+
+        assert!(matches!(
+            reader(&six).read_u32_varint_bytes(),
+            Err(DecodeError::VarintTooLong)
+        ));
+        assert!(matches!(
+            reader(&six).read_zigzag32(),
+            Err(DecodeError::VarintTooLong)
+        ));
+        assert!(matches!(
+            reader(&eleven).read_u64_varint_bytes(),
+            Err(DecodeError::VarintTooLong)
+        ));
+        assert!(matches!(
+            reader(&eleven).read_zigzag64(),
+            Err(DecodeError::VarintTooLong)
+        ));
+        // but right up to the limit's fine
+        assert_eq!(reader(&six[1..]).read_u32_varint_bytes().unwrap(), 0);
+        assert_eq!(reader(&eleven[1..]).read_u64_varint_bytes().unwrap(), 0);
+    }
+
+    #[test]
+    fn varints_cut_short() {
+        assert!(matches!(
+            reader(&[0x80, 0x80]).read_u32_varint_bytes(),
+            Err(DecodeError::Truncated { .. })
+        ));
+        assert!(matches!(
+            reader(&[]).read_zigzag64(),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn le_strings() {
+        let mut buf = MsgBuffer::new();
+        buf.write_le_string("hello");
+        assert_eq!(buf.get_bytes()[..], *b"\x05\x00\x00\x00hello");
+        assert_eq!(reader(buf.get_bytes()).read_le_string().unwrap(), "hello");
+
+        let negative = [0xff, 0xff, 0xff, 0xff, b'a'];
+        assert!(matches!(
+            reader(&negative).read_le_string(),
+            Err(DecodeError::BadLength(-1))
+        ));
+        let too_long = [0x06, 0x00, 0x00, 0x00, b'h', b'e', b'l', b'l', b'o'];
+        assert!(matches!(
+            reader(&too_long).read_le_string(),
+            Err(DecodeError::BadLength(6))
+        ));
+    }
 }
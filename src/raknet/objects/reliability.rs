@@ -3,7 +3,7 @@
 ///
 /// Class to hold reliability type and data.
 /// Refer to frame.rs
-use super::MsgBuffer;
+use super::{DecodeError, MsgBuffer};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ReliabilityType {
@@ -83,7 +83,7 @@ pub struct Reliability {
 
 impl Reliability {
     // resource: http://www.jenkinssoftware.com/raknet/manual/reliabilitytypes.html
-    pub fn extract(flags: u8, buf: &mut MsgBuffer) -> Self {
+    pub fn extract(flags: u8, buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
         let reltype = ReliabilityType::from_flags(flags);
 
         let mut rel_frameindex = None;
@@ -92,25 +92,25 @@ impl Reliability {
         let mut ord_channel = None;
 
         if reltype.is_reliable() {
-            rel_frameindex = Some(buf.read_u24_le_bytes());
+            rel_frameindex = Some(buf.read_u24_le_bytes()?);
         }
 
         if reltype.is_sequenced() {
-            seq_frameindex = Some(buf.read_u24_le_bytes());
+            seq_frameindex = Some(buf.read_u24_le_bytes()?);
         }
 
         if reltype.is_ordered() {
-            ord_frameindex = Some(buf.read_u24_le_bytes());
-            ord_channel = Some(buf.read_byte());
+            ord_frameindex = Some(buf.read_u24_le_bytes()?);
+            ord_channel = Some(buf.read_byte()?);
         }
 
-        Self {
+        Ok(Self {
             reltype: ReliabilityType::from_flags(flags),
             rel_frameindex,
             seq_frameindex,
             ord_frameindex,
             ord_channel,
-        }
+        })
     }
 
     pub fn is_reliable(&self) -> bool {
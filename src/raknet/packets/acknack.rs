@@ -1,5 +1,5 @@
 use super::obj::{FromBuffer, ToBuffer};
-use crate::raknet::objects::MsgBuffer;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
 
 // TODO: acknack can have many bodies of records
 
@@ -33,8 +33,8 @@ fn write_body(input_records: &[u32]) -> MsgBuffer {
     acknack
 }
 
-fn read_body(buf: &mut MsgBuffer) -> Vec<u32> {
-    let _record_count = buf.read_i16_be_bytes();
+fn read_body(buf: &mut MsgBuffer) -> Result<Vec<u32>, DecodeError> {
+    let _record_count = buf.read_i16_be_bytes()?;
     let mut records: Vec<u32> = vec![];
 
     loop {
@@ -42,22 +42,22 @@ fn read_body(buf: &mut MsgBuffer) -> Vec<u32> {
             break;
         }
 
-        let is_range = buf.read_byte() != 1;
+        let is_range = buf.read_byte()? != 1;
 
         if !is_range {
-            let record = buf.read_u24_le_bytes();
+            let record = buf.read_u24_le_bytes()?;
 
             records.push(record);
         } else {
-            let start_index = buf.read_u24_le_bytes();
-            let end_index = buf.read_u24_le_bytes();
+            let start_index = buf.read_u24_le_bytes()?;
+            let end_index = buf.read_u24_le_bytes()?;
 
             let range: Vec<u32> = (start_index..=end_index).collect();
             records.extend_from_slice(&range);
         }
     }
 
-    records
+    Ok(records)
 }
 
 pub struct Ack {
@@ -71,10 +71,10 @@ impl ToBuffer for Ack {
 }
 
 impl FromBuffer for Ack {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        Self {
-            records: read_body(buf),
-        }
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        Ok(Self {
+            records: read_body(buf)?,
+        })
     }
 }
 
@@ -89,9 +89,9 @@ impl ToBuffer for Nack {
 }
 
 impl FromBuffer for Nack {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        Self {
-            records: read_body(buf),
-        }
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        Ok(Self {
+            records: read_body(buf)?,
+        })
     }
 }
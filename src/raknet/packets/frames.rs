@@ -6,8 +6,8 @@ use crate::raknet::objects::msgbuffer::PacketPriority;
 use crate::raknet::objects::msgbuffer::SendPacket;
 use crate::raknet::objects::reliability::ReliabilityType;
 use crate::raknet::objects::FragmentInfo;
-use crate::raknet::objects::MsgBuffer;
 use crate::raknet::objects::Reliability;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Frame {
@@ -61,8 +61,9 @@ impl Frame {
     ) -> Self {
         Self {
             flags: 96,
-            bitlength: (body.len() * 8) as u16,
-            bodysize: body.len() as u16,
+            // + 1 for the packet id
+            bitlength: ((body.len() + 1) * 8) as u16,
+            bodysize: (body.len() + 1) as u16,
             reliability: Reliability {
                 reltype: ReliabilityType::from_flags(96),
                 rel_frameindex: Some(rel_frameindex),
@@ -106,17 +107,17 @@ impl PartialOrd for Frame {
 }
 
 impl FromBuffer for Frame {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
         // so far, pretty much completely taken from PieMC
-        let flags = buf.read_byte();
-        let bitlength = buf.read_u16_be_bytes();
+        let flags = buf.read_byte()?;
+        let bitlength = buf.read_u16_be_bytes()?;
 
-        let reliability = Reliability::extract(flags, buf);
+        let reliability = Reliability::extract(flags, buf)?;
 
         let mut fragment_info = FragmentInfo::new(flags);
-        fragment_info.extract(buf);
+        fragment_info.extract(buf)?;
 
-        let bodysize = (bitlength + 7) / 8;
+        let bodysize = bitlength.div_ceil(8);
         // println!("rel? {:?}", reliability.is_reliable());
         // println!("seq? {:?}", reliability.is_sequenced());
         // println!("ord? {:?}", reliability.is_ordered());
@@ -131,10 +132,10 @@ impl FromBuffer for Frame {
         // println!("{:?}", &fragment_info.compound_size.unwrap_or(234));
         // println!("{:?}", &fragment_info.compound_id.unwrap_or(234));
         // println!("{:?}", &fragment_info.index.unwrap_or(234));
-        let mut body = MsgBuffer::from(buf.read_vec(bodysize as usize));
-        let inner_packet_id = body.read_byte();
+        let mut body = MsgBuffer::from(buf.read_vec(bodysize as usize)?);
+        let inner_packet_id = body.read_byte()?;
 
-        Self {
+        Ok(Self {
             flags,
             bitlength,
             bodysize,
@@ -143,7 +144,7 @@ impl FromBuffer for Frame {
             inner_packet_id,
             body,
             priority: None,
-        }
+        })
     }
 }
 
@@ -232,18 +233,18 @@ impl FrameSet {
 }
 
 impl FromBuffer for FrameSet {
-    fn from_buffer(/*flags: u8, */ buf: &mut MsgBuffer) -> Self {
-        let index = buf.read_u24_le_bytes();
+    fn from_buffer(/*flags: u8, */ buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        let index = buf.read_u24_le_bytes()?;
         let mut frames: Vec<Frame> = vec![];
 
         while !buf.at_end() {
-            frames.push(Frame::from_buffer(buf))
+            frames.push(Frame::from_buffer(buf)?)
         }
 
-        Self {
+        Ok(Self {
             /*flags,*/ index,
             frames,
-        }
+        })
     }
 }
 
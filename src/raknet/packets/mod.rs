@@ -6,7 +6,7 @@ pub mod online;
 
 pub use acknack::{Ack, Nack};
 pub use frames::{Frame, FrameSet};
-pub use obj::{FromBuffer, PacketID, ToBuffer};
+pub use obj::{FromBuffer, ToBuffer};
 pub use offline::{
     IncompatibleProtocol, OfflineConnRep1, OfflineConnRep2, OfflineConnReq1, OfflineConnReq2,
     OfflinePing, OfflinePong,
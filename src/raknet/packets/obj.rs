@@ -1,13 +1,14 @@
-use crate::raknet::objects::MsgBuffer;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
 
-pub trait FromBuffer {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self;
+pub trait FromBuffer: Sized {
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError>;
 }
 
 pub trait ToBuffer {
     fn to_buffer(&self) -> MsgBuffer;
 }
 
+#[allow(dead_code)] // for the game packets, eventually
 pub trait PacketID {
     const ID: u8;
 }
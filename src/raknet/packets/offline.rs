@@ -1,6 +1,6 @@
 use std::net::SocketAddr;
 
-use crate::raknet::objects::MsgBuffer;
+use crate::raknet::objects::{DecodeError, MsgBuffer};
 
 use super::obj::{FromBuffer, ToBuffer};
 
@@ -11,16 +11,16 @@ pub struct OfflinePing {
 }
 
 impl FromBuffer for OfflinePing {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        let timestamp = buf.read_i64_be_bytes();
-        let magic = buf.read_magic();
-        let client_guid = buf.read_i64_be_bytes();
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        let timestamp = buf.read_i64_be_bytes()?;
+        let magic = buf.read_magic()?;
+        let client_guid = buf.read_i64_be_bytes()?;
 
-        Self {
+        Ok(Self {
             timestamp,
             magic,
             client_guid,
-        }
+        })
     }
 }
 
@@ -50,16 +50,16 @@ pub struct OfflineConnReq1 {
 }
 
 impl FromBuffer for OfflineConnReq1 {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        let magic = buf.read_magic();
-        let protocol = buf.read_byte();
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        let magic = buf.read_magic()?;
+        let protocol = buf.read_byte()?;
         let mtu = (buf.len_rest() + 46) as i16;
 
-        Self {
+        Ok(Self {
             magic,
             protocol,
             mtu,
-        }
+        })
     }
 }
 
@@ -90,18 +90,18 @@ pub struct OfflineConnReq2 {
 }
 
 impl FromBuffer for OfflineConnReq2 {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        let magic = buf.read_magic();
-        let server_address = buf.read_address();
-        let mtu = buf.read_i16_be_bytes();
-        let client_guid = buf.read_i64_be_bytes();
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        let magic = buf.read_magic()?;
+        let server_address = buf.read_address()?;
+        let mtu = buf.read_i16_be_bytes()?;
+        let client_guid = buf.read_i64_be_bytes()?;
 
-        Self {
+        Ok(Self {
             magic,
             server_address,
             mtu,
             client_guid,
-        }
+        })
     }
 }
 
@@ -118,8 +118,9 @@ impl ToBuffer for OfflineConnRep2 {
         let mut buf = MsgBuffer::new();
         buf.write_magic(&self.magic);
         buf.write_i64_be_bytes(self.server_guid);
-        buf.write_byte(self.use_encryption as u8);
+        buf.write_address(&self.client_address);
         buf.write_i16_be_bytes(self.mtu);
+        buf.write_byte(self.use_encryption as u8);
 
         buf
     }
@@ -3,7 +3,7 @@ use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use super::{FromBuffer, ToBuffer};
 use crate::raknet::objects::{
     datatypes::{get_unix_milis, to_address_bytes},
-    MsgBuffer,
+    DecodeError, MsgBuffer,
 };
 
 pub struct OnlineConnReq {
@@ -12,11 +12,11 @@ pub struct OnlineConnReq {
 }
 
 impl FromBuffer for OnlineConnReq {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
-        let guid = buf.read_i64_be_bytes();
-        let timestamp = buf.read_i64_be_bytes();
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
+        let guid = buf.read_i64_be_bytes()?;
+        let timestamp = buf.read_i64_be_bytes()?;
 
-        Self { guid, timestamp }
+        Ok(Self { guid, timestamp })
     }
 }
 
@@ -54,22 +54,22 @@ pub struct NewIncomingConnection {
 }
 
 impl FromBuffer for NewIncomingConnection {
-    fn from_buffer(buf: &mut MsgBuffer) -> Self {
+    fn from_buffer(buf: &mut MsgBuffer) -> Result<Self, DecodeError> {
         // TODO: for docs
         // wiki.vg lied to me (!!!)
         // cross checked JSPrismarine, Nukkit, and GoRaknet for this impl
-        let server_address = buf.read_address();
+        let server_address = buf.read_address()?;
         for _ in 0..20 {
-            buf.read_address();
+            buf.read_address()?;
         }
 
-        let request_timestamp = buf.read_i64_be_bytes();
-        let accept_timestamp = buf.read_i64_be_bytes();
+        let request_timestamp = buf.read_i64_be_bytes()?;
+        let accept_timestamp = buf.read_i64_be_bytes()?;
 
-        Self {
+        Ok(Self {
             server_address,
             request_timestamp,
             accept_timestamp,
-        }
+        })
     }
 }
@@ -6,12 +6,14 @@
 /// Reference: https://wiki.vg/Raknet_Protocol
 use rand::Rng;
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use tokio::sync::mpsc::Sender;
 use tokio::task::JoinHandle;
 
-use log::{info, trace};
+use log::{info, log_enabled, trace, warn, Level};
 
 use super::objects::datatypes::get_unix_milis;
 use super::objects::msgbuffer::{Packet, SendPacket};
@@ -20,23 +22,60 @@ use super::packets::*;
 use super::session::Session;
 use super::socket::Socket;
 use crate::config::Config;
+use crate::dissector::{dissect, LOG_TARGET};
+use crate::game::connection::{Incoming, Outgoing};
+use crate::query::QueryHandler;
+use crate::status::SharedStatus;
 
 pub struct RakNetListener {
     socket: Arc<Socket>,
+    lan_sockets: Vec<Socket>,
+    #[allow(dead_code)]
     socket_manager: JoinHandle<()>,
     tx: Sender<(SendPacket, SocketAddr)>,
     server_guid: i64,
-    config: Config,
+    status: SharedStatus,
+    query: Option<QueryHandler>,
+    /// Where game packets go, and come back from (see server.rs)
+    game: mpsc::Sender<Incoming>,
+    outgoing: mpsc::Receiver<(SocketAddr, Outgoing)>,
     sessions: HashMap<String, Session>,
     buf: [u8; 2048],
 }
 
 impl RakNetListener {
-    pub async fn new(config: Config) -> Self {
+    pub async fn new(
+        config: Config,
+        status: SharedStatus,
+        game: mpsc::Sender<Incoming>,
+        outgoing: mpsc::Receiver<(SocketAddr, Outgoing)>,
+    ) -> Self {
         let (tx, mut sockrx) = tokio::sync::mpsc::channel(32);
-        let socket = Arc::new(
-            Socket::bind("127.0.0.1:".to_string() + config.get_property("server-port")).await,
-        );
+        let port = config.get_property("server-port");
+        // neither's in older server.properties, both default to off
+        let lan_visible = config
+            .get_optional("enable-lan-visibility")
+            .is_some_and(|v| v == "true");
+
+        // without LAN visibility we stay on loopback like we always have
+        let socket = Arc::new(if lan_visible {
+            let socket = Socket::bind("0.0.0.0:".to_string() + port).await;
+            socket.enable_broadcast();
+            socket
+        } else {
+            Socket::bind("127.0.0.1:".to_string() + port).await
+        });
+        let host_ip = socket.udpsock.local_addr().unwrap().ip().to_string();
+        let query = config
+            .get_optional("enable-query")
+            .is_some_and(|v| v == "true")
+            .then(|| QueryHandler::new(host_ip));
+
+        let lan_sockets = if lan_visible {
+            Self::bind_lan_sockets(port).await
+        } else {
+            vec![]
+        };
 
         let sock_to_manage = socket.clone();
 
@@ -55,38 +94,102 @@ impl RakNetListener {
 
         Self {
             socket,
+            lan_sockets,
             socket_manager: manager,
             tx,
             server_guid: rand::thread_rng().gen_range(1..=i64::MAX),
-            config,
+            status: status.clone(),
+            query,
+            game,
+            outgoing,
             sessions: HashMap::new(),
             buf: [0u8; 2048],
         }
     }
 
+    /// Clients look for LAN games by broadcasting unconnected pings to the
+    /// default ports (19132, and 19133 multicast for IPv6), so we have to be
+    /// listening there even if the game runs on a different port. These
+    /// sockets only ever answer pings.
+    async fn bind_lan_sockets(port: &str) -> Vec<Socket> {
+        let mut addrs = vec![];
+        if port != "19132" {
+            addrs.push("0.0.0.0:19132");
+        }
+        if port != "19133" {
+            // the IPv6 discovery pings go to the all-nodes group (ff02::1),
+            // which every interface is already a member of
+            addrs.push("[::]:19133");
+        }
+
+        let mut sockets = vec![];
+        for addr in addrs {
+            match Socket::try_bind(addr).await {
+                Ok(socket) => {
+                    if addr.starts_with("0.0.0.0") {
+                        socket.enable_broadcast();
+                    }
+                    sockets.push(socket);
+                }
+                Err(e) => warn!("LAN discovery won't work on {}: {}", addr, e),
+            }
+        }
+
+        sockets
+    }
+
+    /// Nothing if the ping doesn't make sense, anyone can send us those
+    fn make_pong(&mut self, body: &mut MsgBuffer) -> Option<OfflinePong> {
+        let offping = OfflinePing::from_buffer(body).ok()?;
+
+        Some(OfflinePong {
+            timestamp: offping.timestamp,
+            server_guid: self.server_guid,
+            magic: offping.magic,
+            server_name: self.get_server_name(),
+        })
+    }
+
+    async fn answer_lan_pings(&mut self) {
+        for i in 0..self.lan_sockets.len() {
+            let (size, client) = match self.lan_sockets[i].try_recv_from(&mut self.buf) {
+                Ok((packetsize, client)) => (packetsize, client),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => continue,
+                // e.g. an ICMP unreachable for a pong we sent, nothing to
+                // do with the next ping
+                Err(e) => {
+                    warn!("Couldn't read from the LAN socket: {e}");
+                    continue;
+                }
+            };
+
+            if size == 0 || !matches!(self.buf[0], 0x01 | 0x02) {
+                continue;
+            }
+
+            let mut body = MsgBuffer::from(self.buf[1..size].to_vec());
+            let Some(offpong) = self.make_pong(&mut body) else {
+                continue;
+            };
+            self.lan_sockets[i]
+                .send_packet(0x1c, &mut offpong.to_buffer(), client)
+                .await;
+        }
+    }
+
     pub fn get_server_name(&mut self) -> String {
-        let motd = self.config.get_property("server-name");
-
-        // so picky I don't get it smh
-        [
-            "MCPE",
-            motd,
-            "622",
-            "1.20.40",
-            self.sessions.len().to_string().as_str(),
-            self.config.get_property("max-players").as_str(),
-            self.server_guid.to_string().as_str(),
-            motd,
-            "Creative",
-            "1",
-            self.config.get_property("server-port").as_str(),
-            self.config.get_property("server-portv6").as_str(),
-        ]
-        .join(";")
+        self.status.read().unwrap().to_motd(self.server_guid)
     }
 
     pub fn create_session(&mut self, mtu: i16, guid: i64, addr: SocketAddr) {
-        let sess = Session::new(addr, guid, self.server_guid, mtu, self.tx.clone());
+        let sess = Session::new(
+            addr,
+            guid,
+            self.server_guid,
+            mtu,
+            self.game.clone(),
+            self.tx.clone(),
+        );
 
         self.sessions.insert(addr.to_string(), sess);
     }
@@ -94,22 +197,32 @@ impl RakNetListener {
     pub async fn read_message(&mut self) -> Option<(Packet, SocketAddr)> {
         let (size, client) = match self.socket.try_recv_from(&mut self.buf) {
             Ok((packetsize, client)) => (packetsize, client),
-            Err(ref e) if e.to_string() == *"operation would block" => return None,
-            Err(e) => panic!("recv function failed: {e:?}"),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => return None,
+            Err(e) => {
+                warn!("Couldn't read from the socket: {e}");
+                return None;
+            }
         };
 
+        if QueryHandler::is_query(&self.buf[..size]) {
+            if let Some(query) = self.query.as_mut() {
+                let status = self.status.read().unwrap().clone();
+                if let Some(resp) = query.handle(&self.buf[..size], client, &status) {
+                    self.socket.send_to(&resp, client).await;
+                }
+            }
+            return None;
+        }
+
+        if size == 0 {
+            return None;
+        }
         let packet_id = self.buf[0];
         let mut body = MsgBuffer::from(self.buf[1..size].to_vec());
 
         match packet_id {
             0x01 | 0x02 => {
-                let offping = OfflinePing::from_buffer(&mut body);
-                let offpong = OfflinePong {
-                    timestamp: offping.timestamp,
-                    server_guid: self.server_guid,
-                    magic: offping.magic,
-                    server_name: self.get_server_name(),
-                };
+                let offpong = self.make_pong(&mut body)?;
 
                 self.socket
                     .send_packet(0x1c, &mut offpong.to_buffer(), client)
@@ -119,7 +232,13 @@ impl RakNetListener {
             0x05 => {
                 // ((ip.src == 192.168.66.151 && ip.dst == 192.168.66.0/8) || (ip.src == 192.168.66.0/8 && ip.dst == 192.168.66.151))
                 // trace!("0x{packet_id} RECV = {:?}", body.get_bytes());
-                let request1 = OfflineConnReq1::from_buffer(&mut body);
+                let request1 = match OfflineConnReq1::from_buffer(&mut body) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        warn!("Dropping connection request from {}: {}", client, e);
+                        return None;
+                    }
+                };
 
                 if request1.protocol != 11 {
                     let wrong_proto = IncompatibleProtocol {
@@ -147,13 +266,21 @@ impl RakNetListener {
             }
             0x07 => {
                 // trace!("0x{packet_id} RECV = {:?}", body.get_bytes());
-                let request2 = OfflineConnReq2::from_buffer(&mut body);
+                let request2 = match OfflineConnReq2::from_buffer(&mut body) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        warn!("Dropping connection request from {}: {}", client, e);
+                        return None;
+                    }
+                };
                 let reply2 = OfflineConnRep2 {
                     magic: request2.magic,
                     server_guid: self.server_guid,
                     client_address: client,
                     mtu: request2.mtu,
-                    use_encryption: false, // disable encryption // TODO: look into? what is this?
+                    // RakNet's own encryption, Bedrock doesn't use it and encrypts
+                    // game packets itself instead (protocol/encryption.rs)
+                    use_encryption: false,
                 };
 
                 self.create_session(request2.mtu, request2.client_guid, client);
@@ -167,10 +294,8 @@ impl RakNetListener {
             _ => {}
         }
 
-        match packet_id {
-            // 0xa0 => {},
-            // 0xc0 => {},
-            _ => trace!("0x{packet_id} RECV = {:?}", &self.buf[..size]), // rename to body
+        if log_enabled!(target: LOG_TARGET, Level::Trace) {
+            trace!(target: LOG_TARGET, "RECV {}", dissect(&self.buf[..size], false));
         }
 
         Some((
@@ -183,21 +308,34 @@ impl RakNetListener {
         ))
     }
 
-    pub async fn mainloop(&mut self) {
-        loop {
+    /// Until `shutdown` is set
+    pub async fn mainloop(&mut self, shutdown: &AtomicBool) {
+        while !shutdown.load(Ordering::Relaxed) {
             let last_update_time = get_unix_milis();
 
             while get_unix_milis() - last_update_time < 100 {
                 // RakNet ticks are a tenth of a second
+                self.answer_lan_pings().await;
+
                 let (packet, client) = match self.read_message().await {
                     Some((packet, client)) => (packet, client),
                     None => continue,
                 };
 
-                let sess = self.sessions.get_mut(&client.to_string()).unwrap();
+                // stragglers from a session that's already closed
+                let Some(sess) = self.sessions.get_mut(&client.to_string()) else {
+                    continue;
+                };
                 sess.recv(packet).await;
             }
 
+            // whatever the game loop's sent since last time
+            while let Ok((addr, outgoing)) = self.outgoing.try_recv() {
+                if let Some(sess) = self.sessions.get_mut(&addr.to_string()) {
+                    sess.send_game_packets(outgoing).await;
+                }
+            }
+
             // TODO: implement task::spawn around here
             // also find out if raknet ticks or if that's just a minecraft thing
             // cuz idk
@@ -212,14 +350,24 @@ impl RakNetListener {
             }
 
             for (_, sess) in self.sessions.iter_mut() {
+                while let Some(packet) = sess.send_heap.pop() {
+                    self.socket.send_spacket(packet, sess.sockaddr).await;
+                }
+
                 let mut packets = std::mem::take(&mut sess.send_queue);
                 for packet in packets.iter_mut() {
-                    println!("{} / {}", packet.body.len(), sess.mtu);
                     self.socket
                         .send_packet(packet.packet_id, &mut packet.body, sess.sockaddr)
                         .await;
                 }
             }
+
+            self.sessions.retain(|addr, sess| {
+                if sess.closed {
+                    info!("Closed Session ({})", addr);
+                }
+                !sess.closed
+            });
         }
     }
 }
@@ -1,14 +1,13 @@
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 
 use log::warn;
 use tokio::sync::mpsc::Sender;
 
 use super::objects::msgbuffer::Packet;
 use super::objects::{
-    to_i32_varint_bytes,
     get_unix_milis,
     msgbuffer::{PacketPriority, SendPacket},
     MsgBuffer,
@@ -17,11 +16,31 @@ use super::objects::{FragmentInfo, Reliability};
 use super::packets::*;
 use super::packets::{Ack, Nack, OnlineConnAccepted, OnlineConnReq};
 use super::packets::{FromBuffer, ToBuffer};
+use crate::game::connection::{Incoming, Outgoing};
+use crate::protocol::batch::BatchCodec;
+use crate::protocol::encryption::Encryption;
+
+/// IP + UDP headers, frame set header and the biggest a frame header gets
+/// (reliable, ordered and fragmented), with some room to spare
+const MAX_FRAME_OVERHEAD: usize = 20 + 8 + 4 + 20 + 8;
+
+/// Fragmented packets being put back together at once, clients only
+/// ever have a couple going
+const MAX_OPEN_COMPOUNDS: usize = 16;
+/// Pieces in one fragmented packet. Login's the biggest thing a client
+/// sends (a skin's a few hundred KB at most), this is well over that
+/// at any MTU.
+const MAX_COMPOUND_SIZE: i32 = 2048;
+
+/// RakNet only has this many ordering channels
+const MAX_ORD_CHANNELS: u8 = 32;
 
 pub struct Session {
     pub sockaddr: SocketAddr,
     tx: Sender<(SendPacket, SocketAddr)>,
+    #[allow(dead_code)]
     pub guid: i64,
+    #[allow(dead_code)]
     pub server_guid: i64,
     pub mtu: i16,
 
@@ -37,7 +56,17 @@ pub struct Session {
     pub recv_queue: Vec<Packet>,
     pub send_queue: Vec<Packet>,
     resend_queue: Arc<Mutex<HashMap<u32, FrameSet>>>,
+    compound_id: i16,
+    /// Fragments by compound id, each piece by its index
+    compounds: HashMap<i16, Vec<Option<Vec<u8>>>>,
     missing_records: Arc<Mutex<Vec<u32>>>,
+    batch: BatchCodec,
+    /// `None` until the handshake after login
+    encryption: Option<Encryption>,
+    /// Everything above the transport happens in the game loop
+    game: mpsc::Sender<Incoming>,
+    /// The client said goodbye, the listener drops us on its next tick
+    pub closed: bool,
 }
 
 impl Session {
@@ -46,8 +75,12 @@ impl Session {
         guid: i64,
         server_guid: i64,
         mtu: i16,
+        game: mpsc::Sender<Incoming>,
         tx: Sender<(SendPacket, SocketAddr)>,
     ) -> Self {
+        // the game loop's only gone if the server's stopping
+        let _ = game.send(Incoming::Connected(sockaddr));
+
         Self {
             sockaddr,
             tx,
@@ -64,7 +97,13 @@ impl Session {
             recv_queue: vec![],
             send_queue: vec![],
             resend_queue: Arc::new(Mutex::new(HashMap::new())),
+            compound_id: 0,
+            compounds: HashMap::new(),
             missing_records: Arc::new(Mutex::new(vec![])),
+            batch: BatchCodec::default(),
+            encryption: None,
+            game,
+            closed: false,
         }
     }
 
@@ -85,7 +124,7 @@ impl Session {
                 0xa0 => self.recv_nack(packet).await,
                 0xc0 => self.recv_ack(packet).await,
                 0x80..=0x8d => self.recv_frame_set(packet).await,
-                _ => panic!("Packet ID {} is not implemented", packet.packet_id),
+                id => warn!("Dropping unknown packet {:#04x} from {}", id, self.sockaddr),
             };
         }
 
@@ -94,7 +133,7 @@ impl Session {
         // }
         // return false;
 
-        if self.send_heap.is_empty() {
+        if self.frames_queue.lock().unwrap().is_empty() {
             return;
         }
 
@@ -131,7 +170,7 @@ impl Session {
                 self.send_heap.push(frameset.package(current_prio));
                 resend_queue.insert(frameset.index, frameset);
                 frameset = new_frameset;
-                self.fs_server_index += 1; // I already increment in try_add_frame, just need to match here                
+                self.fs_server_index += 1; // I already increment in try_add_frame, just need to match here
             }
         }
 
@@ -174,25 +213,68 @@ impl Session {
         body: MsgBuffer,
         priority: PacketPriority,
     ) {
-        self.rel_server_index += 1;
-
-        match self.ord_channels.get(0) {
+        match self.ord_channels.first() {
             Some(_) => self.ord_channels[0] += 1,
             None => self.ord_channels.insert(0, 0),
         }
+        let ord_index = self.ord_channels[0];
+
+        // anything bigger than what fits in a datagram gets split, and the
+        // client puts it back together (all pieces share the ordered index)
+        let max_size = self.mtu as usize - MAX_FRAME_OVERHEAD;
+        if body.get_bytes().len() < max_size {
+            let rel_index = self.next_rel_index();
+            self.send_frame(
+                Frame::from_default_options(packet_id, body, rel_index, ord_index),
+                priority,
+            )
+            .await;
+            return;
+        }
 
-        // rust compiler my beloved
-        let fs_index = self.next_fs_index();
+        let mut payload = vec![packet_id];
+        payload.extend_from_slice(body.get_bytes());
+
+        let compound_id = self.compound_id;
+        self.compound_id = self.compound_id.wrapping_add(1);
+        let pieces: Vec<&[u8]> = payload.chunks(max_size).collect();
+
+        for (index, piece) in pieces.iter().enumerate() {
+            let rel_index = self.next_rel_index();
+            // the "packet id" of a fragment is just its first byte
+            let mut frame = Frame::from_default_options(
+                piece[0],
+                MsgBuffer::from(piece[1..].to_vec()),
+                rel_index,
+                ord_index,
+            );
+            frame.flags |= FragmentInfo::FLAG;
+            frame.fragment_info = FragmentInfo {
+                is_fragmented: true,
+                compound_size: Some(pieces.len() as i32),
+                compound_id: Some(compound_id),
+                index: Some(index as i32),
+            };
 
-        self.send_frame(
-            Frame::from_default_options(packet_id, body, fs_index, self.ord_channels[0]),
-            priority,
-        )
-        .await;
+            self.send_frame(frame, priority).await;
+        }
+    }
+
+    fn next_rel_index(&mut self) -> u32 {
+        self.rel_server_index += 1;
+
+        // rust compiler my beloved
+        self.rel_server_index - 1
     }
 
     pub async fn recv_ack(&mut self, mut packet: Packet) {
-        let ack_pack = Ack::from_buffer(&mut packet.body);
+        let ack_pack = match Ack::from_buffer(&mut packet.body) {
+            Ok(ack) => ack,
+            Err(e) => {
+                warn!("Dropping ack from {}: {}", self.sockaddr, e);
+                return;
+            }
+        };
         let mut resend_queue = self.resend_queue.lock().unwrap();
 
         for rec in ack_pack.records {
@@ -201,11 +283,20 @@ impl Session {
     }
 
     pub async fn recv_nack(&mut self, mut packet: Packet) {
-        let nack_pack = Nack::from_buffer(&mut packet.body);
+        let nack_pack = match Nack::from_buffer(&mut packet.body) {
+            Ok(nack) => nack,
+            Err(e) => {
+                warn!("Dropping nack from {}: {}", self.sockaddr, e);
+                return;
+            }
+        };
         let mut resend_queue = self.resend_queue.lock().unwrap();
 
         for rec in nack_pack.records {
-            let frame_set = resend_queue.get_mut(&rec).unwrap();
+            // already acked, or never sent
+            let Some(frame_set) = resend_queue.get_mut(&rec) else {
+                continue;
+            };
             let packet = Packet {
                 packet_id: 0x84,
                 timestamp: packet.timestamp,
@@ -245,15 +336,21 @@ impl Session {
     }
 
     pub async fn recv_ping(&mut self, mut packet: Packet) {
+        let Ok(timestamp) = packet.body.read_i64_be_bytes() else {
+            warn!("Dropping ping without a timestamp from {}", self.sockaddr);
+            return;
+        };
         let mut pong = MsgBuffer::new();
-        pong.write_i64_be_bytes(packet.body.read_i64_be_bytes());
+        pong.write_i64_be_bytes(timestamp);
         pong.write_i64_be_bytes(get_unix_milis() as i64);
 
         let frame = Frame {
             flags: 0,
-            bitlength: (pong.len() * 8) as u16,
-            bodysize: pong.len() as u16,
-            reliability: Reliability::extract(0, &mut MsgBuffer::new()),
+            // + 1 for the packet id
+            bitlength: ((pong.len() + 1) * 8) as u16,
+            bodysize: (pong.len() + 1) as u16,
+            reliability: Reliability::extract(0, &mut MsgBuffer::new())
+                .expect("unreliable frames don't have anything to read"),
             fragment_info: FragmentInfo {
                 is_fragmented: false,
                 compound_size: None,
@@ -269,9 +366,18 @@ impl Session {
     }
 
     pub async fn recv_frame_set(&mut self, mut packet: Packet) {
-        let frameset = FrameSet::from_buffer(&mut packet.body);
+        let frameset = match FrameSet::from_buffer(&mut packet.body) {
+            Ok(frameset) => frameset,
+            Err(e) => {
+                warn!("Dropping frame set from {}: {}", self.sockaddr, e);
+                return;
+            }
+        };
+        let Some(first) = frameset.frames.first() else {
+            return;
+        };
 
-        if frameset.frames.get(0).unwrap().reliability.is_reliable() {
+        if first.reliability.is_reliable() {
             if frameset.index > self.fs_client_index + 1 {
                 self.send_nack(self.fs_client_index + 1, frameset.index);
             }
@@ -281,26 +387,100 @@ impl Session {
         self.fs_client_index = frameset.index;
 
         for frame in frameset.frames {
-            self.adjust_internal(&frame);
+            if !self.adjust_internal(&frame) {
+                warn!("Dropping frame on a bad channel from {}", self.sockaddr);
+                continue;
+            }
+            let (packet_id, body) = if frame.fragment_info.is_fragmented {
+                let Some(mut body) = self.reassemble(&frame) else {
+                    continue;
+                };
+                let Ok(packet_id) = body.read_byte() else {
+                    continue;
+                };
+                (packet_id, body)
+            } else {
+                (frame.inner_packet_id, frame.body)
+            };
             let packet = Packet {
-                packet_id: frame.inner_packet_id,
+                packet_id,
                 timestamp: packet.timestamp,
-                body: frame.body,
+                body,
             };
 
-            match frame.inner_packet_id {
+            match packet_id {
                 0x00 => self.recv_ping(packet).await,
                 0x13 => self.recv_frame_new_incoming_connection(packet).await,
                 0x09 => self.recv_frame_connection_request(packet).await,
                 0xfe => self.recv_game_packet(packet).await,
-                0x15 => return, // TODO:
-                _ => panic!("uh oh <:O {}", frame.inner_packet_id),
+                0x15 => {
+                    let _ = self.game.send(Incoming::Disconnected(self.sockaddr));
+                    self.closed = true;
+                    return;
+                }
+                id => warn!("Dropping unknown frame {:#04x} from {}", id, self.sockaddr),
             };
         }
     }
 
+    /// Holds on to a fragment until the rest of its packet's here, then
+    /// hands back the whole thing (packet id first, like the pieces were
+    /// one frame)
+    fn reassemble(&mut self, frame: &Frame) -> Option<MsgBuffer> {
+        let info = &frame.fragment_info;
+        let (Some(size), Some(id), Some(index)) =
+            (info.compound_size, info.compound_id, info.index)
+        else {
+            return None;
+        };
+        if !(1..=MAX_COMPOUND_SIZE).contains(&size) || !(0..size).contains(&index) {
+            warn!(
+                "Dropping fragment {}/{} from {}",
+                index, size, self.sockaddr
+            );
+            return None;
+        }
+        if !self.compounds.contains_key(&id) && self.compounds.len() >= MAX_OPEN_COMPOUNDS {
+            warn!(
+                "Dropping fragment from {}, too many fragmented packets at once",
+                self.sockaddr
+            );
+            return None;
+        }
+
+        let pieces = self
+            .compounds
+            .entry(id)
+            .or_insert_with(|| vec![None; size as usize]);
+        if pieces.len() != size as usize {
+            warn!(
+                "Dropping fragmented packet {} from {}, its size changed",
+                id, self.sockaddr
+            );
+            self.compounds.remove(&id);
+            return None;
+        }
+
+        // the frame's body is the whole piece, the "packet id" included
+        pieces[index as usize] = Some(frame.body.get_bytes().clone());
+        if pieces.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let pieces = self.compounds.remove(&id)?;
+        Some(MsgBuffer::from(
+            pieces.into_iter().flatten().flatten().collect(),
+        ))
+    }
+
     pub async fn recv_frame_connection_request(&mut self, mut packet: Packet) {
-        let request = OnlineConnReq::from_buffer(&mut packet.body);
+        let request = match OnlineConnReq::from_buffer(&mut packet.body) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Dropping connection request from {}: {}", self.sockaddr, e);
+                return;
+            }
+        };
 
         OnlineConnAccepted {
             client_address: self.sockaddr,
@@ -319,7 +499,8 @@ impl Session {
             }
             .to_buffer(),
             PacketPriority::Medium,
-        ).await;
+        )
+        .await;
     }
 
     pub async fn recv_frame_new_incoming_connection(&mut self, _packet: Packet) {
@@ -340,57 +521,66 @@ impl Session {
         //    ...
         // }
         // http://www.raknet.net/raknet/manual/systemoverview.html
-        println!("lol");
-
-        let mut game_packets = vec![];
-
-        loop {
-            if packet.body.at_end() {
-                break;
-            }
-
-            let packetsize = packet.body.read_i32_varint_bytes() as usize;
-            game_packets.push(packet.body.read_vec(packetsize));
+        let mut payload = packet.body.read_rest();
+        if let Some(encryption) = &mut self.encryption {
+            payload = match encryption.decrypt(&payload) {
+                Ok(payload) => payload,
+                // the cipher's a stream, so once a batch is off every
+                // batch after it is too
+                Err(e) => {
+                    warn!("Disconnecting {}: {}", self.sockaddr, e);
+                    self.close().await;
+                    return;
+                }
+            };
         }
 
-        let mut response: Vec<u8> = vec![];
+        let game_packets = match self.batch.decode(&payload) {
+            Ok(game_packets) => game_packets,
+            Err(e) => {
+                warn!("Dropping batch from {}: {}", self.sockaddr, e);
+                return;
+            }
+        };
 
-        for packet in game_packets {
-            let mut reader = MsgBuffer::from(packet);
-            let firstunit = reader.read_i32_varint_bytes();
-            let (_sub_client_id, _sub_sender_id, packet_id) = (
-                (firstunit & 0x3000) >> 12,
-                (firstunit & 0xc00) >> 10,
-                firstunit & 0x3ff,
-            );
+        let _ = self
+            .game
+            .send(Incoming::Packets(self.sockaddr, game_packets));
+    }
 
-            if packet_id == 0x01 {
-                panic!("Ooooooooooooooooooooooo");
+    /// From the game loop
+    pub async fn send_game_packets(&mut self, outgoing: Outgoing) {
+        if !outgoing.packets.is_empty() {
+            let mut response = self.batch.encode(&outgoing.packets);
+            if let Some(encryption) = &mut self.encryption {
+                response = encryption.encrypt(&response);
             }
+            self.send_default_frame(0xfe, MsgBuffer::from(response), PacketPriority::Medium)
+                .await;
+        }
 
-            let mut resp = to_i32_varint_bytes(
-                0_i32 | 0 /*sub_client_id << 12*/ | 0 /*sub_sender_id << 10*/ | 0x8F,
-            );
-            let mut bytes: Vec<u8> = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-            resp.append(&mut bytes);
-            // let mut resp: Vec<u8> = match packet[0] {
-            //                         // 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0
-            //     0xc1 => {
-            //         // vec![12, 32, 143, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
-            //         let mut thing = MsgBuffer::from(packet)
-            //     },
-            //     _ => panic!("d"),
-            // };
-            // [-2, 12, 143, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]
-            resp.insert(0, resp.len() as u8);
-            println!("{:?}", &resp);
-            response.append(&mut resp);
+        if let Some(compression) = outgoing.start_compression {
+            self.batch.enable_compression(compression, false);
+        }
+        if let Some(encryption) = outgoing.start_encryption {
+            self.encryption = Some(encryption);
+        }
+        if outgoing.close {
+            self.close().await;
         }
+    }
 
-        self.send_default_frame(0xfe, MsgBuffer::from(response), PacketPriority::Medium).await;
+    /// Tells the client we're done and lets the game know the player's
+    /// gone. The listener drops the session once what's queued is sent.
+    async fn close(&mut self) {
+        self.send_default_frame(0x15, MsgBuffer::new(), PacketPriority::Medium)
+            .await;
+        let _ = self.game.send(Incoming::Disconnected(self.sockaddr));
+        self.closed = true;
     }
 
-    pub fn adjust_internal(&mut self, frame: &Frame) {
+    /// False if the frame's ordering makes no sense, it should be dropped
+    pub fn adjust_internal(&mut self, frame: &Frame) -> bool {
         // TODO: THIS ASSUMES THEY'RE SORTED
         // ARE YOU SURE THEY'RE SORTED?
         // I DON'T THINK YOU'RE SURE THEY'RE SORTED
@@ -404,11 +594,21 @@ impl Session {
         // TODO: sequenced stuff
 
         if frame.reliability.is_ordered() {
-            let ord_channel = frame.reliability.ord_channel.unwrap();
-            let ord_frameindex = frame.reliability.ord_frameindex.unwrap();
+            let (Some(ord_channel), Some(ord_frameindex)) = (
+                frame.reliability.ord_channel,
+                frame.reliability.ord_frameindex,
+            ) else {
+                return false;
+            };
+            if ord_channel >= MAX_ORD_CHANNELS {
+                return false;
+            }
 
-            if self.ord_channels.get(ord_channel as usize).unwrap() < &ord_frameindex {
-                self.ord_channels[ord_channel as usize] = ord_frameindex;
+            // the client picks the channel, we only keep the ones we send on
+            if let Some(index) = self.ord_channels.get_mut(ord_channel as usize) {
+                if *index < ord_frameindex {
+                    *index = ord_frameindex;
+                }
             }
 
             // if self.ord_channels.contains_key(&ord_channel) {
@@ -419,5 +619,7 @@ impl Session {
             //     self.ord_channels.insert(ord_channel, ord_frameindex);
             // }
         }
+
+        true
     }
 }
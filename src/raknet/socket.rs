@@ -4,7 +4,9 @@ use std::io::Error;
 use std::net::SocketAddr;
 use tokio::net::UdpSocket;
 
-use log::trace;
+use log::{log_enabled, trace, Level};
+
+use crate::dissector::{dissect, LOG_TARGET};
 
 pub struct Socket {
     pub udpsock: UdpSocket,
@@ -12,8 +14,21 @@ pub struct Socket {
 
 impl Socket {
     pub async fn bind(addr: String) -> Self {
-        let udpsock = UdpSocket::bind(addr).await.unwrap();
-        Self { udpsock }
+        Self::try_bind(&addr)
+            .await
+            .unwrap_or_else(|e| panic!("Couldn't bind to {}: {}", addr, e))
+    }
+
+    pub async fn try_bind(addr: &str) -> Result<Self, Error> {
+        let udpsock = UdpSocket::bind(addr).await?;
+        Ok(Self { udpsock })
+    }
+
+    /// So we can hear pings sent to the broadcast address
+    pub fn enable_broadcast(&self) {
+        self.udpsock
+            .set_broadcast(true)
+            .expect("Couldn't enable broadcast");
     }
 
     pub async fn send_packet(&self, packet_id: u8, packet: &mut MsgBuffer, client: SocketAddr) {
@@ -24,11 +39,14 @@ impl Socket {
 
         self.send_to(&bytes, client).await;
 
+        if !log_enabled!(target: LOG_TARGET, Level::Trace) {
+            return;
+        }
         match packet_id {
             0x1c => {}
             0xa0 => {}
             0xc0 => {}
-            _ => trace!("0x{packet_id} SENT = {:?}", &bytes),
+            _ => trace!(target: LOG_TARGET, "SENT {}", dissect(&bytes, false)),
         };
     }
 
@@ -0,0 +1,320 @@
+/// resource_packs.rs
+/// =================
+///
+/// Packs sent to players when they join, loaded from .mcpack/.zip files
+/// in the resource_packs directory. The client is told what we have
+/// (ResourcePacksInfo), asks for whatever it's missing and downloads it in
+/// chunks, then we agree on the order to apply them in (ResourcePackStack).
+///
+/// Reference: https://wiki.bedrock.dev/guide/project-setup.html (manifest.json)
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use log::{info, warn};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+pub const PACK_DIR: &str = "resource_packs";
+
+/// How much of a pack goes in each ResourcePackChunkData
+pub const CHUNK_SIZE: usize = 128 * 1024;
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    NoManifest,
+    BadManifest(String),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::Io(e) => write!(f, "couldn't read pack: {e}"),
+            PackError::Zip(e) => write!(f, "not a valid zip: {e}"),
+            PackError::NoManifest => write!(f, "no manifest.json"),
+            PackError::BadManifest(why) => write!(f, "bad manifest.json: {why}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for PackError {
+    fn from(e: std::io::Error) -> Self {
+        PackError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for PackError {
+    fn from(e: zip::result::ZipError) -> Self {
+        PackError::Zip(e)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PackKind {
+    Resources,
+    Behaviour,
+}
+
+pub struct ResourcePack {
+    pub uuid: String,
+    /// "1.0.0", from the [1, 0, 0] in the manifest
+    pub version: String,
+    pub name: String,
+    pub kind: PackKind,
+    pub has_scripts: bool,
+    /// sha256 of the whole file, the client checks it after downloading
+    pub hash: [u8; 32],
+    data: Vec<u8>,
+}
+
+impl ResourcePack {
+    pub fn load(path: &Path) -> Result<Self, PackError> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    /// The whole .mcpack
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, PackError> {
+        let mut archive = ZipArchive::new(Cursor::new(&data))?;
+
+        // packs are often zipped with their folder, so the manifest isn't
+        // always at the top
+        let manifest_name = archive
+            .file_names()
+            .filter(|name| name.rsplit('/').next() == Some("manifest.json"))
+            .min_by_key(|name| name.matches('/').count())
+            .ok_or(PackError::NoManifest)?
+            .to_string();
+        let mut manifest = String::new();
+        archive
+            .by_name(&manifest_name)?
+            .read_to_string(&mut manifest)?;
+
+        let manifest: Value =
+            serde_json::from_str(&manifest).map_err(|e| PackError::BadManifest(e.to_string()))?;
+        let header = &manifest["header"];
+
+        let uuid = header["uuid"]
+            .as_str()
+            .ok_or_else(|| PackError::BadManifest("no header.uuid".to_string()))?
+            .to_string();
+        let version = header["version"]
+            .as_array()
+            .filter(|parts| parts.len() == 3 && parts.iter().all(Value::is_u64))
+            .ok_or_else(|| PackError::BadManifest("header.version isn't [x, y, z]".to_string()))?
+            .iter()
+            .map(|part| part.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        let name = header["name"].as_str().unwrap_or(&uuid).to_string();
+
+        let modules: Vec<&str> = manifest["modules"]
+            .as_array()
+            .map(|modules| modules.iter().filter_map(|m| m["type"].as_str()).collect())
+            .unwrap_or_default();
+        let kind = if modules.iter().any(|m| *m == "data" || *m == "script") {
+            PackKind::Behaviour
+        } else {
+            PackKind::Resources
+        };
+        let has_scripts = modules
+            .iter()
+            .any(|m| matches!(*m, "script" | "client_data" | "javascript"));
+
+        Ok(Self {
+            uuid,
+            version,
+            name,
+            kind,
+            has_scripts,
+            hash: Sha256::digest(&data).into(),
+            data,
+        })
+    }
+
+    /// How the client refers to a pack, `<uuid>_<version>`
+    pub fn id(&self) -> String {
+        format!("{}_{}", self.uuid, self.version)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn chunk_count(&self) -> u32 {
+        self.data.len().div_ceil(CHUNK_SIZE) as u32
+    }
+
+    pub fn chunk(&self, index: u32) -> Option<&[u8]> {
+        self.data.chunks(CHUNK_SIZE).nth(index as usize)
+    }
+}
+
+pub struct ResourcePacks {
+    pub packs: Vec<ResourcePack>,
+    /// texturepack-required, players can't join without them
+    pub required: bool,
+}
+
+impl ResourcePacks {
+    /// Loads every pack in `dir`. Broken packs are skipped and a missing
+    /// directory just means no packs.
+    pub fn load(dir: &Path, required: bool) -> Self {
+        let mut packs: Vec<ResourcePack> = vec![];
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Self { packs, required },
+        };
+
+        let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        // the order packs are applied in, so keep it predictable
+        paths.sort();
+
+        for path in paths {
+            let extension = path.extension().and_then(|e| e.to_str());
+            if !matches!(extension, Some("mcpack" | "zip")) {
+                continue;
+            }
+
+            match ResourcePack::load(&path) {
+                Ok(pack) if packs.iter().any(|p| p.uuid == pack.uuid) => {
+                    warn!(
+                        "Skipping {}, {} is already loaded",
+                        path.display(),
+                        pack.uuid
+                    )
+                }
+                Ok(pack) => {
+                    info!("Loaded resource pack {} ({})", pack.name, pack.id());
+                    packs.push(pack);
+                }
+                Err(e) => warn!("Skipping resource pack {}: {}", path.display(), e),
+            }
+        }
+
+        Self { packs, required }
+    }
+
+    /// Takes either just the uuid or `<uuid>_<version>`
+    pub fn get(&self, id: &str) -> Option<&ResourcePack> {
+        let (uuid, version) = match id.split_once('_') {
+            Some((uuid, version)) => (uuid, Some(version)),
+            None => (id, None),
+        };
+
+        self.packs
+            .iter()
+            .find(|p| p.uuid == uuid && version.is_none_or(|v| p.version == v))
+    }
+
+    pub fn of_kind(&self, kind: PackKind) -> impl Iterator<Item = &ResourcePack> {
+        self.packs.iter().filter(move |p| p.kind == kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    const MANIFEST: &str = r#"{
+        "format_version": 2,
+        "header": {"name": "Test", "uuid": "1234-abcd", "version": [1, 2, 3]},
+        "modules": [{"type": "data"}, {"type": "script"}]
+    }"#;
+
+    #[test]
+    fn manifest() {
+        // the shallowest manifest wins
+        let pack = ResourcePack::from_bytes(zip(&[
+            ("pack/sub/manifest.json", "{}"),
+            ("pack/manifest.json", MANIFEST),
+        ]))
+        .unwrap();
+        assert_eq!(pack.uuid, "1234-abcd");
+        assert_eq!(pack.version, "1.2.3");
+        assert_eq!(pack.id(), "1234-abcd_1.2.3");
+        assert_eq!(pack.name, "Test");
+        assert_eq!(pack.kind, PackKind::Behaviour);
+        assert!(pack.has_scripts);
+
+        let pack = ResourcePack::from_bytes(zip(&[(
+            "manifest.json",
+            r#"{"header": {"uuid": "u", "version": [0, 0, 1]}, "modules": [{"type": "resources"}]}"#,
+        )]))
+        .unwrap();
+        assert_eq!(pack.name, "u");
+        assert_eq!(pack.kind, PackKind::Resources);
+        assert!(!pack.has_scripts);
+    }
+
+    #[test]
+    fn bad_manifest() {
+        let load = |files: &[(&str, &str)]| ResourcePack::from_bytes(zip(files)).err();
+
+        assert!(matches!(
+            load(&[("pack.txt", "")]),
+            Some(PackError::NoManifest)
+        ));
+        assert!(matches!(
+            load(&[("manifest.json", "{")]),
+            Some(PackError::BadManifest(_))
+        ));
+        assert!(matches!(
+            load(&[("manifest.json", r#"{"header": {"version": [1, 0, 0]}}"#)]),
+            Some(PackError::BadManifest(_))
+        ));
+        assert!(matches!(
+            load(&[(
+                "manifest.json",
+                r#"{"header": {"uuid": "u", "version": "1.0.0"}}"#
+            )]),
+            Some(PackError::BadManifest(_))
+        ));
+        assert!(matches!(
+            ResourcePack::from_bytes(b"not a zip".to_vec()),
+            Err(PackError::Zip(_))
+        ));
+    }
+
+    #[test]
+    fn chunks() {
+        let mut pack = ResourcePack::from_bytes(zip(&[("manifest.json", MANIFEST)])).unwrap();
+        pack.data = vec![7; CHUNK_SIZE + 10];
+
+        assert_eq!(pack.chunk_count(), 2);
+        assert_eq!(pack.chunk(0).map(<[u8]>::len), Some(CHUNK_SIZE));
+        assert_eq!(pack.chunk(1).map(<[u8]>::len), Some(10));
+        assert_eq!(pack.chunk(2), None);
+        assert_eq!(pack.chunk(u32::MAX), None);
+    }
+
+    #[test]
+    fn get() {
+        let packs = ResourcePacks {
+            packs: vec![ResourcePack::from_bytes(zip(&[("manifest.json", MANIFEST)])).unwrap()],
+            required: false,
+        };
+
+        assert!(packs.get("1234-abcd").is_some());
+        assert!(packs.get("1234-abcd_1.2.3").is_some());
+        assert!(packs.get("1234-abcd_1.0.0").is_none());
+        assert!(packs.get("nope").is_none());
+    }
+}
@@ -1,40 +1,339 @@
-use super::config::Config;
+/// server.rs
+/// =========
+///
+/// The game loop, 20 ticks a second like vanilla. RakNet runs on its own
+/// thread (raknet/server.rs) and hands over game packets as they come in,
+/// every tick:
+///
+///     1. every packet that's come in since last tick is handled
+///     2. the world and everything in it moves on a tick, the chunks near
+///        players anyway
+///     3. whatever the connections want to send goes back to RakNet,
+///        blocks that changed included
+///
+/// If a tick takes too long the next ones run straight after it to catch
+/// up, unless it's fallen so far behind it's better to skip them. The loop
+/// has a thread to itself, and autosaves get another so ticks don't wait
+/// on the disk.
+///
+/// Ctrl-C (or SIGTERM) stops both loops, and the world's saved on the way
+/// out.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use super::config::{Config, ConfigError};
+use super::game::biome::{self, BIOME_DEFINITIONS_FILE};
+use super::game::block::{BlockPalette, BLOCK_STATES_FILE};
+use super::game::connection::{ConnectionContext, Incoming, Outgoing, PlayerConnection};
+use super::game::generator::{self, GeneratorPool};
+use super::game::item::{ItemPalette, ITEMS_FILE};
+use super::game::level_db::{LevelDb, WORLDS_DIR};
+use super::game::settings::LevelSettings;
+use super::game::world::World;
+use super::protocol::batch::CompressionSettings;
+use super::protocol::login::LoginVerifier;
 use super::raknet::server::RakNetListener;
-use std::thread;
+use super::resource_packs::{ResourcePacks, PACK_DIR};
+use super::status::{ServerStatus, SharedStatus};
+
+const TICK_LENGTH: Duration = Duration::from_millis(50);
+/// How far behind (in ticks) the loop catches up from, any further and
+/// those ticks are skipped
+const MAX_CATCH_UP: u32 = 10;
+/// How often the world's saved
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 pub struct VoxelServer {
-    // listener: RakNetListener,
-    version: String,
-    protocol_version: String,
+    status: SharedStatus,
+    context: ConnectionContext,
+    connections: HashMap<SocketAddr, PlayerConnection>,
+    last_autosave: Instant,
+    /// The autosave that's being written, if there is one
+    saving: Option<JoinHandle<()>>,
 }
 
 impl VoxelServer {
-    pub async fn init() -> Self {
-        // let listener = RakNetListener::new(
-        //     config
-        // ).await;
-        
-        Self {
-            version: "1.20.41".to_string(),
-            protocol_version: "622".to_string(),
+    pub const VERSION: &'static str = "1.20.41";
+    pub const PROTOCOL_VERSION: u32 = 622;
+
+    pub async fn init(config: &Config) -> Result<Self, ConfigError> {
+        let status =
+            ServerStatus::from_config(config, Self::VERSION, Self::PROTOCOL_VERSION)?.shared();
+
+        Ok(Self {
+            context: Self::context(config, status.clone())?,
+            status,
+            connections: HashMap::new(),
+            last_autosave: Instant::now(),
+            saving: None,
+        })
+    }
+
+    /// Everything connections share, the world included
+    fn context(config: &Config, status: SharedStatus) -> Result<ConnectionContext, ConfigError> {
+        let blocks = Arc::new(BlockPalette::load(
+            Path::new(BLOCK_STATES_FILE),
+            config.get_bool("block-network-ids-are-hashes"),
+        ));
+
+        let mut level = LevelSettings::from_config(config)?;
+        let level_dir = Path::new(WORLDS_DIR).join(&level.level_name);
+        let level_db = LevelDb::open(&level_dir, &level)
+            .unwrap_or_else(|e| panic!("Couldn't open {}: {}", level_dir.display(), e));
+        if let Some(seed) = level_db.seed() {
+            level.seed = seed;
+        }
+        if let Some(level_type) = level_db.level_type() {
+            level.level_type = level_type;
         }
+        level.level_type.check_blocks(&blocks)?;
+        info!("Opened {}", level_dir.display());
+
+        // max-threads is for the whole server, RakNet and the game loop get
+        // one each
+        let threads = match config
+            .get_optional_parsed("max-threads", "a number", |t| t.parse::<usize>().ok())?
+        {
+            Some(threads) if threads > 0 => threads,
+            _ => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        let generator = GeneratorPool::new(
+            generator::from_settings(&level.level_type, level.seed, &blocks),
+            threads.saturating_sub(2),
+        );
+
+        Ok(ConnectionContext {
+            compression: CompressionSettings::from_config(config)?,
+            login_verifier: Arc::new(LoginVerifier::new(config.get_bool("online-mode"))),
+            resource_packs: Arc::new(ResourcePacks::load(
+                Path::new(PACK_DIR),
+                config.get_bool("texturepack-required"),
+            )),
+            level: Arc::new(level),
+            blocks: blocks.clone(),
+            items: Arc::new(ItemPalette::load(Path::new(ITEMS_FILE))),
+            biomes: Arc::new(biome::load_definitions(Path::new(BIOME_DEFINITIONS_FILE))),
+            world: Arc::new(RwLock::new(World::new(blocks, level_db, generator))),
+            status,
+        })
     }
 
-    // pub async fn close(&mut self) {
-    //     self.listener.close().await.unwrap();
-    // }
+    pub async fn run(mut self, config: Config) {
+        let (game, incoming) = mpsc::channel();
+        let (outgoing, from_game) = mpsc::channel();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        tokio::spawn(wait_for_shutdown(shutdown.clone()));
 
-    pub async fn run(&mut self, config: Config) {
-        let _raknet_thread = thread::spawn(|| {
+        let status = self.status.clone();
+        let raknet_shutdown = shutdown.clone();
+        let raknet_thread = thread::spawn(move || {
             tokio::runtime::Runtime::new().unwrap().block_on(async {
-                let mut listener = RakNetListener::new(config).await;
-                listener.mainloop().await;
+                let mut listener = RakNetListener::new(config, status, game, from_game).await;
+                listener.mainloop(&raknet_shutdown).await;
             })
         });
 
-        loop {}
+        // the loop sleeps between ticks, which would hold up the runtime
+        let game_thread = tokio::task::spawn_blocking(move || {
+            self.game_loop(incoming, outgoing, &shutdown);
+
+            if let Some(saving) = self.saving.take() {
+                let _ = saving.join();
+            }
+            World::save(&self.context.world);
+            let _ = raknet_thread.join();
+        });
+        game_thread.await.unwrap();
+    }
+
+    /// Until we're told to stop, or RakNet goes away
+    fn game_loop(
+        &mut self,
+        incoming: Receiver<Incoming>,
+        outgoing: Sender<(SocketAddr, Outgoing)>,
+        shutdown: &AtomicBool,
+    ) {
+        let mut next_tick = Instant::now();
+
+        while !shutdown.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            if !self.tick(&incoming, &outgoing) {
+                return;
+            }
+            let took = started.elapsed();
+
+            let now = Instant::now();
+            let skipped;
+            (next_tick, skipped) = after_tick(next_tick, now);
+            if skipped > 0 {
+                warn!("Can't keep up! The last tick took {took:?}, skipping {skipped} ticks");
+            }
+            if now < next_tick {
+                thread::sleep(next_tick - now);
+            }
+        }
+    }
+
+    /// False once RakNet's gone
+    fn tick(
+        &mut self,
+        incoming: &Receiver<Incoming>,
+        outgoing: &Sender<(SocketAddr, Outgoing)>,
+    ) -> bool {
+        loop {
+            match incoming.try_recv() {
+                Ok(message) => self.handle(message, outgoing),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+
+        let players: Vec<_> = self
+            .connections
+            .values()
+            .filter_map(PlayerConnection::ticking_center)
+            .collect();
+        let block_updates = {
+            let mut world = self.context.world.write().unwrap();
+            world.tick(&players, self.context.level.tick_distance);
+            world.take_block_updates()
+        };
+
+        for (addr, connection) in &mut self.connections {
+            connection.send_block_updates(&block_updates);
+            let packets = connection.tick();
+            if !packets.is_empty() {
+                let _ = outgoing.send((*addr, packets));
+            }
+        }
+
+        self.autosave();
+
+        true
+    }
+
+    /// Starts a save every AUTOSAVE_INTERVAL, and once it's written unloads
+    /// the chunks nobody can see
+    fn autosave(&mut self) {
+        if self.saving.as_ref().is_some_and(JoinHandle::is_finished) {
+            let _ = self.saving.take().unwrap().join();
+            self.context
+                .world
+                .write()
+                .unwrap()
+                .unload(|dimension, chunk| {
+                    self.connections
+                        .values()
+                        .any(|c| c.can_see(dimension, chunk))
+                });
+        }
+
+        if self.saving.is_none() && self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+            let world = self.context.world.clone();
+            self.saving = Some(thread::spawn(move || World::save(&world)));
+            self.last_autosave = Instant::now();
+        }
+    }
+
+    fn handle(&mut self, message: Incoming, outgoing: &Sender<(SocketAddr, Outgoing)>) {
+        match message {
+            Incoming::Connected(addr) => {
+                let connection = PlayerConnection::new(addr, self.context.clone());
+                // the same client again, without saying goodbye first
+                if let Some(mut old) = self.connections.insert(addr, connection) {
+                    old.disconnected();
+                }
+            }
+            Incoming::Packets(addr, packets) => {
+                let Some(connection) = self.connections.get_mut(&addr) else {
+                    return;
+                };
+                let packets = connection.handle(packets);
+                if !packets.is_empty() {
+                    let _ = outgoing.send((addr, packets));
+                }
+            }
+            Incoming::Disconnected(addr) => {
+                if let Some(mut connection) = self.connections.remove(&addr) {
+                    connection.disconnected();
+                }
+            }
+        }
+    }
+}
+
+/// When the tick after the one that was due at `due` should run, given
+/// it's finished at `now`, and how many ticks are skipped to get there. It's
+/// straight away to catch up, unless that's more than MAX_CATCH_UP ticks.
+fn after_tick(due: Instant, now: Instant) -> (Instant, u32) {
+    let next = due + TICK_LENGTH;
+    let behind = now.saturating_duration_since(next);
+    if behind > TICK_LENGTH * MAX_CATCH_UP {
+        let skipped = behind.as_millis() / TICK_LENGTH.as_millis();
+        (now, skipped as u32)
+    } else {
+        (next, 0)
+    }
+}
+
+/// Sets `shutdown` on ctrl-c, or SIGTERM where there's such a thing
+async fn wait_for_shutdown(shutdown: Arc<AtomicBool>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    info!("Stopping the server");
+    shutdown.store(true, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_pacing() {
+        let start = Instant::now();
+
+        // on time, so wait for the next one
+        let (next, skipped) = after_tick(start, start + TICK_LENGTH / 2);
+        assert_eq!((next, skipped), (start + TICK_LENGTH, 0));
+
+        // a few behind, the ones that are due run straight after each
+        // other until it's caught up
+        let now = start + TICK_LENGTH * 4;
+        let mut due = start;
+        let mut ticks = 0;
+        while due <= now {
+            (due, _) = after_tick(due, now);
+            ticks += 1;
+        }
+        assert_eq!(ticks, 5);
+        assert_eq!(due, start + TICK_LENGTH * 5);
+
+        // right at the limit, still caught up
+        let now = start + TICK_LENGTH * (MAX_CATCH_UP + 1);
+        assert_eq!(after_tick(start, now), (start + TICK_LENGTH, 0));
 
-        // let _ = raknet_thread.join();
-        // self.close().await;
+        // too far behind, the rest are skipped
+        let now = start + TICK_LENGTH * 100;
+        assert_eq!(after_tick(start, now), (now, 99));
     }
 }
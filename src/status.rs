@@ -0,0 +1,125 @@
+/// status.rs
+/// =========
+///
+/// What the server looks like from the outside (the server list MOTD).
+/// The game side keeps this up to date, RakNet just reads it whenever
+/// someone pings us.
+///
+/// Reference: https://wiki.vg/Raknet_Protocol#Unconnected_Pong
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use crate::config::{Config, ConfigError};
+use crate::raknet::enums::Gamemode;
+
+pub type SharedStatus = Arc<RwLock<ServerStatus>>;
+
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub server_name: String,
+    pub level_name: String,
+    pub protocol_version: u32,
+    pub version: String,
+    pub online_players: usize,
+    /// Address and display name, in the order they joined. Names aren't
+    /// unique (offline mode), addresses are.
+    pub players: Vec<(SocketAddr, String)>,
+    pub max_players: usize,
+    pub gamemode: Gamemode,
+    pub port_v4: u16,
+    pub port_v6: u16,
+}
+
+impl ServerStatus {
+    pub fn from_config(
+        config: &Config,
+        version: &str,
+        protocol_version: u32,
+    ) -> Result<Self, ConfigError> {
+        Ok(Self {
+            server_name: config.get_property("server-name").clone(),
+            level_name: config.get_property("level-name").clone(),
+            protocol_version,
+            version: version.to_string(),
+            online_players: 0,
+            players: vec![],
+            max_players: config.get_parsed("max-players", "a number", |m| m.parse().ok())?,
+            gamemode: config.get_parsed("gamemode", Gamemode::EXPECTED, Gamemode::from_name)?,
+            port_v4: config.get_parsed("server-port", "a port number", |p| p.parse().ok())?,
+            port_v6: config.get_parsed("server-portv6", "a port number", |p| p.parse().ok())?,
+        })
+    }
+
+    pub fn shared(self) -> SharedStatus {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Builds the string sent in unconnected pongs, e.g.
+    /// `MCPE;Voxelware;622;1.20.41;0;20;1234;Level;Creative;1;19132;19133;`
+    pub fn to_motd(&self, server_guid: i64) -> String {
+        // so picky I don't get it smh
+        [
+            "MCPE".to_string(),
+            escape(&self.server_name),
+            self.protocol_version.to_string(),
+            self.version.clone(),
+            self.online_players.to_string(),
+            self.max_players.to_string(),
+            server_guid.to_string(),
+            escape(&self.level_name),
+            self.gamemode.name().to_string(),
+            self.gamemode.id().to_string(),
+            self.port_v4.to_string(),
+            self.port_v6.to_string(),
+            String::new(), // the client expects a trailing semicolon
+        ]
+        .join(";")
+    }
+}
+
+/// The MOTD is semicolon separated and the client doesn't understand any
+/// kind of escaping, so swap semicolons out for the greek question mark
+/// (which looks exactly the same)
+fn escape(value: &str) -> String {
+    value.replace(';', "\u{037e}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> ServerStatus {
+        ServerStatus {
+            server_name: "Voxelware".to_string(),
+            level_name: "Level".to_string(),
+            protocol_version: 622,
+            version: "1.20.40".to_string(),
+            online_players: 3,
+            players: vec![],
+            max_players: 20,
+            gamemode: Gamemode::Spectator,
+            port_v4: 19132,
+            port_v6: 19133,
+        }
+    }
+
+    #[test]
+    fn motd() {
+        assert_eq!(
+            status().to_motd(1234),
+            "MCPE;Voxelware;622;1.20.40;3;20;1234;Level;Spectator;6;19132;19133;"
+        );
+    }
+
+    #[test]
+    fn motd_escape() {
+        let mut status = status();
+        status.server_name = "a;b".to_string();
+        status.level_name = ";".to_string();
+
+        let motd = status.to_motd(-1);
+        assert_eq!(motd.split(';').count(), 13);
+        assert!(motd.starts_with("MCPE;a\u{037e}b;622;"));
+        assert!(motd.contains(";-1;\u{037e};"));
+    }
+}